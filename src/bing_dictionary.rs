@@ -1,49 +1,227 @@
-use std::env;
-
 use bytes::Bytes;
-use edge_gpt::{ChatSession, ConversationStyle, CookieInFile};
 use serde::{Deserialize, Serialize};
 use teloxide::{
     payloads::SendMessage,
     types::{MessageEntity, Recipient},
 };
 
-use crate::{azure_tts::AzureTTS, duolingo::Vocabulary};
-use isolang::Language;
+use crate::{
+    error::Result,
+    llm::{ask_json, ChatBackend, Creativity},
+    prompt::{PromptContext, Prompts, Template},
+    tts::SpeechSynthesizer,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct Example {
+    #[serde(alias = "example_sentence")]
+    pub sentence: String,
+    #[serde(alias = "example_sentence_translation")]
+    pub translation: String,
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Inflection {
+    pub form: String,
+    pub word: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct Word {
+    pub spell: String,
+    pub pronunciation: String,
+    #[serde(default)]
+    pub part_of_speech: String,
+    /// The grammatical gender as its indefinite article, such as `en` or `ett`.
+    #[serde(default)]
+    pub article: Option<String>,
+    pub meaning: String,
+    #[serde(default)]
+    pub inflections: Vec<Inflection>,
+    pub example_sentence: String,
+    pub example_sentence_translation: String,
+    #[serde(default)]
+    pub more_examples: Vec<Example>,
+}
+
+/// The dictionary entry as the `dictionary` prompt asks for it.
+#[derive(Deserialize)]
+struct Lookup {
     spell: String,
     pronunciation: String,
+    #[serde(default)]
+    part_of_speech: String,
+    #[serde(default)]
+    article: Option<String>,
     meaning: String,
-    example_sentence: String,
-    example_sentence_translation: String,
+    #[serde(default)]
+    inflections: Vec<Inflection>,
+    examples: Vec<Example>,
+}
+
+impl Lookup {
+    fn validate(&self) -> std::result::Result<(), String> {
+        for (name, value) in [
+            ("spell", &self.spell),
+            ("pronunciation", &self.pronunciation),
+            ("meaning", &self.meaning),
+        ] {
+            if value.trim().is_empty() {
+                return Err(format!("`{name}` is empty"));
+            }
+        }
+        if self.examples.is_empty() {
+            return Err("`examples` is empty".to_string());
+        }
+        self.examples.iter().try_for_each(Example::validate)
+    }
+}
+
+impl Example {
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.sentence.trim().is_empty() || self.translation.trim().is_empty() {
+            return Err("an example has an empty `sentence` or `translation`".to_string());
+        }
+        Ok(())
+    }
 }
 
 impl Word {
-    pub async fn from_vocabulary(
-        vocabulary: &Vocabulary,
-        ui_language: &str,
-        language: &str,
-    ) -> Self {
-        let spell = &vocabulary.word_string;
-        let language_full_name = Language::from_639_1(language).unwrap().to_name();
-        let ui_language_full_name = Language::from_639_1(ui_language).unwrap().to_name();
-        let promote = format!("look up {language_full_name} word \"{spell}\" in dictionary, output the result in this format: {{\"spell\": \"<word>\", \"pronunciation\": \"<IPA of the word>\", \"meaning\": \"<{ui_language_full_name} meaning>\", \"example_sentence\": \"<Example sentence>\", \"example_sentence_translation\": \"<Example sentence's {ui_language_full_name} meaning>\"}}");
-        let mut chat = new_chat().await;
-        let result = chat.send_message(&promote).await.unwrap();
-        let start_pos = result.text.find(|c| c == '{').unwrap();
-        let end_pos = result.text.find(|c| c == '}').unwrap();
-        let json_str = &result.text[start_pos..=end_pos];
-        serde_json::from_str(json_str).unwrap()
+    /// Asks the language model to look up `spell`.
+    pub async fn look_up(
+        spell: &str,
+        prompts: &Prompts,
+        context: &PromptContext,
+        chat_backend: &dyn ChatBackend,
+    ) -> Result<Self> {
+        let promote = prompts.render(Template::Dictionary, context, &[("word", spell)]);
+        let mut chat = chat_backend.create_session(Creativity::Balanced).await?;
+        let lookup: Lookup = ask_json(chat.as_mut(), &promote, Lookup::validate).await?;
+        let mut examples = lookup.examples.into_iter();
+        let example = examples.next().expect("validated to be non-empty");
+        Ok(Self {
+            spell: lookup.spell,
+            pronunciation: lookup.pronunciation,
+            part_of_speech: lookup.part_of_speech,
+            article: lookup.article.filter(|it| !it.trim().is_empty()),
+            meaning: lookup.meaning,
+            inflections: lookup.inflections,
+            example_sentence: example.sentence,
+            example_sentence_translation: example.translation,
+            more_examples: examples.collect(),
+        })
     }
 
-    pub async fn to_telegram_message(
-        &self,
-        tts: &AzureTTS,
-        language: &str,
-        chat_id: impl Into<Recipient> + Clone,
-    ) -> (SendMessage, Bytes, Bytes) {
+    /// Folds another dictionary entry of the same word into this one.
+    pub fn merge(&mut self, other: Word) {
+        for (field, addition, separator) in [
+            (&mut self.meaning, other.meaning, "; "),
+            (&mut self.part_of_speech, other.part_of_speech, ", "),
+        ] {
+            if field.is_empty() {
+                *field = addition;
+            } else if !addition.is_empty() && !field.contains(&addition) {
+                *field = format!("{field}{separator}{addition}");
+            }
+        }
+        if self.pronunciation.is_empty() {
+            self.pronunciation = other.pronunciation;
+        }
+        if self.article.is_none() {
+            self.article = other.article;
+        }
+        if self.inflections.is_empty() {
+            self.inflections = other.inflections;
+        }
+        self.more_examples.extend(other.more_examples);
+    }
+
+    /// Makes the first of `more_examples` the main example when there is none.
+    pub fn promote_first_example(&mut self) {
+        if self.example_sentence.is_empty() && !self.more_examples.is_empty() {
+            let example = self.more_examples.remove(0);
+            self.example_sentence = example.sentence;
+            self.example_sentence_translation = example.translation;
+        }
+    }
+
+    /// Asks for a different example sentence, replacing the current one.
+    pub async fn another_example(
+        &mut self,
+        prompts: &Prompts,
+        context: &PromptContext,
+        chat_backend: &dyn ChatBackend,
+    ) -> Result<()> {
+        let promote = prompts.render(
+            Template::AnotherExample,
+            context,
+            &[
+                ("word", &self.spell),
+                ("current_example", &self.example_sentence),
+            ],
+        );
+        let mut chat = chat_backend.create_session(Creativity::Creative).await?;
+        let example: Example = ask_json(chat.as_mut(), &promote, Example::validate).await?;
+        self.example_sentence = example.sentence;
+        self.example_sentence_translation = example.translation;
+        Ok(())
+    }
+
+    /// The inflected forms, one per line with the names lined up.
+    pub fn forms_table(&self) -> String {
+        let width = self
+            .inflections
+            .iter()
+            .map(|it| it.form.chars().count())
+            .max()
+            .unwrap_or_default();
+        self.inflections
+            .iter()
+            .map(|it| match it.form.as_str() {
+                "" => it.word.clone(),
+                form => format!("{form:width$}  {}", it.word),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The `/forms` reply: the word and its inflection table.
+    pub fn to_forms_text(&self) -> (String, Vec<MessageEntity>) {
+        let mut text = self.headline();
+        let mut entities = vec![MessageEntity::bold(0, self.spell.encode_utf16().count())];
+        if self.inflections.is_empty() {
+            text.push_str("\nNo inflected forms known.");
+        } else {
+            text.push('\n');
+            let table = self.forms_table();
+            let offset = text.encode_utf16().count();
+            entities.push(MessageEntity::pre(
+                None,
+                offset,
+                table.encode_utf16().count(),
+            ));
+            text.push_str(&table);
+        }
+        (text, entities)
+    }
+
+    /// The word followed by its article and part of speech.
+    fn headline(&self) -> String {
+        let grammar = [self.article.as_deref(), Some(self.part_of_speech.as_str())]
+            .into_iter()
+            .flatten()
+            .filter(|it| !it.is_empty())
+            .collect::<Vec<_>>();
+        if grammar.is_empty() {
+            self.spell.clone()
+        } else {
+            format!("{} ({})", self.spell, grammar.join(", "))
+        }
+    }
+
+    /// The card text, with the meaning and translation hidden behind spoilers,
+    /// and the inflection table when `with_forms` is set.
+    pub fn to_telegram_text(&self, with_forms: bool) -> (String, Vec<MessageEntity>) {
         let mut text = String::new();
         let mut entities: Vec<MessageEntity> = Vec::new();
         let mut offset = 0;
@@ -52,8 +230,8 @@ impl Word {
             *offset += s.encode_utf16().count();
         };
 
-        add_text(&self.spell.to_string(), &mut offset);
-        entities.push(MessageEntity::bold(0, offset));
+        entities.push(MessageEntity::bold(0, self.spell.encode_utf16().count()));
+        add_text(&self.headline(), &mut offset);
 
         add_text(&format!("\n{}\n", self.pronunciation), &mut offset);
 
@@ -64,33 +242,60 @@ impl Word {
             offset - meaning_start_offset,
         ));
 
-        add_text(&format!("\n{}\n", self.example_sentence), &mut offset);
+        let first_example = (&self.example_sentence, &self.example_sentence_translation);
+        let more_examples = self
+            .more_examples
+            .iter()
+            .map(|it| (&it.sentence, &it.translation));
+        let examples = [first_example].into_iter().chain(more_examples);
+        for (sentence, translation) in examples.filter(|(it, _)| !it.is_empty()) {
+            add_text(&format!("\n\n{sentence}\n"), &mut offset);
 
-        let example_sentence_translation_start_offset = offset;
-        add_text(&self.example_sentence_translation.to_string(), &mut offset);
-        entities.push(MessageEntity::spoiler(
-            example_sentence_translation_start_offset,
-            offset - example_sentence_translation_start_offset,
-        ));
-        let mut text_message = SendMessage::new(chat_id.clone(), text);
+            let translation_start_offset = offset;
+            add_text(translation, &mut offset);
+            entities.push(MessageEntity::spoiler(
+                translation_start_offset,
+                offset - translation_start_offset,
+            ));
+        }
+
+        if with_forms && !self.inflections.is_empty() {
+            add_text("\n\n", &mut offset);
+            let table_start_offset = offset;
+            add_text(&self.forms_table(), &mut offset);
+            entities.push(MessageEntity::pre(
+                None,
+                table_start_offset,
+                offset - table_start_offset,
+            ));
+        }
+        (text, entities)
+    }
+
+    pub fn to_telegram_message(
+        &self,
+        chat_id: impl Into<Recipient>,
+        with_forms: bool,
+    ) -> SendMessage {
+        let (text, entities) = self.to_telegram_text(with_forms);
+        let mut text_message = SendMessage::new(chat_id, text);
         text_message.entities = Some(entities);
-        let voice = tts
-            .voices
-            .iter()
-            .find(|it| it.locale.contains(language))
-            .unwrap()
-            .clone();
-        let spell_voice = tts.tts_simple(&self.spell, &voice);
-        let sentence_voice = tts.tts_simple(&self.example_sentence, &voice);
-        let (spell_voice, sentence_voice) = tokio::join!(spell_voice, sentence_voice);
-        (text_message, spell_voice, sentence_voice)
+        text_message
     }
-}
 
-pub async fn new_chat() -> ChatSession {
-    let cookie_str = env::var("EDGE_GPT_COOKIE").unwrap();
-    let cookies: Vec<CookieInFile> = serde_json::from_str(&cookie_str).unwrap();
-    ChatSession::create(ConversationStyle::Balanced, &cookies)
-        .await
-        .unwrap()
+    /// Speaks the word and the example sentence, if there is one.
+    pub async fn voices(
+        &self,
+        tts: &dyn SpeechSynthesizer,
+        language: &str,
+    ) -> (Option<Bytes>, Option<Bytes>) {
+        let spell_voice = tts.synthesize(&self.spell, language);
+        let sentence_voice = async {
+            if self.example_sentence.is_empty() {
+                return None;
+            }
+            tts.synthesize(&self.example_sentence, language).await
+        };
+        tokio::join!(spell_voice, sentence_voice)
+    }
 }
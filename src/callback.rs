@@ -0,0 +1,115 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::{review::Grade, util::language_name};
+
+/// What a pressed inline button asks for.
+///
+/// Encoded as a short `:` separated string so it fits in the 64 bytes
+/// Telegram allows for `callback_data`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallbackData {
+    Review { vocabulary_id: String, grade: Grade },
+    AnotherExample,
+    ReplayAudio,
+    NextWord,
+    SelectLanguage(String),
+    QuizAnswer { question: u32, option: usize },
+    MistakeReview { id: String, grade: Grade },
+}
+
+impl CallbackData {
+    pub fn encode(&self) -> String {
+        match self {
+            CallbackData::Review {
+                vocabulary_id,
+                grade,
+            } => format!("review:{vocabulary_id}:{}", grade.as_str()),
+            CallbackData::AnotherExample => "example".to_string(),
+            CallbackData::ReplayAudio => "replay".to_string(),
+            CallbackData::NextWord => "next".to_string(),
+            CallbackData::SelectLanguage(language) => format!("language:{language}"),
+            CallbackData::QuizAnswer { question, option } => format!("quiz:{question}:{option}"),
+            CallbackData::MistakeReview { id, grade } => {
+                format!("mistake:{id}:{}", grade.as_str())
+            }
+        }
+    }
+
+    pub fn decode(data: &str) -> Option<Self> {
+        let mut parts = data.split(':');
+        let result = match parts.next()? {
+            "review" => CallbackData::Review {
+                vocabulary_id: parts.next()?.to_string(),
+                grade: parts.next()?.try_into().ok()?,
+            },
+            "example" => CallbackData::AnotherExample,
+            "replay" => CallbackData::ReplayAudio,
+            "next" => CallbackData::NextWord,
+            "language" => CallbackData::SelectLanguage(parts.next()?.to_string()),
+            "mistake" => CallbackData::MistakeReview {
+                id: parts.next()?.to_string(),
+                grade: parts.next()?.try_into().ok()?,
+            },
+            "quiz" => CallbackData::QuizAnswer {
+                question: parts.next()?.parse().ok()?,
+                option: parts.next()?.parse().ok()?,
+            },
+            _ => return None,
+        };
+        parts.next().is_none().then_some(result)
+    }
+
+    pub fn button(&self, text: &str) -> InlineKeyboardButton {
+        InlineKeyboardButton::callback(text, self.encode())
+    }
+}
+
+/// The buttons shown under every word card.
+pub fn word_card_row() -> Vec<InlineKeyboardButton> {
+    vec![
+        CallbackData::AnotherExample.button("Another example"),
+        CallbackData::ReplayAudio.button("Replay audio"),
+        CallbackData::NextWord.button("Next word"),
+    ]
+}
+
+pub fn word_card_keyboard() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([word_card_row()])
+}
+
+pub fn review_keyboard(vocabulary_id: &str) -> InlineKeyboardMarkup {
+    let grades = Grade::ALL.map(|grade| {
+        CallbackData::Review {
+            vocabulary_id: vocabulary_id.to_string(),
+            grade,
+        }
+        .button(grade.label())
+    });
+    InlineKeyboardMarkup::new([grades.to_vec(), word_card_row()])
+}
+
+pub fn mistake_keyboard(id: &str) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([Grade::ALL.map(|grade| {
+        CallbackData::MistakeReview {
+            id: id.to_string(),
+            grade,
+        }
+        .button(grade.label())
+    })])
+}
+
+pub fn language_keyboard(languages: &[String]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(languages.iter().map(|language| {
+        vec![CallbackData::SelectLanguage(language.clone()).button(&language_name(language))]
+    }))
+}
+
+/// One button per option, the callback data only carries the indices.
+pub fn quiz_keyboard(question: u32, options: &[String]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(
+        options
+            .iter()
+            .enumerate()
+            .map(|(option, text)| vec![CallbackData::QuizAnswer { question, option }.button(text)]),
+    )
+}
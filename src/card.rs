@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, MessageId};
+
+use crate::{
+    bing_dictionary::Word,
+    error::Result,
+    store::{schema, StateStore},
+};
+
+/// A word card sent to a chat, kept so its buttons can act on it later.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct WordCard {
+    pub vocabulary_id: String,
+    pub language: String,
+    pub word: Word,
+}
+
+pub async fn load_card(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    message_id: MessageId,
+) -> Result<Option<WordCard>> {
+    let card = store
+        .get_string(&schema::card_key(chat_id, message_id))
+        .await?;
+    Ok(card.and_then(|it| serde_json::from_str(&it).ok()))
+}
+
+pub async fn save_card(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    message_id: MessageId,
+    card: &WordCard,
+) -> Result<()> {
+    store
+        .set_string(
+            &schema::card_key(chat_id, message_id),
+            &serde_json::to_string(card)?,
+            Some(schema::CARD_TTL_SECS),
+        )
+        .await
+}
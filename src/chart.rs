@@ -0,0 +1,257 @@
+//! A bar chart drawn straight into an uncompressed PNG, no fonts or image crates needed.
+
+const WIDTH: usize = 600;
+const HEIGHT: usize = 300;
+const MARGIN: usize = 20;
+/// Room left of the y axis and below the x axis for the labels.
+const LABEL_SPACE: usize = 40;
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const AXIS: [u8; 3] = [120, 120, 120];
+const BAR: [u8; 3] = [88, 204, 2];
+
+/// Digits of 3×5 pixels, a bit per pixel row by row from the top left.
+const DIGITS: [u16; 10] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_001_001_001,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+];
+/// How many pixels wide and high each pixel of a digit is drawn.
+const DIGIT_SCALE: usize = 2;
+const DIGIT_ADVANCE: usize = 4 * DIGIT_SCALE;
+const DIGIT_HEIGHT: usize = 5 * DIGIT_SCALE;
+
+struct Canvas(Vec<[u8; 3]>);
+
+impl Canvas {
+    fn fill(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, color: [u8; 3]) {
+        for y in y0..y1.min(HEIGHT) {
+            for x in x0..x1.min(WIDTH) {
+                self.0[y * WIDTH + x] = color;
+            }
+        }
+    }
+
+    /// Writes `number` with its top edge at `y`.
+    fn number(&mut self, number: u32, x: Anchor, y: usize) {
+        let text = number.to_string();
+        let width = text.len() * DIGIT_ADVANCE - DIGIT_SCALE;
+        let left = match x {
+            Anchor::Center(center_x) => center_x.saturating_sub(width / 2),
+            Anchor::Right(right_x) => right_x.saturating_sub(width),
+        };
+        for (index, digit) in text.bytes().enumerate() {
+            let glyph = DIGITS[(digit - b'0') as usize];
+            for bit in 0..15 {
+                if glyph & (1 << (14 - bit)) != 0 {
+                    let x = left + index * DIGIT_ADVANCE + bit % 3 * DIGIT_SCALE;
+                    let y = y + bit / 3 * DIGIT_SCALE;
+                    self.fill(x, y, x + DIGIT_SCALE, y + DIGIT_SCALE, AXIS);
+                }
+            }
+        }
+    }
+}
+
+enum Anchor {
+    Center(usize),
+    Right(usize),
+}
+
+/// One bar per value, scaled so the largest one fills the height, with
+/// `labels` such as days of the month under the bars and the scale on the
+/// y axis.
+pub fn bar_chart_png(values: &[u32], labels: &[u32]) -> Vec<u8> {
+    let mut canvas = Canvas(vec![BACKGROUND; WIDTH * HEIGHT]);
+    let baseline = HEIGHT - LABEL_SPACE;
+    canvas.fill(LABEL_SPACE, baseline, WIDTH - MARGIN, baseline + 1, AXIS);
+    canvas.fill(LABEL_SPACE - 1, MARGIN, LABEL_SPACE, baseline + 1, AXIS);
+    let max = values.iter().copied().max().unwrap_or_default().max(1);
+    // The scale: a tick at the top for the largest value, and zero.
+    canvas.fill(LABEL_SPACE - 4, MARGIN, LABEL_SPACE, MARGIN + 1, AXIS);
+    for (value, y) in [(max, MARGIN), (0, baseline)] {
+        let right = Anchor::Right(LABEL_SPACE - 8);
+        canvas.number(value, right, y - DIGIT_HEIGHT / 2);
+    }
+    let slot = (WIDTH - LABEL_SPACE - MARGIN) / values.len().max(1);
+    for (index, value) in values.iter().enumerate() {
+        let height = *value as usize * (baseline - MARGIN) / max as usize;
+        let x = LABEL_SPACE + index * slot + slot / 8;
+        canvas.fill(x, baseline - height, x + slot * 3 / 4, baseline, BAR);
+        if let Some(label) = labels.get(index) {
+            let center = Anchor::Center(LABEL_SPACE + index * slot + slot / 2);
+            canvas.number(*label, center, baseline + 8);
+        }
+    }
+    let pixels = canvas.0;
+    let mut raw = Vec::with_capacity(HEIGHT * (WIDTH * 3 + 1));
+    for row in pixels.chunks(WIDTH) {
+        // Filter type "none" for every scanline.
+        raw.push(0);
+        raw.extend(row.iter().flatten());
+    }
+    encode_png(WIDTH as u32, HEIGHT as u32, &raw)
+}
+
+fn encode_png(width: u32, height: u32, raw: &[u8]) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::new();
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bit RGB, default compression, filtering and no interlacing.
+    header.extend([8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut result = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        result.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        result.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        result.extend(len.to_le_bytes());
+        result.extend((!len).to_le_bytes());
+        result.extend(block);
+    }
+    result.extend(adler32(data).to_be_bytes());
+    result
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits a PNG into its chunks, checking their CRCs.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind_and_data, crc) = rest[4..].split_at(4 + len);
+            assert_eq!(
+                crc32(kind_and_data),
+                u32::from_be_bytes(crc[..4].try_into().unwrap())
+            );
+            let (kind, data) = kind_and_data.split_at(4);
+            chunks.push((kind.try_into().unwrap(), data));
+            rest = &crc[4..];
+        }
+        chunks
+    }
+
+    /// Inflates a zlib stream of stored blocks, checking its Adler-32.
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!((u16::from(zlib[0]) << 8 | u16::from(zlib[1])) % 31, 0);
+        let mut data = Vec::new();
+        let mut rest = &zlib[2..];
+        loop {
+            let last = rest[0] & 1 == 1;
+            assert_eq!(rest[0] >> 1, 0, "not a stored block");
+            let len = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(!len, u16::from_le_bytes([rest[3], rest[4]]));
+            data.extend(&rest[5..5 + len as usize]);
+            rest = &rest[5 + len as usize..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn checksums_match_known_vectors() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[0xff; 6000]), 0xa497_59ea);
+    }
+
+    #[test]
+    fn zlib_stored_splits_into_blocks() {
+        let data: Vec<u8> = (0..200_000u32).map(|it| it as u8).collect();
+        assert_eq!(inflate_stored(&zlib_stored(&data)), data);
+        assert!(inflate_stored(&zlib_stored(&[])).is_empty());
+    }
+
+    #[test]
+    fn chart_decodes() {
+        let png = bar_chart_png(&[0, 3, 6], &[30, 31, 1]);
+        let chunks = chunks(&png);
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        let header = chunks[0].1;
+        assert_eq!(header[..8], [0, 0, 2, 88, 0, 0, 1, 44]);
+        assert_eq!(header[8..], [8, 2, 0, 0, 0]);
+        let raw = inflate_stored(chunks[1].1);
+        assert_eq!(raw.len(), HEIGHT * (WIDTH * 3 + 1));
+        let pixel = |x: usize, y: usize| {
+            let start = y * (WIDTH * 3 + 1) + 1 + x * 3;
+            <[u8; 3]>::try_from(&raw[start..start + 3]).unwrap()
+        };
+        let slot = (WIDTH - LABEL_SPACE - MARGIN) / 3;
+        let center = |index: usize| LABEL_SPACE + index * slot + slot / 2;
+        let baseline = HEIGHT - LABEL_SPACE;
+        // The largest bar fills the height, the half one half of it.
+        assert_eq!(pixel(center(2), MARGIN), BAR);
+        assert_eq!(pixel(center(1), (MARGIN + baseline) / 2 + 1), BAR);
+        assert_eq!(pixel(center(1), (MARGIN + baseline) / 2 - 1), BACKGROUND);
+        assert_eq!(pixel(center(0), baseline - 1), BACKGROUND);
+        // The middle column of the "1" under the last bar.
+        assert_eq!(pixel(center(2), baseline + 8), AXIS);
+        // Labels left of the y axis at the top and at the baseline.
+        let labelled = |y: usize| (0..LABEL_SPACE - 4).any(|x| pixel(x, y) == AXIS);
+        assert!(labelled(MARGIN));
+        assert!(labelled(baseline));
+        assert!(!labelled((MARGIN + baseline) / 2));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use super::offline::add_entry;
+use crate::bing_dictionary::{Example, Inflection, Word};
+
+fn part_of_speech(class: &str) -> &str {
+    match class {
+        "nn" => "noun",
+        "vb" => "verb",
+        "jj" => "adjective",
+        "ab" => "adverb",
+        "pp" => "preposition",
+        "pn" => "pronoun",
+        "kn" => "conjunction",
+        "in" => "interjection",
+        "rg" => "numeral",
+        "pm" => "proper noun",
+        "abbrev" => "abbreviation",
+        other => other,
+    }
+}
+
+/// Swedish nouns are `ett` words when their definite singular ends in -t.
+fn article(class: &str, inflections: &[Inflection]) -> Option<String> {
+    if class != "nn" {
+        return None;
+    }
+    let definite = &inflections.first()?.word;
+    Some(if definite.ends_with('t') { "ett" } else { "en" }.to_string())
+}
+
+/// Names the forms of the paradigms whose order Folkets keeps fixed.
+fn label_forms(class: &str, inflections: &mut [Inflection]) {
+    let names: &[&str] = match class {
+        "nn" => &["definite singular", "indefinite plural", "definite plural"],
+        "jj" => &["neuter", "plural/definite", "comparative", "superlative"],
+        _ => return,
+    };
+    if inflections.len() == names.len() {
+        for (inflection, name) in inflections.iter_mut().zip(names) {
+            inflection.form = name.to_string();
+        }
+    }
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads a Folkets lexikon dump such as `folkets_sv_en_public.xml`, keeping
+/// the `<word>`s whose `lang` is `language`.
+///
+/// Folkets writes pronunciations in its own notation rather than IPA.
+pub fn parse(content: &str, language: &str) -> HashMap<String, Word> {
+    let tag_regex = Regex::new(r"<(/?)([A-Za-z]+)([^>]*?)(/?)>").unwrap();
+    let attribute_regex = Regex::new(r#"([A-Za-z]+)="([^"]*)""#).unwrap();
+    let mut entries = HashMap::new();
+    let mut word: Option<(Word, String)> = None;
+    // The element inside `<word>` a `<translation>` belongs to.
+    let mut parent: Option<String> = None;
+    let mut meanings: Vec<String> = Vec::new();
+    for tag in tag_regex.captures_iter(content) {
+        let closing = &tag[1] == "/";
+        let name = &tag[2];
+        let self_closing = &tag[4] == "/";
+        let attributes = attribute_regex
+            .captures_iter(&tag[3])
+            .map(|it| (it[1].to_string(), unescape(&it[2])))
+            .collect::<HashMap<_, _>>();
+        let value = attributes.get("value").cloned().unwrap_or_default();
+        if name == "word" {
+            if closing {
+                if let Some((mut finished, class)) = word.take() {
+                    finished.meaning = meanings.join(", ");
+                    finished.article = article(&class, &finished.inflections);
+                    label_forms(&class, &mut finished.inflections);
+                    meanings.clear();
+                    if !finished.meaning.is_empty() {
+                        add_entry(&mut entries, finished);
+                    }
+                }
+            } else if attributes.get("lang").map(String::as_str) == Some(language) {
+                let class = attributes.get("class").cloned().unwrap_or_default();
+                word = Some((
+                    Word {
+                        spell: value,
+                        part_of_speech: part_of_speech(&class).to_string(),
+                        ..Word::default()
+                    },
+                    class,
+                ));
+            }
+            continue;
+        }
+        let Some((current, _)) = word.as_mut() else {
+            continue;
+        };
+        match (name, closing) {
+            ("translation", false) => match parent.as_deref() {
+                None => meanings.push(value),
+                Some("example") => {
+                    if let Some(example) = current.more_examples.last_mut() {
+                        example.translation = value;
+                    }
+                }
+                _ => {}
+            },
+            ("phonetic", false) if parent.is_none() => current.pronunciation = value,
+            ("inflection", false) => current.inflections.push(Inflection {
+                form: String::new(),
+                word: value,
+            }),
+            ("example", false) if parent.is_none() => current.more_examples.push(Example {
+                sentence: value,
+                translation: String::new(),
+            }),
+            _ => {}
+        }
+        if closing {
+            if parent.as_deref() == Some(name) {
+                parent = None;
+            }
+        } else if parent.is_none() && !self_closing && name != "paradigm" {
+            parent = Some(name.to_string());
+        }
+    }
+    entries.values_mut().for_each(Word::promote_first_example);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEXIKON: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<dictionary source-language="sv" target-language="en" name="Folkets lexikon">
+<word value="hus" lang="sv" class="nn">
+  <translation value="house"/>
+  <translation value="building"/>
+  <phonetic value="hu:s"/>
+  <paradigm>
+    <inflection value="huset"/>
+    <inflection value="hus"/>
+    <inflection value="husen"/>
+  </paradigm>
+  <example value="Huset är rött.">
+    <translation value="The house is red."/>
+  </example>
+  <definition value="byggnad">
+    <translation value="structure"/>
+  </definition>
+</word>
+<word value="hund" lang="sv" class="nn">
+  <translation value="dog"/>
+  <paradigm>
+    <inflection value="hunden"/>
+    <inflection value="hundar"/>
+    <inflection value="hundarna"/>
+  </paradigm>
+</word>
+<word value="snabb" lang="sv" class="jj">
+  <translation value="quick &amp; fast"/>
+  <paradigm>
+    <inflection value="snabbt"/>
+    <inflection value="snabba"/>
+    <inflection value="snabbare"/>
+    <inflection value="snabbast"/>
+  </paradigm>
+</word>
+<word value="hund" lang="sv" class="vb">
+  <translation value="to hound"/>
+</word>
+<word value="dog" lang="en" class="nn">
+  <translation value="hund"/>
+</word>
+<word value="tom" lang="sv" class="ab"/>
+</dictionary>"#;
+
+    #[test]
+    fn parses_words_of_the_language() {
+        let entries = parse(LEXIKON, "sv");
+        let mut spells = entries.keys().map(String::as_str).collect::<Vec<_>>();
+        spells.sort();
+        assert_eq!(spells, ["hund", "hus", "snabb"]);
+
+        let hus = &entries["hus"];
+        assert_eq!(hus.meaning, "house, building");
+        assert_eq!(hus.part_of_speech, "noun");
+        assert_eq!(hus.pronunciation, "hu:s");
+        assert_eq!(hus.article.as_deref(), Some("ett"));
+        let forms = hus
+            .inflections
+            .iter()
+            .map(|it| (it.form.as_str(), it.word.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            forms,
+            [
+                ("definite singular", "huset"),
+                ("indefinite plural", "hus"),
+                ("definite plural", "husen"),
+            ]
+        );
+        assert_eq!(hus.example_sentence, "Huset är rött.");
+        assert_eq!(hus.example_sentence_translation, "The house is red.");
+        assert!(hus.more_examples.is_empty());
+
+        let snabb = &entries["snabb"];
+        assert_eq!(snabb.meaning, "quick & fast");
+        assert_eq!(snabb.article, None);
+        assert_eq!(snabb.inflections[3].form, "superlative");
+    }
+
+    #[test]
+    fn merges_words_spelled_alike() {
+        let hund = &parse(LEXIKON, "sv")["hund"];
+        assert_eq!(hund.meaning, "dog; to hound");
+        assert_eq!(hund.part_of_speech, "noun, verb");
+        assert_eq!(hund.article.as_deref(), Some("en"));
+        assert_eq!(hund.inflections.len(), 3);
+    }
+
+    #[test]
+    fn article_follows_the_definite_singular() {
+        let article_of = |definite: &str| {
+            let inflections = [Inflection {
+                form: String::new(),
+                word: definite.to_string(),
+            }];
+            article("nn", &inflections)
+        };
+        assert_eq!(article_of("äpplet").as_deref(), Some("ett"));
+        assert_eq!(article_of("ögat").as_deref(), Some("ett"));
+        assert_eq!(article_of("katten").as_deref(), Some("en"));
+        assert_eq!(article_of("flickan").as_deref(), Some("en"));
+        assert_eq!(article("nn", &[]), None);
+        assert_eq!(article("vb", &[]), None);
+    }
+}
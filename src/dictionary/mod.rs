@@ -0,0 +1,67 @@
+mod folkets;
+mod offline;
+mod wiktionary;
+
+use async_trait::async_trait;
+
+use crate::{
+    bing_dictionary::Word,
+    error::Result,
+    llm::ChatBackend,
+    prompt::{PromptContext, Prompts},
+};
+
+pub use offline::{import, OfflineDictionary};
+
+#[async_trait]
+pub trait DictionarySource: Send + Sync {
+    /// Looks up `spell` in `language`, explained in `ui_language` (ISO 639-1
+    /// codes), `None` when this source doesn't know the word.
+    async fn look_up(&self, spell: &str, language: &str, ui_language: &str)
+        -> Result<Option<Word>>;
+}
+
+/// Asks the language model, which knows every word but may make things up.
+pub struct LlmDictionary<'a> {
+    pub prompts: &'a Prompts,
+    pub context: &'a PromptContext,
+    pub chat_backend: &'a dyn ChatBackend,
+}
+
+#[async_trait]
+impl DictionarySource for LlmDictionary<'_> {
+    async fn look_up(&self, spell: &str, _: &str, _: &str) -> Result<Option<Word>> {
+        Word::look_up(spell, self.prompts, self.context, self.chat_backend)
+            .await
+            .map(Some)
+    }
+}
+
+/// Asks each source in turn until one of them knows the word.
+///
+/// A failing source is skipped, its error is only returned when no later
+/// source has the word either.
+pub struct Fallback<'a>(pub Vec<&'a dyn DictionarySource>);
+
+#[async_trait]
+impl DictionarySource for Fallback<'_> {
+    async fn look_up(
+        &self,
+        spell: &str,
+        language: &str,
+        ui_language: &str,
+    ) -> Result<Option<Word>> {
+        let mut last_error = None;
+        for source in &self.0 {
+            match source.look_up(spell, language, ui_language).await {
+                Ok(Some(word)) => return Ok(Some(word)),
+                Ok(None) => {}
+                Err(error) => {
+                    println!("Dictionary lookup of {spell} failed: {error}");
+                    last_error = Some(error);
+                }
+            }
+        }
+        last_error.map_or(Ok(None), Err)
+    }
+}
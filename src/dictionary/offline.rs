@@ -0,0 +1,87 @@
+use std::{collections::HashMap, env, fs};
+
+use async_trait::async_trait;
+
+use super::{folkets, wiktionary, DictionarySource};
+use crate::{
+    bing_dictionary::Word,
+    error::Result,
+    store::{self, schema, StateStore},
+};
+
+/// Entries written to the store at once while importing.
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+fn entry_key(spell: &str) -> String {
+    spell.trim().to_lowercase()
+}
+
+/// A dictionary imported from a dump on disk, kept in the hash
+/// `dictionary-{language}-{ui_language}` keyed by the lowercased word.
+pub struct OfflineDictionary<'a> {
+    store: &'a dyn StateStore,
+}
+
+impl<'a> OfflineDictionary<'a> {
+    /// Reads from `store`, `None` when `DICTIONARY=llm` asks to skip it.
+    pub fn from_env(store: &'a dyn StateStore) -> Option<Self> {
+        if env::var("DICTIONARY").as_deref() == Ok("llm") {
+            return None;
+        }
+        Some(Self { store })
+    }
+}
+
+#[async_trait]
+impl DictionarySource for OfflineDictionary<'_> {
+    async fn look_up(
+        &self,
+        spell: &str,
+        language: &str,
+        ui_language: &str,
+    ) -> Result<Option<Word>> {
+        let entry = self
+            .store
+            .hash_get(
+                &schema::dictionary_key(language, ui_language),
+                &entry_key(spell),
+            )
+            .await?;
+        Ok(entry.and_then(|it| serde_json::from_str(&it).ok()))
+    }
+}
+
+/// Adds `word` to the entries, merging it into an earlier entry of the same word.
+pub(super) fn add_entry(entries: &mut HashMap<String, Word>, word: Word) {
+    match entries.get_mut(&entry_key(&word.spell)) {
+        Some(existing) => existing.merge(word),
+        None => {
+            entries.insert(entry_key(&word.spell), word);
+        }
+    }
+}
+
+/// `import <folkets|wiktionary> <path> <language> <ui_language>`: builds the
+/// offline index from a Folkets lexikon XML or a Wiktionary JSONL extract.
+pub async fn import() {
+    let args = env::args().skip(2).collect::<Vec<_>>();
+    let [format, path, language, ui_language] = &args[..] else {
+        panic!("Usage: import <folkets|wiktionary> <path> <language> <ui_language>");
+    };
+    let content = fs::read_to_string(path).unwrap();
+    let entries = match format.as_str() {
+        "folkets" => folkets::parse(&content, language),
+        "wiktionary" => wiktionary::parse(&content, language),
+        other => panic!("Unknown dictionary format: {other}, expected folkets or wiktionary"),
+    };
+    let store = store::from_env().await.unwrap();
+    let key = schema::dictionary_key(language, ui_language);
+    let entries = entries
+        .into_iter()
+        .map(|(spell, word)| (spell, serde_json::to_string(&word).unwrap()))
+        .collect::<Vec<_>>();
+    for batch in entries.chunks(IMPORT_BATCH_SIZE) {
+        store.hash_set(&key, batch).await.unwrap();
+    }
+    println!("Imported {} words into {key}", entries.len());
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::offline::add_entry;
+use crate::bing_dictionary::{Example, Inflection, Word};
+
+/// One line of a kaikki.org style Wiktionary extract.
+#[derive(Deserialize)]
+struct Entry {
+    word: String,
+    #[serde(default)]
+    pos: String,
+    #[serde(default)]
+    lang_code: String,
+    #[serde(default)]
+    senses: Vec<Sense>,
+    #[serde(default)]
+    sounds: Vec<Sound>,
+    #[serde(default)]
+    forms: Vec<Form>,
+}
+
+#[derive(Deserialize)]
+struct Sense {
+    #[serde(default)]
+    glosses: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    examples: Vec<SenseExample>,
+}
+
+#[derive(Deserialize)]
+struct SenseExample {
+    text: String,
+    #[serde(default)]
+    english: String,
+}
+
+#[derive(Deserialize)]
+struct Sound {
+    #[serde(default)]
+    ipa: String,
+}
+
+#[derive(Deserialize)]
+struct Form {
+    form: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// The forms worth showing, Wiktionary also lists table headers and sources.
+fn is_inflection(form: &Form) -> bool {
+    !form
+        .tags
+        .iter()
+        .any(|it| it == "table-tags" || it == "inflection-template" || it == "romanization")
+}
+
+/// Reads a JSONL Wiktionary extract, keeping the entries of `language`.
+/// Lines that aren't entries are skipped.
+pub fn parse(content: &str, language: &str) -> HashMap<String, Word> {
+    let mut entries = HashMap::new();
+    for line in content.lines() {
+        let Ok(entry) = serde_json::from_str::<Entry>(line) else {
+            continue;
+        };
+        if entry.lang_code != language {
+            continue;
+        }
+        let is_form_of = |sense: &&Sense| sense.tags.iter().any(|it| it == "form-of");
+        let meaning = entry
+            .senses
+            .iter()
+            .filter(|sense| !is_form_of(sense))
+            .filter_map(|sense| sense.glosses.first().cloned())
+            .collect::<Vec<_>>()
+            .join("; ");
+        if meaning.is_empty() {
+            continue;
+        }
+        let word = Word {
+            spell: entry.word,
+            pronunciation: entry
+                .sounds
+                .into_iter()
+                .map(|it| it.ipa)
+                .find(|it| !it.is_empty())
+                .unwrap_or_default(),
+            part_of_speech: entry.pos,
+            meaning,
+            inflections: entry
+                .forms
+                .into_iter()
+                .filter(is_inflection)
+                .map(|it| Inflection {
+                    form: it.tags.join(" "),
+                    word: it.form,
+                })
+                .collect(),
+            more_examples: entry
+                .senses
+                .into_iter()
+                .flat_map(|it| it.examples)
+                .filter(|it| !it.english.is_empty())
+                .map(|it| Example {
+                    sentence: it.text,
+                    translation: it.english,
+                })
+                .collect(),
+            ..Word::default()
+        };
+        add_entry(&mut entries, word);
+    }
+    entries.values_mut().for_each(Word::promote_first_example);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTRACT: &str = r#"{"word": "katt", "pos": "noun", "lang_code": "sv", "sounds": [{"audio": "katt.ogg"}, {"ipa": "/kat/"}], "forms": [{"form": "c", "tags": ["table-tags"]}, {"form": "katten", "tags": ["definite", "singular"]}, {"form": "katter", "tags": ["indefinite", "plural"]}], "senses": [{"glosses": ["cat"], "examples": [{"text": "Katten sover.", "english": "The cat is sleeping."}, {"text": "Katt!"}]}, {"glosses": ["tomcat"], "examples": [{"text": "En katt jamar.", "english": "A cat meows."}]}]}
+{"word": "katten", "pos": "noun", "lang_code": "sv", "senses": [{"glosses": ["definite singular of katt"], "tags": ["form-of"]}]}
+{"word": "katt", "pos": "verb", "lang_code": "sv", "senses": [{"glosses": ["to sneak"]}]}
+{"word": "cat", "pos": "noun", "lang_code": "en", "senses": [{"glosses": ["katt"]}]}
+not json
+{"word": "tom", "lang_code": "sv"}"#;
+
+    #[test]
+    fn parses_entries_of_the_language() {
+        let entries = parse(EXTRACT, "sv");
+        assert_eq!(entries.keys().collect::<Vec<_>>(), ["katt"]);
+        let katt = &entries["katt"];
+        assert_eq!(katt.meaning, "cat; tomcat; to sneak");
+        assert_eq!(katt.part_of_speech, "noun, verb");
+        assert_eq!(katt.pronunciation, "/kat/");
+        let forms = katt
+            .inflections
+            .iter()
+            .map(|it| (it.form.as_str(), it.word.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            forms,
+            [
+                ("definite singular", "katten"),
+                ("indefinite plural", "katter"),
+            ]
+        );
+        assert_eq!(katt.example_sentence, "Katten sover.");
+        assert_eq!(katt.example_sentence_translation, "The cat is sleeping.");
+        assert_eq!(katt.more_examples.len(), 1);
+        assert_eq!(katt.more_examples[0].sentence, "En katt jamar.");
+    }
+}
@@ -1,12 +1,27 @@
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
-use crate::util::new_reqwest_client;
+use crate::{
+    error::{Error, Result},
+    util::new_reqwest_client,
+};
+
+const DEFAULT_URL: &str = "https://www.duolingo.com";
+
+/// `path` on Duolingo, or on `DUOLINGO_URL` such as a fake server in tests.
+fn url(path: &str) -> String {
+    let base_url = std::env::var("DUOLINGO_URL").unwrap_or_else(|_| DEFAULT_URL.to_string());
+    format!("{}{path}", base_url.trim_end_matches('/'))
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Vocabulary {
     pub id: String,
     pub word_string: String,
     pub last_practiced_ms: u64,
+    /// The language of the course the word is from.
+    #[serde(default)]
+    pub language: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -15,24 +30,32 @@ pub struct Duolingo {
     duolingo_jwt: String,
     pub languages: Vec<String>,
     pub ui_language: String,
+    /// The course being practised, `vocabulary` belongs to it.
+    #[serde(default)]
+    pub active_language: Option<String>,
     pub vocabulary: Vec<Vocabulary>,
 }
 
 impl Duolingo {
-    pub async fn new(duolingo_name: &str, duolingo_jwt: &str) -> Self {
-        let (languages, ui_language) = Self::fetch_language_info(duolingo_name, duolingo_jwt).await;
-        let mut vocabulary = Self::fetch_vocabularies(duolingo_jwt).await;
-        vocabulary.sort_unstable_by_key(|it| it.last_practiced_ms);
-        Self {
+    pub async fn new(duolingo_name: &str, duolingo_jwt: &str) -> Result<Self> {
+        let (languages, ui_language) =
+            Self::fetch_language_info(duolingo_name, duolingo_jwt).await?;
+        let (active_language, vocabulary) = Self::fetch_vocabularies(duolingo_jwt, None).await?;
+        let vocabulary = vocabulary
+            .into_iter()
+            .filter(|it| it.language.as_deref() == Some(&active_language))
+            .collect();
+        Ok(Self {
             duolingo_name: duolingo_name.to_string(),
             duolingo_jwt: duolingo_jwt.to_string(),
             languages,
             ui_language,
+            active_language: Some(active_language),
             vocabulary,
-        }
+        })
     }
 
-    pub async fn from_env() -> Self {
+    pub async fn from_env() -> Result<Self> {
         Self::new(
             &std::env::var("DUOLINGO_NAME").unwrap(),
             &std::env::var("DUOLINGO_JWT").unwrap(),
@@ -40,38 +63,90 @@ impl Duolingo {
         .await
     }
 
+    /// The language being learnt, the active course or else the first one.
+    pub fn learning_language(&self) -> Result<&str> {
+        self.active_language
+            .as_deref()
+            .or_else(|| self.languages.first().map(String::as_str))
+            .ok_or(Error::NoCourse)
+    }
+
+    /// Practises the `language` course here and loads its vocabulary, leaving
+    /// the course active on Duolingo as it is.
+    pub async fn select_course(&mut self, language: &str) -> Result<()> {
+        if !self.languages.iter().any(|it| it == language) {
+            return Err(Error::UnknownCourse(language.to_string()));
+        }
+        let course = (language, self.ui_language.as_str());
+        let (_, vocabulary) = Self::fetch_vocabularies(&self.duolingo_jwt, Some(course)).await?;
+        self.active_language = Some(language.to_string());
+        self.vocabulary = vocabulary
+            .into_iter()
+            .filter(|it| it.language.as_deref() == Some(language))
+            .collect();
+        Ok(())
+    }
+
     pub async fn fetch_language_info(
         duolingo_name: &str,
         duolingo_jwt: &str,
-    ) -> (Vec<String>, String) {
-        let url = format!("https://www.duolingo.com/users/{duolingo_name}");
+    ) -> Result<(Vec<String>, String)> {
         let response = new_reqwest_client()
-            .get(&url)
+            .get(url(&format!("/users/{duolingo_name}")))
             .bearer_auth(duolingo_jwt)
             .send()
-            .await
-            .unwrap();
-        let user_info: serde_json::Value = response.json().await.unwrap();
+            .await?;
+        let user_info: serde_json::Value = check_status(response)?.json().await?;
         let languages = user_info["language_data"]
             .as_object()
-            .unwrap()
+            .ok_or(Error::DuolingoSessionExpired)?
             .keys()
             .cloned()
             .collect();
-        let ui_language = user_info["ui_language"].as_str().unwrap().to_string();
-        (languages, ui_language)
+        let ui_language = user_info["ui_language"]
+            .as_str()
+            .ok_or(Error::DuolingoSessionExpired)?
+            .to_string();
+        Ok((languages, ui_language))
     }
 
-    async fn fetch_vocabularies(duolingo_jwt: &str) -> Vec<Vocabulary> {
-        let mut vocabulary_info: serde_json::Value = new_reqwest_client()
-            .get("https://www.duolingo.com/vocabulary/overview")
-            .bearer_auth(duolingo_jwt)
-            .send()
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap();
-        serde_json::from_value(vocabulary_info["vocab_overview"].take()).unwrap()
+    /// Fetches the vocabulary, asking for the `(learning_language,
+    /// from_language)` course, returning the current course on Duolingo along
+    /// with it. Duolingo may answer with another course than asked for, so the
+    /// words are marked with the language of their course to be filtered by.
+    async fn fetch_vocabularies(
+        duolingo_jwt: &str,
+        course: Option<(&str, &str)>,
+    ) -> Result<(String, Vec<Vocabulary>)> {
+        let mut request = new_reqwest_client()
+            .get(url("/vocabulary/overview"))
+            .bearer_auth(duolingo_jwt);
+        if let Some((learning_language, from_language)) = course {
+            request = request.query(&[
+                ("learning_language", learning_language),
+                ("from_language", from_language),
+            ]);
+        }
+        let response = request.send().await?;
+        let mut vocabulary_info: serde_json::Value = check_status(response)?.json().await?;
+        let learning_language = vocabulary_info["learning_language"]
+            .as_str()
+            .ok_or(Error::NoCourse)?
+            .to_string();
+        let mut vocabulary: Vec<Vocabulary> =
+            serde_json::from_value(vocabulary_info["vocab_overview"].take())?;
+        for word in &mut vocabulary {
+            word.language
+                .get_or_insert_with(|| learning_language.clone());
+        }
+        vocabulary.sort_unstable_by_key(|it| it.last_practiced_ms);
+        Ok((learning_language, vocabulary))
+    }
+}
+
+fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::DuolingoSessionExpired),
+        _ => Ok(response.error_for_status()?),
     }
 }
@@ -0,0 +1,108 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("network request failed: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("Telegram API call {method} failed: {description}")]
+    Telegram {
+        method: &'static str,
+        description: String,
+    },
+    #[error("Telegram API call {method} is rate limited for {retry_after:?}")]
+    TelegramRateLimited {
+        method: &'static str,
+        retry_after: std::time::Duration,
+    },
+    #[error("Duolingo rejected the JWT")]
+    DuolingoSessionExpired,
+    #[error("not logged in to Duolingo")]
+    DuolingoNotLoggedIn,
+    #[error("no Duolingo course found")]
+    NoCourse,
+    #[error("no Duolingo course for {0}")]
+    UnknownCourse(String),
+    #[error("the Duolingo vocabulary is empty")]
+    EmptyVocabulary,
+    #[error("too few looked-up words for a quiz")]
+    NotEnoughWords,
+    #[error("no dictionary knows {0}")]
+    UnknownWord(String),
+    #[error("unknown time zone {0}")]
+    UnknownTimeZone(String),
+    #[error("Azure TTS failed: {0}")]
+    AzureTTS(String),
+    #[error("no speech recognized in the voice message")]
+    SpeechNotRecognized,
+    #[error("the language model failed: {0}")]
+    Llm(String),
+    #[error("unexpected reply from the language model: {0}")]
+    MalformedLlmReply(String),
+    #[error("the chat session is missing or expired")]
+    ChatSessionExpired,
+    #[error("the word card is missing or expired")]
+    CardExpired,
+    #[error("redis failed: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[error("the state file failed: {0}")]
+    StateFile(std::io::Error),
+    #[error("the stored state has schema version {0}, newer than this build supports")]
+    UnsupportedSchema(String),
+    #[error("JSON (de)serialization failed: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("wrong usage, expected `{0}`")]
+    Usage(&'static str),
+    #[error("unsupported update: {0}")]
+    UnsupportedUpdate(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The reply shown to the user when handling their message failed.
+    pub fn user_message(&self) -> String {
+        match self {
+            Error::DuolingoSessionExpired => {
+                "Your Duolingo session expired, run /duolingo_login again.".to_string()
+            }
+            Error::DuolingoNotLoggedIn => {
+                "Please use /duolingo_login to login to duolingo.".to_string()
+            }
+            Error::NoCourse => "You aren't learning any language on Duolingo yet.".to_string(),
+            Error::UnknownCourse(language) => {
+                format!("You don't have a Duolingo course for {language}, see /language.")
+            }
+            Error::EmptyVocabulary => {
+                "Your Duolingo vocabulary is empty, learn some words first.".to_string()
+            }
+            Error::SpeechNotRecognized => {
+                "I couldn't make out that voice message, please try again or type it.".to_string()
+            }
+            Error::NotEnoughWords => {
+                "I need a few more words first, look at some with /random_word.".to_string()
+            }
+            Error::UnknownTimeZone(name) => {
+                format!("I don't know the time zone \"{name}\", use a name like Europe/Stockholm.")
+            }
+            Error::UnknownWord(word) => format!("I couldn't find \"{word}\" in any dictionary."),
+            Error::Llm(_) => {
+                "The language model is unavailable right now, please try again later.".to_string()
+            }
+            Error::MalformedLlmReply(_) => {
+                "The language model gave an answer I couldn't understand, please try again."
+                    .to_string()
+            }
+            Error::ChatSessionExpired => {
+                "This conversation has expired, start a new one with /chat.".to_string()
+            }
+            Error::CardExpired => {
+                "This card has expired, get a new one with /random_word.".to_string()
+            }
+            Error::TelegramRateLimited { .. } => {
+                "Telegram asked me to slow down, please try again in a minute.".to_string()
+            }
+            Error::Usage(usage) => format!("Usage: {usage}"),
+            _ => "Something went wrong, please try again later.".to_string(),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use teloxide::types::ChatId;
+
+use crate::{
+    error::Result,
+    store::{schema, StateStore},
+};
+
+const LEADERBOARD_SIZE: usize = 10;
+
+/// Remembers that `member_id` takes part in the group, under `name`.
+pub async fn record_member(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    member_id: ChatId,
+    name: &str,
+) -> Result<()> {
+    let fields = [(member_id.to_string(), name.to_string())];
+    store
+        .hash_set(&schema::group_members_key(chat_id), &fields)
+        .await
+}
+
+/// The members seen in the group, with their names.
+pub async fn members(store: &dyn StateStore, chat_id: ChatId) -> Result<Vec<(ChatId, String)>> {
+    let members = store
+        .hash_get_all(&schema::group_members_key(chat_id))
+        .await?;
+    Ok(members
+        .into_iter()
+        .filter_map(|(id, name)| Some((ChatId(id.parse().ok()?), name)))
+        .collect())
+}
+
+/// Counts a correct group quiz answer of `member_id`.
+pub async fn add_point(store: &dyn StateStore, chat_id: ChatId, member_id: ChatId) -> Result<()> {
+    store
+        .sorted_set_increment(
+            &schema::quiz_leaderboard_key(chat_id),
+            &member_id.to_string(),
+            1.0,
+        )
+        .await
+}
+
+/// The leaderboard as lines of the best members and their points.
+pub async fn leaderboard_text(store: &dyn StateStore, chat_id: ChatId) -> Result<String> {
+    let scores = store
+        .sorted_set_top(&schema::quiz_leaderboard_key(chat_id), LEADERBOARD_SIZE)
+        .await?;
+    if scores.is_empty() {
+        return Ok("No quiz answers yet, start one with /quiz.".to_string());
+    }
+    let names = members(store, chat_id)
+        .await?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let lines = scores
+        .iter()
+        .enumerate()
+        .map(|(rank, (id, points))| {
+            let name = id
+                .parse()
+                .ok()
+                .and_then(|id| names.get(&ChatId(id)))
+                .map_or("Someone", String::as_str);
+            format!("{}. {name}: {points}", rank + 1)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(format!("Quiz leaderboard:\n{lines}"))
+}
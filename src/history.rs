@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+use crate::{
+    error::Result,
+    mistakes::Category,
+    review::{now_ms, Grade},
+    store::{schema, StateStore},
+};
+
+/// Only the latest events are kept per chat.
+const MAX_EVENTS: usize = 20_000;
+
+/// Something the learner did.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventKind {
+    WordShown {
+        vocabulary_id: String,
+    },
+    Reviewed {
+        vocabulary_id: String,
+        grade: Grade,
+    },
+    QuizAnswered {
+        vocabulary_id: String,
+        correct: bool,
+    },
+    ChatTurn,
+    StoryGenerated,
+    MistakeCorrected {
+        category: Category,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Event {
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Appends events to the chat's log, kept in the list `events-{chat_id}`.
+pub async fn record(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    kinds: impl IntoIterator<Item = EventKind>,
+) -> Result<()> {
+    let at_ms = now_ms();
+    let events = kinds
+        .into_iter()
+        .map(|kind| serde_json::to_string(&Event { at_ms, kind }))
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    store
+        .list_push(&schema::events_key(chat_id), &events, MAX_EVENTS)
+        .await
+}
+
+pub async fn load(store: &dyn StateStore, chat_id: ChatId) -> Result<Vec<Event>> {
+    let events = store.list_all(&schema::events_key(chat_id)).await?;
+    Ok(events
+        .into_iter()
+        .filter_map(|it| serde_json::from_str(&it).ok())
+        .collect())
+}
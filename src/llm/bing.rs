@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use edge_gpt::{ConversationStyle, CookieInFile};
+
+use super::{ChatBackend, ChatReply, ChatSession, Creativity};
+use crate::error::{Error, Result};
+
+pub struct BingBackend {
+    cookies: Vec<CookieInFile>,
+}
+
+impl BingBackend {
+    pub fn new(cookies: Vec<CookieInFile>) -> Self {
+        Self { cookies }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let cookie_str = std::env::var("EDGE_GPT_COOKIE")
+            .map_err(|_| Error::Llm("EDGE_GPT_COOKIE is not set".to_string()))?;
+        Ok(Self::new(serde_json::from_str(&cookie_str)?))
+    }
+}
+
+#[async_trait]
+impl ChatBackend for BingBackend {
+    async fn create_session(&self, creativity: Creativity) -> Result<Box<dyn ChatSession>> {
+        let style = match creativity {
+            Creativity::Creative => ConversationStyle::Creative,
+            Creativity::Balanced => ConversationStyle::Balanced,
+        };
+        let session = edge_gpt::ChatSession::create(style, &self.cookies)
+            .await
+            .map_err(|e| Error::Llm(e.to_string()))?;
+        Ok(Box::new(BingSession(session)))
+    }
+
+    fn restore_session(&self, state: &str) -> Result<Box<dyn ChatSession>> {
+        Ok(Box::new(BingSession(serde_json::from_str(state)?)))
+    }
+}
+
+struct BingSession(edge_gpt::ChatSession);
+
+#[async_trait]
+impl ChatSession for BingSession {
+    async fn send_message(&mut self, text: &str) -> Result<ChatReply> {
+        let response = self
+            .0
+            .send_message(text)
+            .await
+            .map_err(|e| Error::Llm(e.to_string()))?;
+        Ok(ChatReply {
+            text: response.text,
+            source_attributions: response.source_attributions,
+        })
+    }
+
+    fn save(&self) -> String {
+        serde_json::to_string(&self.0).unwrap()
+    }
+}
@@ -0,0 +1,105 @@
+mod bing;
+mod openai;
+mod scripted;
+mod structured;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+pub use bing::BingBackend;
+pub use openai::OpenAiBackend;
+pub use scripted::ScriptedBackend;
+pub use structured::ask_json;
+
+/// How inventive the answers should be, each backend maps it to its own knob.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum Creativity {
+    Creative,
+    Balanced,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct ChatReply {
+    pub text: String,
+    pub source_attributions: Vec<String>,
+}
+
+#[async_trait]
+pub trait ChatBackend: Send + Sync {
+    async fn create_session(&self, creativity: Creativity) -> Result<Box<dyn ChatSession>>;
+    fn restore_session(&self, state: &str) -> Result<Box<dyn ChatSession>>;
+}
+
+#[async_trait]
+pub trait ChatSession: Send {
+    async fn send_message(&mut self, text: &str) -> Result<ChatReply>;
+    /// Serializes the session so it can be restored by [`ChatBackend::restore_session`].
+    fn save(&self) -> String;
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    Bing,
+    OpenAi,
+    Scripted,
+}
+
+impl TryFrom<&str> for BackendKind {
+    fn try_from(s: &str) -> std::result::Result<Self, Self::Error> {
+        match s {
+            "bing" => Ok(Self::Bing),
+            "openai" => Ok(Self::OpenAi),
+            "scripted" => Ok(Self::Scripted),
+            _ => Err(()),
+        }
+    }
+
+    type Error = ();
+}
+
+impl BackendKind {
+    /// Reads the deployment wide backend from `LLM_BACKEND`, defaults to Bing.
+    pub fn from_env() -> Self {
+        std::env::var("LLM_BACKEND")
+            .ok()
+            .and_then(|it| it.as_str().try_into().ok())
+            .unwrap_or(Self::Bing)
+    }
+
+    /// Whether users may pick this backend for themselves, the scripted one is
+    /// only for tests through `LLM_BACKEND`.
+    pub fn user_selectable(self) -> bool {
+        self != Self::Scripted
+    }
+
+    pub fn backend(self) -> Result<Box<dyn ChatBackend>> {
+        Ok(match self {
+            Self::Bing => Box::new(BingBackend::from_env()?),
+            Self::OpenAi => Box::new(OpenAiBackend::from_env()),
+            Self::Scripted => Box::new(ScriptedBackend::from_env()?),
+        })
+    }
+}
+
+/// A chat session as persisted between messages, remembering which backend created it.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct StoredSession {
+    pub backend: BackendKind,
+    pub state: String,
+}
+
+impl StoredSession {
+    pub fn new(backend: BackendKind, session: &dyn ChatSession) -> Self {
+        Self {
+            backend,
+            state: session.save(),
+        }
+    }
+
+    pub fn restore(&self) -> Result<Box<dyn ChatSession>> {
+        self.backend.backend()?.restore_session(&self.state)
+    }
+}
@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{ChatBackend, ChatReply, ChatSession, Creativity};
+use crate::{
+    error::{Error, Result},
+    util::new_reqwest_client,
+};
+
+/// Any server speaking the OpenAI chat-completions protocol,
+/// e.g. api.openai.com, a llama.cpp server or Ollama.
+#[derive(Clone, Debug)]
+pub struct OpenAiBackend {
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct OpenAiMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct OpenAiSessionState {
+    temperature: f32,
+    messages: Vec<OpenAiMessage>,
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: impl ToString, model: impl ToString, api_key: Option<String>) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            model: model.to_string(),
+            api_key,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("OPENAI_BASE_URL")
+                .unwrap_or_else(|_| "https://api.openai.com/v1".to_string()),
+            std::env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-3.5-turbo".to_string()),
+            std::env::var("OPENAI_API_KEY").ok(),
+        )
+    }
+}
+
+#[async_trait]
+impl ChatBackend for OpenAiBackend {
+    async fn create_session(&self, creativity: Creativity) -> Result<Box<dyn ChatSession>> {
+        let temperature = match creativity {
+            Creativity::Creative => 1.0,
+            Creativity::Balanced => 0.7,
+        };
+        Ok(Box::new(OpenAiSession {
+            backend: self.clone(),
+            state: OpenAiSessionState {
+                temperature,
+                messages: Vec::new(),
+            },
+        }))
+    }
+
+    fn restore_session(&self, state: &str) -> Result<Box<dyn ChatSession>> {
+        Ok(Box::new(OpenAiSession {
+            backend: self.clone(),
+            state: serde_json::from_str(state)?,
+        }))
+    }
+}
+
+struct OpenAiSession {
+    backend: OpenAiBackend,
+    state: OpenAiSessionState,
+}
+
+#[async_trait]
+impl ChatSession for OpenAiSession {
+    async fn send_message(&mut self, text: &str) -> Result<ChatReply> {
+        self.state.messages.push(OpenAiMessage {
+            role: "user".to_string(),
+            content: text.to_string(),
+        });
+        let url = format!(
+            "{}/chat/completions",
+            self.backend.base_url.trim_end_matches('/')
+        );
+        let mut request = new_reqwest_client().post(&url).json(&serde_json::json!({
+            "model": self.backend.model,
+            "temperature": self.state.temperature,
+            "messages": self.state.messages,
+        }));
+        if let Some(api_key) = &self.backend.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let mut response: serde_json::Value = request.send().await?.json().await?;
+        if let Some(error) = response["error"]["message"].as_str() {
+            self.state.messages.pop();
+            return Err(Error::Llm(error.to_string()));
+        }
+        let message: OpenAiMessage =
+            serde_json::from_value(response["choices"][0]["message"].take())
+                .map_err(|e| Error::MalformedLlmReply(e.to_string()))?;
+        let text = message.content.clone();
+        self.state.messages.push(message);
+        Ok(ChatReply {
+            text,
+            source_attributions: Vec::new(),
+        })
+    }
+
+    fn save(&self) -> String {
+        serde_json::to_string(&self.state).unwrap()
+    }
+}
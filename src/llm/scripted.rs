@@ -0,0 +1,81 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use async_trait::async_trait;
+
+use super::{ChatBackend, ChatReply, ChatSession, Creativity};
+use crate::error::{Error, Result};
+
+/// Replies with a fixed list of answers in order, whatever is asked.
+/// Meant for tests and dry runs without network access.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedBackend {
+    responses: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl ScriptedBackend {
+    pub fn new(responses: impl IntoIterator<Item = impl ToString>) -> Self {
+        Self {
+            responses: Arc::new(Mutex::new(
+                responses.into_iter().map(|it| it.to_string()).collect(),
+            )),
+        }
+    }
+
+    /// The backend shared by the whole process, so every session continues
+    /// the same script. Starts with `SCRIPTED_LLM_RESPONSES`, a JSON array of
+    /// strings, if set.
+    pub fn from_env() -> Result<Self> {
+        static SHARED: OnceLock<ScriptedBackend> = OnceLock::new();
+        if let Some(shared) = SHARED.get() {
+            return Ok(shared.clone());
+        }
+        let responses = match std::env::var("SCRIPTED_LLM_RESPONSES") {
+            Ok(responses_str) => serde_json::from_str::<Vec<String>>(&responses_str)?,
+            Err(_) => Vec::new(),
+        };
+        Ok(SHARED.get_or_init(|| Self::new(responses)).clone())
+    }
+
+    /// Replaces what is left of the script.
+    #[cfg(test)]
+    pub fn set_responses(&self, responses: impl IntoIterator<Item = impl ToString>) {
+        *self.responses.lock().unwrap() = responses.into_iter().map(|it| it.to_string()).collect();
+    }
+}
+
+#[async_trait]
+impl ChatBackend for ScriptedBackend {
+    async fn create_session(&self, _creativity: Creativity) -> Result<Box<dyn ChatSession>> {
+        Ok(Box::new(ScriptedSession(self.clone())))
+    }
+
+    fn restore_session(&self, _state: &str) -> Result<Box<dyn ChatSession>> {
+        Ok(Box::new(ScriptedSession(self.clone())))
+    }
+}
+
+struct ScriptedSession(ScriptedBackend);
+
+#[async_trait]
+impl ChatSession for ScriptedSession {
+    async fn send_message(&mut self, _text: &str) -> Result<ChatReply> {
+        let text = self
+            .0
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| Error::Llm("scripted backend ran out of responses".to_string()))?;
+        Ok(ChatReply {
+            text,
+            source_attributions: Vec::new(),
+        })
+    }
+
+    fn save(&self) -> String {
+        String::new()
+    }
+}
@@ -0,0 +1,238 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::ChatSession;
+use crate::error::{Error, Result};
+
+/// How many times the model is asked in total before giving up.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Sends `prompt` and parses a JSON object of type `T` out of the reply.
+///
+/// When the reply has no usable JSON or `validate` rejects it, the model is
+/// told what was wrong and asked again in the same session.
+pub async fn ask_json<T: DeserializeOwned>(
+    session: &mut dyn ChatSession,
+    prompt: &str,
+    validate: impl Fn(&T) -> std::result::Result<(), String>,
+) -> Result<T> {
+    let mut reply = session.send_message(prompt).await?;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let problem = match parse_json::<T>(&reply.text) {
+            Ok(value) => match validate(&value) {
+                Ok(()) => return Ok(value),
+                Err(problem) => problem,
+            },
+            Err(problem) => problem,
+        };
+        println!("Rejected structured reply (attempt {attempt}): {problem}");
+        if attempt == MAX_ATTEMPTS {
+            break;
+        }
+        let retry = format!(
+            "Your answer could not be used: {problem}. Reply again with only the corrected JSON object, in the format asked for."
+        );
+        reply = session.send_message(&retry).await?;
+    }
+    Err(Error::MalformedLlmReply(reply.text))
+}
+
+fn parse_json<T: DeserializeOwned>(text: &str) -> std::result::Result<T, String> {
+    let value = extract_json(text).ok_or("it contains no JSON object")?;
+    serde_json::from_value(value).map_err(|error| error.to_string())
+}
+
+/// Finds the JSON object in a chatty reply: fenced code blocks are tried
+/// first, then every balanced `{…}` in the text, each also after [`repair`].
+pub fn extract_json(text: &str) -> Option<Value> {
+    fenced_blocks(text)
+        .into_iter()
+        .chain(balanced_objects(text))
+        .find_map(|candidate| {
+            serde_json::from_str::<Value>(candidate)
+                .ok()
+                .or_else(|| serde_json::from_str(&repair(candidate)).ok())
+                .filter(Value::is_object)
+        })
+}
+
+/// The contents of the ``` fenced blocks, in order.
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    text.split("```")
+        .skip(1)
+        .step_by(2)
+        .map(|block| {
+            // Drop the info string such as `json`.
+            match block.split_once('\n') {
+                Some((info, body)) if !info.trim_start().starts_with('{') => body,
+                _ => block,
+            }
+        })
+        .collect()
+}
+
+/// Every top level `{…}` with balanced braces, ignoring braces inside strings.
+/// An object still open at the end of the text is returned up to the end.
+fn balanced_objects(text: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' if depth > 0 => in_string = true,
+            '{' => {
+                if depth == 0 {
+                    start = index;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    result.push(&text[start..=index]);
+                }
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        result.push(&text[start..]);
+    }
+    result
+}
+
+/// Fixes the usual slips: typographic quotes, trailing commas and
+/// unclosed strings, arrays or objects at the end of a cut off reply.
+fn repair(candidate: &str) -> String {
+    let candidate = candidate.replace(['“', '”'], "\"");
+    let mut result = String::with_capacity(candidate.len());
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in candidate.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                '\n' => {
+                    result.push_str("\\n");
+                    continue;
+                }
+                _ => {}
+            }
+            result.push(c);
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                let trimmed_len = result.trim_end().len();
+                if result[..trimmed_len].ends_with(',') {
+                    result.truncate(trimmed_len - 1);
+                }
+                closers.pop();
+            }
+            _ => {}
+        }
+        result.push(c);
+    }
+    if in_string {
+        result.push('"');
+    }
+    let trimmed_len = result.trim_end().len();
+    if result[..trimmed_len].ends_with(',') {
+        result.truncate(trimmed_len - 1);
+    }
+    while let Some(closer) = closers.pop() {
+        result.push(closer);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn fenced_json_is_preferred() {
+        let text = "Here you go:\n```json\n{\"word\": \"hund\"}\n```\nAnd {\"word\": \"katt\"}.";
+        assert_eq!(extract_json(text), Some(json!({"word": "hund"})));
+        assert_eq!(fenced_blocks("```{\"a\": 1}```"), ["{\"a\": 1}"]);
+    }
+
+    #[test]
+    fn prose_around_the_json_is_skipped() {
+        let text = "Sure! {\"word\": \"hund\", \"gender\": \"en\"} Hope that helps.";
+        assert_eq!(
+            extract_json(text),
+            Some(json!({"word": "hund", "gender": "en"}))
+        );
+        assert_eq!(extract_json("No JSON here."), None);
+        assert_eq!(extract_json("Only a list: [1, 2]"), None);
+    }
+
+    #[test]
+    fn nested_and_unbalanced_braces() {
+        assert_eq!(
+            balanced_objects("x {a} y {b {c}} z } {d"),
+            ["{a}", "{b {c}}", "{d"]
+        );
+        let nested = "Result: {\"a\": {\"b\": [1, {\"c\": 2}]}} done";
+        assert_eq!(
+            extract_json(nested),
+            Some(json!({"a": {"b": [1, {"c": 2}]}}))
+        );
+        let broken_first = "{not json} then {\"a\": 1}";
+        assert_eq!(extract_json(broken_first), Some(json!({"a": 1})));
+    }
+
+    #[test]
+    fn braces_inside_strings_are_ignored() {
+        let text = r#"{"text": "use } and { and \" here"} tail"#;
+        assert_eq!(
+            balanced_objects(text),
+            [r#"{"text": "use } and { and \" here"}"#]
+        );
+        assert_eq!(
+            extract_json(text),
+            Some(json!({"text": "use } and { and \" here"}))
+        );
+    }
+
+    #[test]
+    fn trailing_commas_are_removed() {
+        assert_eq!(repair(r#"{"a": [1, 2,], }"#), r#"{"a": [1, 2]}"#);
+        assert_eq!(
+            extract_json(r#"{"a": [1, 2,],}"#),
+            Some(json!({"a": [1, 2]}))
+        );
+    }
+
+    #[test]
+    fn repair_closes_a_cut_off_reply() {
+        assert_eq!(repair(r#"{"a": [1, 2,"#), r#"{"a": [1, 2]}"#);
+        assert_eq!(repair(r#"{"a": "unfinished"#), r#"{"a": "unfinished"}"#);
+        assert_eq!(repair("{“a”: 1}"), r#"{"a": 1}"#);
+        assert_eq!(repair("{\"a\": \"two\nlines\"}"), r#"{"a": "two\nlines"}"#);
+        assert_eq!(
+            extract_json("Sure: {\"a\": {\"b\": \"cut"),
+            Some(json!({"a": {"b": "cut"}}))
+        );
+    }
+}
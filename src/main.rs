@@ -1,45 +1,121 @@
-mod azure_tts;
 mod bing_dictionary;
+mod callback;
+mod card;
+mod chart;
+mod dictionary;
 mod duolingo;
+mod error;
+mod group;
+mod history;
+mod llm;
+mod mistakes;
+mod prompt;
+mod pronunciation;
+mod quiz;
+mod reminder;
+mod review;
+mod runner;
+mod stats;
+mod store;
+mod stt;
 mod telegram;
+mod timezone;
+mod tts;
 mod util;
+mod word_cache;
+
+#[cfg(test)]
+mod tests;
 
-use bing_dictionary::Word;
 use bytes::Bytes;
-use edge_gpt::{ChatSession, ConversationStyle, CookieInFile, NewBingResponseMessage};
-use ezio::prelude::*;
+use callback::CallbackData;
+use card::WordCard;
+use dictionary::{DictionarySource, LlmDictionary, OfflineDictionary};
+use duolingo::Vocabulary;
+use error::{Error, Result};
+use history::EventKind;
+use llm::{BackendKind, ChatBackend, ChatReply, Creativity, StoredSession};
+use mistakes::MistakeRecord;
+use prompt::{PromptContext, Prompts, Template};
+use pronunciation::{Attempt, PronunciationAssessor, WordCheck};
+use quiz::{Question, QuestionKind, QuizSession};
 use rand::prelude::*;
-use redis::{aio::Connection, AsyncCommands};
 use regex::Regex;
+use reminder::Reminder;
+use review::Grade;
 use serde::{Deserialize, Serialize};
-use std::env;
+use stats::Stats;
+use std::{cmp::Reverse, env, sync::Arc};
+use store::{bot_state, schema, StateStore};
+use stt::{CommandSTT, SpeechRecognizer};
 use telegram::{
     fix_attributions, fix_bold, fix_unordered_list, simple_respond_message, to_utf16_offset,
 };
 use teloxide::{
-    payloads::SendMessage,
-    types::{Message, MessageEntity, MessageEntityKind, Update, UpdateKind},
+    payloads::{EditMessageReplyMarkup, EditMessageText, SendMessage},
+    types::{
+        BotCommand, CallbackQuery, ChatId, InlineKeyboardMarkup, Message, MessageEntity,
+        MessageEntityKind, Voice,
+    },
 };
-use util::decrypt;
+use timezone::TimeZone;
+use tts::{AzureTTS, CommandTTS, Fallback, SpeechSynthesizer};
+use util::language_name;
+use word_cache::{CachedWord, WordKey};
+
+/// Voice replies to a word card up to this long are scored against the word,
+/// longer ones against the example sentence.
+const WORD_VOICE_MAX_SECS: u32 = 3;
+const MISTAKES_LISTED: usize = 10;
+/// Words each other member adds to a group `/story`, and the most it uses.
+const GROUP_STORY_WORDS_PER_MEMBER: usize = 2;
+const GROUP_STORY_MAX_WORDS: usize = 10;
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum CommandKind {
     Start,
     DuolingoLogin,
     RandomWord,
+    RefreshWord,
+    Forms,
+    CardForms,
     Chat,
     Story,
+    Review,
+    Quiz,
+    Stats,
+    Mistakes,
+    Leaderboard,
+    Remind,
+    Language,
+    Persona,
+    Level,
+    Backend,
     Help,
 }
 
 impl TryFrom<&str> for CommandKind {
-    fn try_from(s: &str) -> Result<Self, Self::Error> {
+    fn try_from(s: &str) -> std::result::Result<Self, Self::Error> {
         match s {
             "start" => Ok(Self::Start),
             "duolingo_login" => Ok(Self::DuolingoLogin),
             "random_word" => Ok(Self::RandomWord),
+            "refresh_word" => Ok(Self::RefreshWord),
+            "forms" => Ok(Self::Forms),
+            "card_forms" => Ok(Self::CardForms),
             "chat" => Ok(Self::Chat),
             "story" => Ok(Self::Story),
+            "review" => Ok(Self::Review),
+            "quiz" => Ok(Self::Quiz),
+            "stats" => Ok(Self::Stats),
+            "mistakes" => Ok(Self::Mistakes),
+            "leaderboard" => Ok(Self::Leaderboard),
+            "remind" => Ok(Self::Remind),
+            "language" => Ok(Self::Language),
+            "persona" => Ok(Self::Persona),
+            "level" => Ok(Self::Level),
+            "backend" => Ok(Self::Backend),
             "help" => Ok(Self::Help),
             _ => Err(()),
         }
@@ -48,286 +124,1427 @@ impl TryFrom<&str> for CommandKind {
     type Error = ();
 }
 
+/// The commands listed in Telegram's menu, with their descriptions.
+const MENU_COMMANDS: [(&str, &str); 19] = [
+    ("random_word", "Show a random word from your vocabulary"),
+    ("review", "Review the words and mistakes that are due"),
+    ("quiz", "Take a multiple choice quiz"),
+    ("chat", "Start a conversation"),
+    ("story", "Tell a story with your recent words"),
+    ("forms", "Show the inflected forms of a word"),
+    ("refresh_word", "Look a word up again"),
+    (
+        "card_forms",
+        "Show inflection tables on word cards, on or off",
+    ),
+    ("stats", "Show your learning history"),
+    ("mistakes", "List your most frequent mistakes"),
+    ("leaderboard", "Show the group quiz leaderboard"),
+    ("remind", "Set up a daily reminder"),
+    ("language", "Pick the course to practise"),
+    ("level", "Set your CEFR level"),
+    ("persona", "Set the conversation partner"),
+    ("backend", "Pick the language model"),
+    ("duolingo_login", "Log in to Duolingo"),
+    ("start", "Say hello"),
+    ("help", "List what I can do"),
+];
+
+pub fn menu_commands() -> Vec<BotCommand> {
+    MENU_COMMANDS
+        .iter()
+        .map(|(command, description)| BotCommand::new(*command, *description))
+        .collect()
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Bot {
     #[serde(default = "telegram::Telegram::from_env", skip_serializing)]
     pub telegram: telegram::Telegram,
-    pub azure_tts: azure_tts::AzureTTS,
+    /// Shared by every user, missing while Azure is off or unreachable.
+    #[serde(skip, default = "tts::azure")]
+    pub azure_tts: Option<Arc<AzureTTS>>,
+    #[serde(default = "CommandTTS::from_env", skip_serializing)]
+    pub command_tts: Option<CommandTTS>,
+    #[serde(default = "CommandSTT::from_env", skip_serializing)]
+    pub command_stt: Option<CommandSTT>,
     pub duolingo: Option<duolingo::Duolingo>,
+    /// Overrides the deployment wide `LLM_BACKEND` for this user.
+    #[serde(default)]
+    pub llm_backend: Option<BackendKind>,
+    /// Replaces the `chat` prompt template for this user.
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// CEFR level the prompts are written for.
+    #[serde(default)]
+    pub level: Option<String>,
+    /// Whether word cards end with the inflection table.
+    #[serde(default)]
+    pub card_forms: bool,
+    #[serde(default)]
+    pub reminder: Option<Reminder>,
+    /// The user this state belongs to, which is the chat in private chats.
+    #[serde(skip)]
+    pub member_id: Option<ChatId>,
 }
 
 impl Bot {
-    pub async fn new(
-        telegram_token: impl ToString,
-        azure_tts_subscription_key: impl ToString,
-    ) -> Self {
+    pub async fn new(telegram_token: impl ToString) -> Self {
         Self {
             telegram: telegram::Telegram::new(telegram_token),
-            azure_tts: azure_tts::AzureTTS::new(azure_tts_subscription_key).await,
+            azure_tts: tts::azure(),
+            command_tts: CommandTTS::from_env(),
+            command_stt: CommandSTT::from_env(),
             duolingo: None,
+            llm_backend: None,
+            persona: None,
+            level: None,
+            card_forms: false,
+            reminder: None,
+            member_id: None,
+        }
+    }
+
+    fn llm_backend_kind(&self) -> BackendKind {
+        self.llm_backend
+            .filter(|it| it.user_selectable())
+            .unwrap_or_else(BackendKind::from_env)
+    }
+
+    fn chat_backend(&self) -> Result<Box<dyn ChatBackend>> {
+        self.llm_backend_kind().backend()
+    }
+
+    fn speech_synthesizer(&self) -> Fallback<'_> {
+        let mut engines: Vec<&dyn SpeechSynthesizer> = Vec::new();
+        if let Some(azure_tts) = self.azure_tts.as_deref().filter(|_| tts::azure_enabled()) {
+            engines.push(azure_tts);
+        }
+        if let Some(command_tts) = &self.command_tts {
+            engines.push(command_tts);
+        }
+        Fallback(engines)
+    }
+
+    fn speech_recognizer(&self) -> stt::Fallback<'_> {
+        let mut engines: Vec<&dyn SpeechRecognizer> = Vec::new();
+        if let Some(azure_tts) = self.azure_tts.as_deref().filter(|_| stt::azure_enabled()) {
+            engines.push(azure_tts);
         }
+        if let Some(command_stt) = &self.command_stt {
+            engines.push(command_stt);
+        }
+        stt::Fallback(engines)
+    }
+
+    fn duolingo(&self) -> Result<&duolingo::Duolingo> {
+        self.duolingo.as_ref().ok_or(Error::DuolingoNotLoggedIn)
+    }
+
+    fn prompt_context(&self) -> Result<PromptContext> {
+        let duolingo = self.duolingo()?;
+        Ok(PromptContext::new(
+            duolingo.learning_language()?,
+            &duolingo.ui_language,
+            self.level.as_deref(),
+        ))
     }
 
-    pub async fn handle(&mut self, message: &Message, redis_connection: &mut Connection) {
+    /// Whose learning progress to use for an update in `chat_id`.
+    fn member(&self, chat_id: ChatId) -> ChatId {
+        self.member_id.unwrap_or(chat_id)
+    }
+
+    /// Whether `vocabulary_id` is in this member's vocabulary.
+    fn owns_word(&self, vocabulary_id: &str) -> bool {
+        self.duolingo
+            .iter()
+            .flat_map(|it| &it.vocabulary)
+            .any(|it| it.id == vocabulary_id)
+    }
+
+    fn duolingo_mut(&mut self) -> Result<&mut duolingo::Duolingo> {
+        self.duolingo.as_mut().ok_or(Error::DuolingoNotLoggedIn)
+    }
+
+    /// Handles a message, replying with a friendly explanation when it fails.
+    pub async fn handle(&mut self, message: &Message, store: &dyn StateStore) {
+        if let Err(error) = self.handle_message(message, store).await {
+            println!(
+                "Failed to handle message in chat {}: {error}",
+                message.chat.id
+            );
+            let respond = simple_respond_message(message, &error.user_message());
+            if let Err(error) = self.telegram.send_message(&respond).await {
+                println!(
+                    "Failed to report error to chat {}: {error}",
+                    message.chat.id
+                );
+            }
+        }
+    }
+
+    async fn handle_message(&mut self, message: &Message, store: &dyn StateStore) -> Result<()> {
         if let Some(text) = message.text() {
             if text.starts_with('/') {
-                let end_of_command_text =
-                    text.chars().position(|it| it == ' ').unwrap_or(text.len());
-                let command_str = &text[1..end_of_command_text];
+                let end_of_command_text = text.find(' ').unwrap_or(text.len());
+                let Some(command_str) = telegram::command_name(&text[1..end_of_command_text])
+                else {
+                    return Ok(());
+                };
                 let params_str = &text[end_of_command_text..];
                 if let Ok(command) = command_str.try_into() {
                     match command {
                         CommandKind::Start => {
                             let respond = simple_respond_message(
                                 message,
-                                "Hello, this is a bot for language learning.\n Try `/help` to see what I can do.",
+                                "Hello, this is a bot for language learning.\n Try /help to see what I can do.",
                             );
-                            self.telegram.send_message(&respond).await;
+                            self.telegram.send_message(&respond).await?;
                         }
                         CommandKind::DuolingoLogin => {
-                            let mut params = params_str.trim().split(' ');
-                            let name = params.next().unwrap();
-                            let jwt = params.next().unwrap();
-                            let duolingo = duolingo::Duolingo::new(name, jwt).await;
+                            let mut params = params_str.split_whitespace();
+                            let (Some(name), Some(jwt)) = (params.next(), params.next()) else {
+                                return Err(Error::Usage("/duolingo_login <name> <jwt>"));
+                            };
+                            let duolingo = duolingo::Duolingo::new(name, jwt).await?;
                             self.duolingo = Some(duolingo);
                         }
                         CommandKind::RandomWord => {
-                            self.random_word(message).await;
+                            self.random_word(message.chat.id, store).await?;
+                        }
+                        CommandKind::RefreshWord => {
+                            self.refresh_word(message, params_str, store).await?;
+                        }
+                        CommandKind::Forms => {
+                            self.forms(message, params_str, store).await?;
+                        }
+                        CommandKind::CardForms => {
+                            self.set_card_forms(message, params_str).await?;
                         }
                         CommandKind::Chat => {
-                            self.start_chat(message, redis_connection).await;
+                            self.start_chat(message, store).await?;
                         }
                         CommandKind::Story => {
-                            self.story(message).await;
+                            self.story(message, store).await?;
+                        }
+                        CommandKind::Review => {
+                            self.review(message, store).await?;
+                        }
+                        CommandKind::Quiz => {
+                            self.quiz(message, params_str, store).await?;
+                        }
+                        CommandKind::Stats => {
+                            self.stats(message, params_str, store).await?;
+                        }
+                        CommandKind::Mistakes => {
+                            self.mistakes(message, store).await?;
+                        }
+                        CommandKind::Leaderboard => {
+                            self.leaderboard(message, store).await?;
+                        }
+                        CommandKind::Remind => {
+                            self.remind(message, params_str, store).await?;
+                        }
+                        CommandKind::Language => {
+                            self.select_language(message, params_str).await?;
+                        }
+                        CommandKind::Persona => {
+                            self.set_persona(message, params_str).await?;
+                        }
+                        CommandKind::Level => {
+                            self.set_level(message, params_str).await?;
                         }
-                        _ => {
-                            unimplemented!()
+                        CommandKind::Backend => {
+                            self.select_backend(message, params_str).await?;
+                        }
+                        CommandKind::Help => {
+                            let text = MENU_COMMANDS
+                                .iter()
+                                .map(|(command, description)| format!("/{command} - {description}"))
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            let respond = simple_respond_message(message, &text);
+                            self.telegram.send_message(&respond).await?;
                         }
                     }
                 }
-            } else if message.reply_to_message().is_some() {
-                self.response_chat(message, redis_connection).await;
+            } else if let Some(reply_to_message) = reply_to_bot(message) {
+                let stored_session = self.chat_session(reply_to_message, store).await?;
+                self.response_chat(message, text, stored_session, store)
+                    .await?;
+            }
+        } else if let (Some(voice), Some(reply_to_message)) =
+            (message.voice(), reply_to_bot(message))
+        {
+            let card = card::load_card(store, message.chat.id, reply_to_message.id).await?;
+            if let Some(card) = card {
+                return self.assess_pronunciation(message, voice, card, store).await;
             }
+            // Only a conversation is worth transcribing for.
+            let stored_session = self.chat_session(reply_to_message, store).await?;
+            let transcript = self.transcribe(message.chat.id, &voice.file.id).await?;
+            let respond = simple_respond_message(message, &format!("🎤 {transcript}"));
+            self.telegram.send_message(&respond).await?;
+            self.response_chat(message, &transcript, stored_session, store)
+                .await?;
         }
+        Ok(())
     }
 
-    async fn random_word(&mut self, message: &Message) {
-        if let Some(duolingo) = &self.duolingo {
-            let vocabulary = {
-                let mut rng = thread_rng();
-                duolingo.vocabulary.choose(&mut rng).unwrap()
-            };
-            let language = duolingo.languages.first().unwrap();
-            let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
-            let word =
-                Word::from_vocabulary(vocabulary, duolingo.ui_language.as_ref(), language).await;
-            let (text, word, sentence) = word
-                .to_telegram_message(&self.azure_tts, language, message.chat.id)
-                .await;
-            status_sender.send(()).unwrap();
-            self.telegram.send_message(&text).await;
-            self.telegram.send_voice(message.chat.id, &word).await;
-            self.telegram.send_voice(message.chat.id, &sentence).await;
-        } else {
-            let respond = simple_respond_message(
-                message,
-                "Please use `/duolingo_login` to login to duolingo.",
+    /// Handles a button press, answering it with a short notification.
+    pub async fn handle_callback_query(&mut self, query: &CallbackQuery, store: &dyn StateStore) {
+        let text = match self.handle_callback(query, store).await {
+            Ok(text) => text,
+            Err(error) => {
+                println!(
+                    "Failed to handle callback query from {}: {error}",
+                    query.from.id
+                );
+                Some(error.user_message())
+            }
+        };
+        if let Err(error) = self.telegram.answer_callback_query(&query.id, text).await {
+            println!(
+                "Failed to answer callback query from {}: {error}",
+                query.from.id
             );
-            self.telegram.send_message(&respond).await;
         }
     }
 
-    async fn chat_respond_from_bing(
+    async fn handle_callback(
+        &mut self,
+        query: &CallbackQuery,
+        store: &dyn StateStore,
+    ) -> Result<Option<String>> {
+        let (Some(data), Some(message)) = (&query.data, &query.message) else {
+            return Ok(None);
+        };
+        let Some(callback_data) = CallbackData::decode(data) else {
+            return Ok(None);
+        };
+        let chat_id = message.chat.id;
+        match callback_data {
+            CallbackData::Review {
+                vocabulary_id,
+                grade,
+            } => {
+                // In groups anyone can press the buttons under a member's card.
+                if !self.owns_word(&vocabulary_id) {
+                    return Ok(Some(
+                        "This card is from someone else's vocabulary.".to_string(),
+                    ));
+                }
+                let member_id = self.member(chat_id);
+                let mut schedule = review::load_schedule(store, member_id).await?;
+                let mut card = schedule.remove(&vocabulary_id).unwrap_or_default();
+                card.review(grade, review::now_ms());
+                review::save_card(store, member_id, &vocabulary_id, &card).await?;
+                let event = EventKind::Reviewed {
+                    vocabulary_id,
+                    grade,
+                };
+                history::record(store, member_id, [event]).await?;
+                let mut edit = EditMessageReplyMarkup::new(chat_id, message.id);
+                edit.reply_markup = Some(callback::word_card_keyboard());
+                self.telegram.edit_message_reply_markup(&edit).await?;
+                let next_review = if card.interval_days < 1.0 {
+                    "in a few minutes".to_string()
+                } else {
+                    format!("in {:.0} days", card.interval_days)
+                };
+                Ok(Some(format!("Next review {next_review}.")))
+            }
+            CallbackData::AnotherExample => {
+                let mut card = card::load_card(store, chat_id, message.id)
+                    .await?
+                    .ok_or(Error::CardExpired)?;
+                let status_sender = self.telegram.start_sending_typing_status(chat_id);
+                let prompts = Prompts::load(store).await?;
+                let context = PromptContext::new(
+                    &card.language,
+                    &self.duolingo()?.ui_language,
+                    self.level.as_deref(),
+                );
+                card.word
+                    .another_example(&prompts, &context, self.chat_backend()?.as_ref())
+                    .await?;
+                let (text, entities) = card.word.to_telegram_text(self.card_forms);
+                let mut edit = EditMessageText::new(chat_id, message.id, text);
+                edit.entities = Some(entities);
+                edit.reply_markup = message.reply_markup().cloned();
+                self.telegram.edit_message_text(&edit).await?;
+                card::save_card(store, chat_id, message.id, &card).await?;
+                let (_, sentence) = card
+                    .word
+                    .voices(&self.speech_synthesizer(), &card.language)
+                    .await;
+                let _ = status_sender.send(());
+                if let Some(sentence) = &sentence {
+                    self.send_voice(chat_id, sentence, store).await?;
+                }
+                Ok(None)
+            }
+            CallbackData::ReplayAudio => {
+                let card = card::load_card(store, chat_id, message.id)
+                    .await?
+                    .ok_or(Error::CardExpired)?;
+                let (word, sentence) = card
+                    .word
+                    .voices(&self.speech_synthesizer(), &card.language)
+                    .await;
+                for voice in [word, sentence].iter().flatten() {
+                    self.send_voice(chat_id, voice, store).await?;
+                }
+                Ok(None)
+            }
+            CallbackData::NextWord => {
+                self.random_word(chat_id, store).await?;
+                Ok(None)
+            }
+            CallbackData::QuizAnswer { question, option } => {
+                let Some(mut session) = quiz::load_session(store, chat_id).await? else {
+                    return Ok(Some("This quiz has ended.".to_string()));
+                };
+                let current = session.current.take().filter(|_| question == session.asked);
+                let Some(current) = current else {
+                    return Ok(Some("This question was already answered.".to_string()));
+                };
+                let correct = option == current.answer;
+                session.asked += 1;
+                if correct {
+                    session.correct += 1;
+                }
+                let member_id = self.member(chat_id);
+                // In groups the word may come from another member's vocabulary.
+                if self.owns_word(&current.vocabulary_id) {
+                    let grade = if correct { Grade::Good } else { Grade::Again };
+                    let mut schedule = review::load_schedule(store, member_id).await?;
+                    let mut card = schedule.remove(&current.vocabulary_id).unwrap_or_default();
+                    card.review(grade, review::now_ms());
+                    review::save_card(store, member_id, &current.vocabulary_id, &card).await?;
+                }
+                let event = EventKind::QuizAnswered {
+                    vocabulary_id: current.vocabulary_id.clone(),
+                    correct,
+                };
+                history::record(store, member_id, [event]).await?;
+                let is_group = !message.chat.is_private();
+                if is_group && correct {
+                    group::add_point(store, chat_id, member_id).await?;
+                }
+                let verdict = if correct {
+                    format!("✅ {}", current.options[current.answer])
+                } else {
+                    format!(
+                        "❌ {}, the answer is {}",
+                        current.options.get(option).map_or("", String::as_str),
+                        current.options[current.answer]
+                    )
+                };
+                let text = format!(
+                    "Question {}/{}\n\n{}\n\n{verdict}",
+                    question + 1,
+                    session.total,
+                    current.prompt,
+                );
+                self.telegram
+                    .edit_message_text(&EditMessageText::new(chat_id, message.id, text))
+                    .await?;
+                if session.asked < session.total {
+                    // A group quiz keeps asking about the words of whoever started it.
+                    let host = match session.host.filter(|it| *it != member_id) {
+                        Some(host_id) => bot_state::read(store, host_id).await?,
+                        None => None,
+                    };
+                    let asker = match host {
+                        Some(mut host) => {
+                            host.telegram = self.telegram.clone();
+                            host
+                        }
+                        None => self.clone(),
+                    };
+                    asker.send_question(chat_id, session, store).await?;
+                } else {
+                    quiz::end_session(store, chat_id).await?;
+                    let mut text = format!(
+                        "Quiz finished, {} of {} correct.",
+                        session.correct, session.total
+                    );
+                    if is_group {
+                        text.push_str("\n\n");
+                        text.push_str(&group::leaderboard_text(store, chat_id).await?);
+                    }
+                    self.telegram
+                        .send_message(&SendMessage::new(chat_id, text))
+                        .await?;
+                }
+                Ok(Some(
+                    if correct { "Correct!" } else { "Not quite." }.to_string(),
+                ))
+            }
+            CallbackData::MistakeReview { id, grade } => {
+                let member_id = self.member(chat_id);
+                let record =
+                    mistakes::review(store, member_id, &id, grade, review::now_ms()).await?;
+                let Some(record) = record else {
+                    return Ok(Some("This mistake is no longer recorded.".to_string()));
+                };
+                self.telegram
+                    .edit_message_reply_markup(&EditMessageReplyMarkup::new(chat_id, message.id))
+                    .await?;
+                let next_review = if record.card.interval_days < 1.0 {
+                    "in a few minutes".to_string()
+                } else {
+                    format!("in {:.0} days", record.card.interval_days)
+                };
+                Ok(Some(format!("Next drill {next_review}.")))
+            }
+            CallbackData::SelectLanguage(language) => {
+                let status_sender = self.telegram.start_sending_typing_status(chat_id);
+                self.duolingo_mut()?.select_course(&language).await?;
+                let _ = status_sender.send(());
+                let text = format!("Now practising {}.", language_name(&language));
+                let edit = EditMessageText::new(chat_id, message.id, text.clone());
+                self.telegram.edit_message_text(&edit).await?;
+                Ok(Some(text))
+            }
+        }
+    }
+
+    async fn random_word(&self, chat_id: ChatId, store: &dyn StateStore) -> Result<()> {
+        let duolingo = self.duolingo()?;
+        let vocabulary = {
+            let mut rng = thread_rng();
+            duolingo
+                .vocabulary
+                .choose(&mut rng)
+                .ok_or(Error::EmptyVocabulary)?
+        };
+        let keyboard = callback::word_card_keyboard();
+        self.send_word_card(chat_id, vocabulary, keyboard, store)
+            .await
+    }
+
+    async fn review(&self, message: &Message, store: &dyn StateStore) -> Result<()> {
+        let duolingo = self.duolingo()?;
+        if duolingo.vocabulary.is_empty() {
+            return Err(Error::EmptyVocabulary);
+        }
+        let now_ms = review::now_ms();
+        let member_id = self.member(message.chat.id);
+        let mistakes = mistakes::load_records(store, member_id).await?;
+        if let Some((id, record)) = mistakes::next_due(&mistakes, now_ms) {
+            return self.send_mistake_drill(message.chat.id, id, record).await;
+        }
+        let schedule = review::load_schedule(store, member_id).await?;
+        match review::next_due(&duolingo.vocabulary, &schedule, now_ms) {
+            Ok(vocabulary) => {
+                let keyboard = callback::review_keyboard(&vocabulary.id);
+                self.send_word_card(message.chat.id, vocabulary, keyboard, store)
+                    .await
+            }
+            Err(next_due_ms) => {
+                let text = match next_due_ms {
+                    Some(due_ms) => format!(
+                        "Nothing to review right now, the next word is due in {} hours.",
+                        (due_ms.saturating_sub(now_ms) as f64 / 3_600_000.0).ceil()
+                    ),
+                    None => "Nothing to review right now.".to_string(),
+                };
+                let respond = simple_respond_message(message, &text);
+                self.telegram.send_message(&respond).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Reports the learning history, `/stats chart` adds a chart of words per day.
+    async fn stats(
         &self,
         message: &Message,
-        mut bing_respond: NewBingResponseMessage,
-    ) -> (SendMessage, Bytes) {
-        let mut entities = Vec::new();
-        fix_unordered_list(&mut bing_respond);
-        fix_attributions(&mut bing_respond, &mut entities);
-        fix_bold(&mut bing_respond, &mut entities);
-        if let Some(duolingo) = &self.duolingo {
-            let language = duolingo.languages.first().unwrap();
-            let translation_hided = hide_translation(&bing_respond, &mut entities);
-            let tts_content = extract_tts_part(&translation_hided);
-            let voice = self
-                .azure_tts
-                .voices
+        params_str: &str,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let member_id = self.member(message.chat.id);
+        let events = history::load(store, member_id).await?;
+        let stats = Stats::from_events(&events, review::now_ms(), &self.time_zone());
+        let text = stats.to_text();
+        if params_str.trim() == "chart" {
+            let png = chart::bar_chart_png(&stats.words_per_day, &stats.days_of_month);
+            self.telegram
+                .send_photo(message.chat.id, png, Some(text))
+                .await?;
+        } else {
+            let respond = simple_respond_message(message, &text);
+            self.telegram.send_message(&respond).await?;
+        }
+        Ok(())
+    }
+
+    async fn mistakes(&self, message: &Message, store: &dyn StateStore) -> Result<()> {
+        let member_id = self.member(message.chat.id);
+        let records = mistakes::load_records(store, member_id).await?;
+        let mut records = records.values().collect::<Vec<_>>();
+        records.sort_by_key(|it| (Reverse(it.count), Reverse(it.last_ms)));
+        let text = if records.is_empty() {
+            "No mistakes recorded yet, keep chatting with /chat.".to_string()
+        } else {
+            let lines = records
                 .iter()
-                .find(|it| it.locale.contains(language))
-                .unwrap()
-                .clone();
-            let tts_result = self.azure_tts.tts_simple(&tts_content, &voice).await;
-            (
-                SendMessage {
-                    chat_id: message.chat.id.into(),
-                    text: bing_respond.text,
-                    entities: Some(entities),
-                    disable_web_page_preview: Some(true),
-                    reply_to_message_id: Some(message.id),
-                    message_thread_id: None,
-                    parse_mode: None,
-                    disable_notification: None,
-                    protect_content: None,
-                    allow_sending_without_reply: None,
-                    reply_markup: None,
-                },
-                tts_result,
+                .take(MISTAKES_LISTED)
+                .map(|it| {
+                    let mistake = &it.mistake;
+                    let what = if mistake.can_drill() {
+                        format!("{} → {}", mistake.wrong, mistake.correct)
+                    } else {
+                        mistake.explanation.clone()
+                    };
+                    format!("• {what} ({}, {}×)", mistake.category.label(), it.count)
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!(
+                "Your most frequent mistakes:\n{lines}\n\nThose made {} times or more come up in /review.",
+                mistakes::DRILL_MIN_COUNT
             )
+        };
+        let respond = simple_respond_message(message, &text);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    async fn leaderboard(&self, message: &Message, store: &dyn StateStore) -> Result<()> {
+        let text = if message.chat.is_private() {
+            "The quiz leaderboard is kept in group chats.".to_string()
         } else {
-            unimplemented!()
+            group::leaderboard_text(store, message.chat.id).await?
+        };
+        let respond = simple_respond_message(message, &text);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    async fn send_mistake_drill(
+        &self,
+        chat_id: ChatId,
+        id: &str,
+        record: &MistakeRecord,
+    ) -> Result<()> {
+        let language = language_name(self.duolingo()?.learning_language()?);
+        let mut text = format!(
+            "Mistake drill, made {} times\n\n{}\n\n",
+            record.count,
+            record.mistake.question(&language)
+        );
+        let answer_offset = text.encode_utf16().count();
+        text.push_str(&record.mistake.correct);
+        let mut send_message = SendMessage::new(chat_id, text);
+        send_message.entities = Some(vec![MessageEntity::spoiler(
+            answer_offset,
+            record.mistake.correct.encode_utf16().count(),
+        )]);
+        send_message.reply_markup = Some(callback::mistake_keyboard(id).into());
+        self.telegram.send_message(&send_message).await?;
+        Ok(())
+    }
+
+    /// `/remind HH:MM [time zone]` sets the daily push, `/remind quiet HH:MM-HH:MM|off`
+    /// its quiet hours, `/remind pause|resume|off` pauses or stops it.
+    async fn remind(
+        &mut self,
+        message: &Message,
+        params_str: &str,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let usage = Error::Usage(
+            "/remind HH:MM [time zone] | quiet HH:MM-HH:MM | quiet off | pause | resume | off",
+        );
+        let mut params = params_str.split_whitespace();
+        match (params.next(), params.next()) {
+            (None, _) => {}
+            (Some("off"), None) => self.reminder = None,
+            (Some("pause"), None) => self.reminder.as_mut().ok_or(usage)?.paused = true,
+            (Some("resume"), None) => self.reminder.as_mut().ok_or(usage)?.paused = false,
+            (Some("quiet"), Some("off")) => self.reminder.as_mut().ok_or(usage)?.quiet_hours = None,
+            (Some("quiet"), Some(range)) => {
+                let quiet_hours = range
+                    .split_once('-')
+                    .and_then(|(start, end)| {
+                        Some((reminder::parse_minute(start)?, reminder::parse_minute(end)?))
+                    })
+                    .ok_or(Error::Usage("/remind quiet HH:MM-HH:MM"))?;
+                self.reminder.as_mut().ok_or(usage)?.quiet_hours = Some(quiet_hours);
+            }
+            (Some(time), time_zone) => {
+                let minute = reminder::parse_minute(time).ok_or(usage)?;
+                let previous = self.reminder.take();
+                let time_zone = time_zone
+                    .map(str::to_string)
+                    .or_else(|| previous.as_ref().map(|it| it.time_zone.clone()))
+                    .unwrap_or_else(|| "UTC".to_string());
+                if TimeZone::load(&time_zone).is_none() {
+                    self.reminder = previous;
+                    return Err(Error::UnknownTimeZone(time_zone));
+                }
+                self.reminder = Some(Reminder {
+                    minute,
+                    time_zone,
+                    quiet_hours: previous.and_then(|it| it.quiet_hours),
+                    paused: false,
+                });
+            }
+        }
+        self.schedule_reminder(self.member(message.chat.id), review::now_ms(), store)
+            .await?;
+        let mut text = match &self.reminder {
+            Some(reminder) => reminder.describe(),
+            None => "No daily reminder, set one with /remind 08:30 Europe/Stockholm.".to_string(),
+        };
+        if self.reminder.is_some() && !message.chat.is_private() {
+            text.push_str(" It is sent to you in a private chat with me.");
         }
+        let respond = simple_respond_message(message, &text);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
     }
 
-    async fn story_respond_from_bing(
+    /// The time zone of the reminder, UTC without one.
+    fn time_zone(&self) -> TimeZone {
+        self.reminder
+            .as_ref()
+            .and_then(|it| TimeZone::load(&it.time_zone))
+            .unwrap_or(TimeZone::UTC)
+    }
+
+    /// Queues the reminder for its next time after `now_ms`, or drops it when off or paused.
+    async fn schedule_reminder(
+        &self,
+        chat_id: ChatId,
+        now_ms: u64,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let due_ms = match &self.reminder {
+            Some(reminder) if !reminder.paused => {
+                let time_zone = TimeZone::load(&reminder.time_zone)
+                    .ok_or_else(|| Error::UnknownTimeZone(reminder.time_zone.clone()))?;
+                Some(reminder.next_due_ms(&time_zone, now_ms))
+            }
+            _ => None,
+        };
+        reminder::schedule(store, chat_id, due_ms).await
+    }
+
+    /// Sends the reminder that came due, unless it is quiet hours, and queues the next one.
+    pub async fn send_reminder(&self, chat_id: ChatId, store: &dyn StateStore) -> Result<()> {
+        let now_ms = review::now_ms();
+        let quiet = match &self.reminder {
+            Some(reminder) => {
+                TimeZone::load(&reminder.time_zone).is_some_and(|it| reminder.is_quiet(&it, now_ms))
+            }
+            None => true,
+        };
+        let sent = if quiet {
+            Ok(())
+        } else {
+            self.send_daily_practice(chat_id, store).await
+        };
+        self.schedule_reminder(chat_id, now_ms, store).await?;
+        sent
+    }
+
+    /// A due mistake drill or review word, or else a word of the day.
+    async fn send_daily_practice(&self, chat_id: ChatId, store: &dyn StateStore) -> Result<()> {
+        let duolingo = self.duolingo()?;
+        let now_ms = review::now_ms();
+        let member_id = self.member(chat_id);
+        let mistakes = mistakes::load_records(store, member_id).await?;
+        if let Some((id, record)) = mistakes::next_due(&mistakes, now_ms) {
+            self.telegram
+                .send_message(&SendMessage::new(
+                    chat_id,
+                    "Time to practise a mistake again.",
+                ))
+                .await?;
+            return self.send_mistake_drill(chat_id, id, record).await;
+        }
+        let schedule = review::load_schedule(store, member_id).await?;
+        if let Ok(vocabulary) = review::next_due(&duolingo.vocabulary, &schedule, now_ms) {
+            if schedule.contains_key(&vocabulary.id) {
+                self.telegram
+                    .send_message(&SendMessage::new(chat_id, "A word is due for review."))
+                    .await?;
+                let keyboard = callback::review_keyboard(&vocabulary.id);
+                return self
+                    .send_word_card(chat_id, vocabulary, keyboard, store)
+                    .await;
+            }
+        }
+        self.telegram
+            .send_message(&SendMessage::new(chat_id, "Your word of the day."))
+            .await?;
+        self.random_word(chat_id, store).await
+    }
+
+    async fn quiz(
+        &self,
+        message: &Message,
+        params_str: &str,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let params_str = params_str.trim();
+        let total = if params_str.is_empty() {
+            quiz::DEFAULT_QUESTIONS
+        } else {
+            params_str
+                .parse::<u32>()
+                .map_err(|_| Error::Usage("/quiz [number of questions]"))?
+                .clamp(1, quiz::MAX_QUESTIONS)
+        };
+        let session = QuizSession {
+            total,
+            host: self.member_id,
+            ..QuizSession::default()
+        };
+        self.send_question(message.chat.id, session, store).await
+    }
+
+    /// Asks the next question of a quiz about a word that was looked up before.
+    async fn send_question(
+        &self,
+        chat_id: ChatId,
+        mut session: QuizSession,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let duolingo = self.duolingo()?;
+        let language = duolingo.learning_language()?;
+        let keys = duolingo
+            .vocabulary
+            .iter()
+            .map(|it| WordKey::new(language, &duolingo.ui_language, &it.word_string))
+            .collect::<Vec<_>>();
+        let words = word_cache::load_words(store, &keys).await?;
+        let known = duolingo
+            .vocabulary
+            .iter()
+            .zip(keys)
+            .zip(words)
+            .filter_map(|((vocabulary, key), word)| Some((vocabulary, key, word?)))
+            .collect::<Vec<_>>();
+        if known.len() < 2 {
+            return Err(Error::NotEnoughWords);
+        }
+        let words = known.iter().map(|(_, _, word)| word).collect::<Vec<_>>();
+        let mut targets = known.iter().collect::<Vec<_>>();
+        targets.shuffle(&mut thread_rng());
+        // A word can't be asked about when the others all share its answers.
+        let mut question = None;
+        for (vocabulary, key, word) in targets {
+            let spell_voice = word_cache::load_spell_voice(store, key).await?;
+            let can_listen = spell_voice.is_some();
+            let generated =
+                Question::generate(&vocabulary.id, word, &words, can_listen, &mut thread_rng());
+            if let Some(generated) = generated {
+                question = Some((generated, spell_voice));
+                break;
+            }
+        }
+        let (question, spell_voice) = question.ok_or(Error::NotEnoughWords)?;
+        if let (QuestionKind::Listening, Some(voice)) = (question.kind, &spell_voice) {
+            self.send_voice(chat_id, voice, store).await?;
+        }
+        let text = format!(
+            "Question {}/{}\n\n{}",
+            session.asked + 1,
+            session.total,
+            question.prompt
+        );
+        let mut send_message = SendMessage::new(chat_id, text);
+        send_message.reply_markup =
+            Some(callback::quiz_keyboard(session.asked, &question.options).into());
+        self.telegram.send_message(&send_message).await?;
+        session.current = Some(question);
+        quiz::save_session(store, chat_id, &session).await
+    }
+
+    async fn send_word_card(
+        &self,
+        chat_id: ChatId,
+        vocabulary: &Vocabulary,
+        keyboard: InlineKeyboardMarkup,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let language = self.duolingo()?.learning_language()?;
+        let status_sender = self.telegram.start_sending_typing_status(chat_id);
+        let CachedWord {
+            word,
+            spell_voice,
+            sentence_voice,
+        } = self.look_up_word(&vocabulary.word_string, store).await?;
+        let mut text = word.to_telegram_message(chat_id, self.card_forms);
+        text.reply_markup = Some(keyboard.into());
+        let _ = status_sender.send(());
+        let sent = self.telegram.send_message(&text).await?;
+        let card = WordCard {
+            vocabulary_id: vocabulary.id.clone(),
+            language: language.to_string(),
+            word,
+        };
+        card::save_card(store, chat_id, sent.id, &card).await?;
+        let event = EventKind::WordShown {
+            vocabulary_id: vocabulary.id.clone(),
+        };
+        history::record(store, self.member(chat_id), [event]).await?;
+        // Voice replies to the audio are scored against the card as well.
+        for voice in [spell_voice, sentence_voice].iter().flatten() {
+            let sent = self.send_voice(chat_id, voice, store).await?;
+            card::save_card(store, chat_id, sent.id, &card).await?;
+        }
+        Ok(())
+    }
+
+    /// Looks a word up in the cache, then the dictionaries, caching the result.
+    async fn look_up_word(&self, spell: &str, store: &dyn StateStore) -> Result<CachedWord> {
+        let duolingo = self.duolingo()?;
+        let language = duolingo.learning_language()?;
+        let key = WordKey::new(language, &duolingo.ui_language, spell);
+        if let Some(cached) = word_cache::load_word(store, &key).await? {
+            return Ok(cached);
+        }
+        let prompts = Prompts::load(store).await?;
+        let context = self.prompt_context()?;
+        let chat_backend = self.chat_backend()?;
+        let offline = OfflineDictionary::from_env(store);
+        let llm = LlmDictionary {
+            prompts: &prompts,
+            context: &context,
+            chat_backend: chat_backend.as_ref(),
+        };
+        let mut sources: Vec<&dyn DictionarySource> = Vec::new();
+        if let Some(offline) = &offline {
+            sources.push(offline);
+        }
+        sources.push(&llm);
+        let word = dictionary::Fallback(sources)
+            .look_up(spell, language, &duolingo.ui_language)
+            .await?
+            .ok_or_else(|| Error::UnknownWord(spell.to_string()))?;
+        let (spell_voice, sentence_voice) = word.voices(&self.speech_synthesizer(), language).await;
+        let cached = CachedWord {
+            word,
+            spell_voice,
+            sentence_voice,
+        };
+        word_cache::save_word(store, &key, &cached).await?;
+        Ok(cached)
+    }
+
+    async fn forms(
         &self,
         message: &Message,
-        bing_respond: NewBingResponseMessage,
-    ) -> (SendMessage, SendMessage, Bytes) {
-        if let Some(duolingo) = &self.duolingo {
-            let language = &duolingo.languages[0];
-            let ui_language = &duolingo.ui_language;
-            let start_position = bing_respond.text.find("\"\"\"").unwrap();
-            let end_position = bing_respond
-                .text
-                .rfind("\"\"\"")
-                .unwrap_or(bing_respond.text.len());
-            let content = bing_respond.text[start_position + 3..end_position].trim();
-            let translate_promote = format!("Translate the given text to {ui_language}. Be faithful or accurate in translation. Make the translation readable or intelligible. Be elegant or natural in translation. If the text cannot be translated, return the original text as is. Do not translate person's name. Do not add any additional text in the translation. The text to be translated is:\n{content}");
-            let voice = self
-                .azure_tts
-                .voices
+        params_str: &str,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let spell = params_str.trim();
+        if spell.is_empty() {
+            return Err(Error::Usage("/forms <word>"));
+        }
+        let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
+        let cached = self.look_up_word(spell, store).await?;
+        let _ = status_sender.send(());
+        let (text, entities) = cached.word.to_forms_text();
+        let mut respond = SendMessage::new(message.chat.id, text);
+        respond.entities = Some(entities);
+        respond.reply_to_message_id = Some(message.id);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    async fn set_card_forms(&mut self, message: &Message, params_str: &str) -> Result<()> {
+        self.card_forms = match params_str.trim() {
+            "on" => true,
+            "off" => false,
+            _ => return Err(Error::Usage("/card_forms on|off")),
+        };
+        let text = if self.card_forms {
+            "Word cards now include the inflection table."
+        } else {
+            "Word cards no longer include the inflection table."
+        };
+        let respond = simple_respond_message(message, text);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    /// Looks up a word again, either the one given or the one on the card replied to.
+    async fn refresh_word(
+        &self,
+        message: &Message,
+        params_str: &str,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let usage = Error::Usage("/refresh_word <word>, or reply to a word card");
+        let duolingo = self.duolingo()?;
+        let spell = params_str.trim();
+        let vocabulary_id = if !spell.is_empty() {
+            duolingo
+                .vocabulary
                 .iter()
-                .find(|it| it.locale.contains(language))
-                .unwrap()
-                .clone();
-            let cookie_str = env::var("EDGE_GPT_COOKIE").unwrap();
-            let cookies: Vec<CookieInFile> = serde_json::from_str(&cookie_str).unwrap();
-            let mut session = ChatSession::create(ConversationStyle::Balanced, &cookies)
-                .await
-                .unwrap();
-            let (translate_response, tts_result) = tokio::join!(
-                session.send_message(&translate_promote),
-                self.azure_tts.tts_simple(content, &voice)
-            );
-            let translate_response = translate_response.unwrap();
-            let length = translate_response.text.as_str().encode_utf16().count();
-            (
-                SendMessage {
-                    chat_id: message.chat.id.into(),
-                    text: content.to_string(),
-                    entities: None,
-                    disable_web_page_preview: Some(true),
-                    reply_to_message_id: Some(message.id),
-                    message_thread_id: None,
-                    parse_mode: None,
-                    disable_notification: None,
-                    protect_content: None,
-                    allow_sending_without_reply: None,
-                    reply_markup: None,
-                },
-                SendMessage {
-                    chat_id: message.chat.id.into(),
-                    text: translate_response.text,
-                    entities: Some(vec![MessageEntity {
-                        kind: MessageEntityKind::Spoiler,
-                        offset: 0,
-                        length,
-                    }]),
-                    disable_web_page_preview: Some(true),
-                    reply_to_message_id: Some(message.id),
-                    message_thread_id: None,
-                    parse_mode: None,
-                    disable_notification: None,
-                    protect_content: None,
-                    allow_sending_without_reply: None,
-                    reply_markup: None,
-                },
-                tts_result,
-            )
+                .find(|it| it.word_string.eq_ignore_ascii_case(spell))
+                .map(|it| it.id.clone())
+        } else if let Some(reply_to_message) = message.reply_to_message() {
+            card::load_card(store, message.chat.id, reply_to_message.id)
+                .await?
+                .map(|it| it.vocabulary_id)
         } else {
-            unimplemented!()
+            None
+        };
+        let vocabulary = vocabulary_id
+            .and_then(|id| duolingo.vocabulary.iter().find(|it| it.id == id))
+            .ok_or(usage)?;
+        let key = WordKey::new(
+            duolingo.learning_language()?,
+            &duolingo.ui_language,
+            &vocabulary.word_string,
+        );
+        word_cache::forget_word(store, &key).await?;
+        self.send_word_card(
+            message.chat.id,
+            vocabulary,
+            callback::word_card_keyboard(),
+            store,
+        )
+        .await
+    }
+
+    async fn select_language(&mut self, message: &Message, params_str: &str) -> Result<()> {
+        let language = params_str.trim();
+        if !language.is_empty() {
+            let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
+            self.duolingo_mut()?.select_course(language).await?;
+            let _ = status_sender.send(());
+            let text = format!("Now practising {}.", language_name(language));
+            let respond = simple_respond_message(message, &text);
+            self.telegram.send_message(&respond).await?;
+            return Ok(());
         }
+        let duolingo = self.duolingo()?;
+        let active_language = duolingo.learning_language()?;
+        let courses = duolingo
+            .languages
+            .iter()
+            .map(|it| {
+                let marker = if it == active_language {
+                    " (active)"
+                } else {
+                    ""
+                };
+                format!("• {} ({it}){marker}", language_name(it))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let text = format!("Your courses:\n{courses}\nPick the one to practise.");
+        let mut respond = simple_respond_message(message, &text);
+        respond.reply_markup = Some(callback::language_keyboard(&duolingo.languages).into());
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    async fn set_persona(&mut self, message: &Message, params_str: &str) -> Result<()> {
+        let persona = params_str.trim();
+        let text = if persona.is_empty() {
+            self.persona = None;
+            "Back to the default conversation partner."
+        } else {
+            self.persona = Some(persona.to_string());
+            "Your next /chat will use this persona. Placeholders like {target_language}, {ui_language}, {level} and {correction_format} are filled in."
+        };
+        let respond = simple_respond_message(message, text);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    async fn set_level(&mut self, message: &Message, params_str: &str) -> Result<()> {
+        let level = params_str.trim().to_uppercase();
+        if !["A1", "A2", "B1", "B2", "C1", "C2"].contains(&level.as_str()) {
+            return Err(Error::Usage("/level A1|A2|B1|B2|C1|C2"));
+        }
+        let text = format!("Prompts are now written for {level} level.");
+        self.level = Some(level);
+        let respond = simple_respond_message(message, &text);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    async fn select_backend(&mut self, message: &Message, params_str: &str) -> Result<()> {
+        let name = params_str.trim();
+        let text = if name.is_empty() {
+            self.llm_backend = None;
+            format!("Using the default backend ({:?}).", BackendKind::from_env())
+        } else if let Some(kind) = BackendKind::try_from(name)
+            .ok()
+            .filter(|it| it.user_selectable())
+        {
+            self.llm_backend = Some(kind);
+            format!("Using the {kind:?} backend.")
+        } else {
+            "Unknown backend, available ones are bing and openai.".to_string()
+        };
+        let respond = simple_respond_message(message, &text);
+        self.telegram.send_message(&respond).await?;
+        Ok(())
+    }
+
+    async fn chat_respond_from_bing(
+        &self,
+        message: &Message,
+        mut bing_respond: ChatReply,
+    ) -> Result<(SendMessage, Option<Bytes>)> {
+        let mut entities = Vec::new();
+        fix_unordered_list(&mut bing_respond);
+        fix_attributions(&mut bing_respond, &mut entities);
+        fix_bold(&mut bing_respond, &mut entities);
+        let language = self.duolingo()?.learning_language()?;
+        let translation_hided = hide_translation(&bing_respond, &mut entities);
+        let tts_content = extract_tts_part(&translation_hided);
+        let tts_result = self
+            .speech_synthesizer()
+            .synthesize(&tts_content, language)
+            .await;
+        Ok((
+            SendMessage {
+                chat_id: message.chat.id.into(),
+                text: bing_respond.text,
+                entities: Some(entities),
+                disable_web_page_preview: Some(true),
+                reply_to_message_id: Some(message.id),
+                message_thread_id: None,
+                parse_mode: None,
+                disable_notification: None,
+                protect_content: None,
+                allow_sending_without_reply: None,
+                reply_markup: None,
+            },
+            tts_result,
+        ))
     }
 
-    async fn start_chat(&self, message: &Message, redis_connection: &mut Connection) {
-        let cookie_str = env::var("EDGE_GPT_COOKIE").unwrap();
-        let cookies: Vec<CookieInFile> = serde_json::from_str(&cookie_str).unwrap();
+    async fn story_respond_from_bing(
+        &self,
+        message: &Message,
+        bing_respond: ChatReply,
+        prompts: &Prompts,
+    ) -> Result<(SendMessage, SendMessage, Option<Bytes>)> {
+        let language = self.duolingo()?.learning_language()?;
+        let start_position = bing_respond
+            .text
+            .find("\"\"\"")
+            .ok_or_else(|| Error::MalformedLlmReply(bing_respond.text.clone()))?;
+        let end_position = bing_respond
+            .text
+            .rfind("\"\"\"")
+            .filter(|it| *it > start_position)
+            .unwrap_or(bing_respond.text.len());
+        let content = bing_respond.text[start_position + 3..end_position].trim();
+        let translate_promote = prompts.render(
+            Template::Translate,
+            &self.prompt_context()?,
+            &[("text", content)],
+        );
+        let speech_synthesizer = self.speech_synthesizer();
+        let chat_backend = self.chat_backend()?;
+        let mut session = chat_backend.create_session(Creativity::Balanced).await?;
+        let (translate_response, tts_result) = tokio::join!(
+            session.send_message(&translate_promote),
+            speech_synthesizer.synthesize(content, language)
+        );
+        let translate_response = translate_response?;
+        let length = translate_response.text.as_str().encode_utf16().count();
+        Ok((
+            SendMessage {
+                chat_id: message.chat.id.into(),
+                text: content.to_string(),
+                entities: None,
+                disable_web_page_preview: Some(true),
+                reply_to_message_id: Some(message.id),
+                message_thread_id: None,
+                parse_mode: None,
+                disable_notification: None,
+                protect_content: None,
+                allow_sending_without_reply: None,
+                reply_markup: None,
+            },
+            SendMessage {
+                chat_id: message.chat.id.into(),
+                text: translate_response.text,
+                entities: Some(vec![MessageEntity {
+                    kind: MessageEntityKind::Spoiler,
+                    offset: 0,
+                    length,
+                }]),
+                disable_web_page_preview: Some(true),
+                reply_to_message_id: Some(message.id),
+                message_thread_id: None,
+                parse_mode: None,
+                disable_notification: None,
+                protect_content: None,
+                allow_sending_without_reply: None,
+                reply_markup: None,
+            },
+            tts_result,
+        ))
+    }
+
+    async fn start_chat(&self, message: &Message, store: &dyn StateStore) -> Result<()> {
+        let backend_kind = self.llm_backend_kind();
+        let prompts = Prompts::load(store).await?;
+        let context = self.prompt_context()?;
+        let promote = match &self.persona {
+            Some(persona) => prompts.render_str(persona, &context, &[]),
+            None => prompts.render(Template::Chat, &context, &[]),
+        };
         let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
-        let mut session = ChatSession::create(ConversationStyle::Creative, &cookies)
-            .await
-            .unwrap();
-        let response = session
-            .send_message(include_str!("../chat_promote.txt"))
-            .await
-            .unwrap();
-        let (send_message, tts_result) = self.chat_respond_from_bing(message, response).await;
-        status_sender.send(()).unwrap();
-        let send_message_response = self.telegram.send_message(&send_message).await;
-        self.telegram.send_voice(message.chat.id, &tts_result).await;
-        let key = format!("{}-{}", message.chat.id, send_message_response.id);
-        let session_str = serde_json::to_string(&session).unwrap();
-        let _: () = redis_connection
-            .set_ex(key, session_str, 60 * 60)
+        let mut session = backend_kind
+            .backend()?
+            .create_session(Creativity::Creative)
+            .await?;
+        let response = session.send_message(&promote).await?;
+        let (send_message, tts_result) = self.chat_respond_from_bing(message, response).await?;
+        let _ = status_sender.send(());
+        let send_message_response = self.telegram.send_message(&send_message).await?;
+        if let Some(tts_result) = &tts_result {
+            self.send_voice(message.chat.id, tts_result, store).await?;
+        }
+        let key = schema::chat_session_key(message.chat.id, send_message_response.id);
+        let session_str =
+            serde_json::to_string(&StoredSession::new(backend_kind, session.as_ref()))?;
+        store
+            .set_string(&key, &session_str, Some(schema::CHAT_SESSION_TTL_SECS))
             .await
-            .unwrap();
     }
 
-    async fn response_chat(&self, message: &Message, redis_connection: &mut Connection) {
-        let reply_to_message = message.reply_to_message().unwrap();
-        let key = format!("{}-{}", message.chat.id, reply_to_message.id);
-        let corresponding_session: String = redis_connection.get(key).await.unwrap();
-        let mut session: ChatSession = serde_json::from_str(&corresponding_session).unwrap();
+    /// Sends audio as a voice note, reusing the Telegram `file_id` when the
+    /// same audio was uploaded before.
+    async fn send_voice(
+        &self,
+        chat_id: ChatId,
+        voice: &Bytes,
+        store: &dyn StateStore,
+    ) -> Result<Message> {
+        let key = schema::voice_file_key(&tts::audio_digest(voice));
+        let file_id = store.get_string(&key).await?;
+        if let Some(file_id) = file_id {
+            match self.telegram.send_voice_by_file_id(chat_id, &file_id).await {
+                Ok(sent) => return Ok(sent),
+                Err(error) => println!("Failed to resend voice {file_id}: {error}"),
+            }
+        }
+        let sent = self.telegram.send_voice(chat_id, voice).await?;
+        if let Some(sent_voice) = sent.voice() {
+            store
+                .set_string(&key, &sent_voice.file.id, Some(schema::VOICE_FILE_TTL_SECS))
+                .await?;
+        }
+        Ok(sent)
+    }
+
+    async fn download_voice(&self, file_id: &str) -> Result<Bytes> {
+        let file = self.telegram.get_file(file_id).await?;
+        self.telegram.download_file(&file).await
+    }
+
+    /// Scores a voice reply to a word card and keeps it in the word's history.
+    /// Without an engine that scores pronunciation it only reports which
+    /// words were recognized, and keeps nothing.
+    async fn assess_pronunciation(
+        &self,
+        message: &Message,
+        voice: &Voice,
+        card: WordCard,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let reference_text =
+            if voice.duration <= WORD_VOICE_MAX_SECS || card.word.example_sentence.is_empty() {
+                &card.word.spell
+            } else {
+                &card.word.example_sentence
+            };
+        let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
+        let recording = self.download_voice(&voice.file.id).await?;
+        let mut engines: Vec<&dyn PronunciationAssessor> = Vec::new();
+        if let Some(azure_tts) = self.azure_tts.as_deref().filter(|_| stt::azure_enabled()) {
+            engines.push(azure_tts);
+        }
+        let assessment = pronunciation::Fallback(engines)
+            .assess(&recording, reference_text, &card.language)
+            .await;
+        let Some(assessment) = assessment else {
+            let recognizer = self.speech_recognizer();
+            let check =
+                WordCheck::run(&recognizer, &recording, reference_text, &card.language).await;
+            let _ = status_sender.send(());
+            let check = check.ok_or(Error::SpeechNotRecognized)?;
+            let respond = simple_respond_message(message, &check.to_telegram_text(reference_text));
+            self.telegram.send_message(&respond).await?;
+            return Ok(());
+        };
+        let _ = status_sender.send(());
+        let text = assessment.to_telegram_text(reference_text, &card.word.pronunciation);
+        let respond = simple_respond_message(message, &text);
+        self.telegram.send_message(&respond).await?;
+        pronunciation::save_attempt(
+            store,
+            self.member(message.chat.id),
+            &card.vocabulary_id,
+            Attempt {
+                at_ms: review::now_ms(),
+                reference_text: reference_text.clone(),
+                assessment,
+            },
+        )
+        .await
+    }
+
+    async fn transcribe(&self, chat_id: ChatId, file_id: &str) -> Result<String> {
+        let language = self.duolingo()?.learning_language()?;
+        let status_sender = self.telegram.start_sending_typing_status(chat_id);
+        let voice = self.download_voice(file_id).await?;
+        let transcript = self.speech_recognizer().transcribe(&voice, language).await;
+        let _ = status_sender.send(());
+        transcript.ok_or(Error::SpeechNotRecognized)
+    }
+
+    /// The conversation continued by replying to `reply_to_message`.
+    async fn chat_session(
+        &self,
+        reply_to_message: &Message,
+        store: &dyn StateStore,
+    ) -> Result<StoredSession> {
+        let key = schema::chat_session_key(reply_to_message.chat.id, reply_to_message.id);
+        let corresponding_session = store.get_string(&key).await?;
+        let corresponding_session = corresponding_session.ok_or(Error::ChatSessionExpired)?;
+        Ok(serde_json::from_str(&corresponding_session)?)
+    }
+
+    async fn response_chat(
+        &self,
+        message: &Message,
+        text: &str,
+        stored_session: StoredSession,
+        store: &dyn StateStore,
+    ) -> Result<()> {
+        let mut session = stored_session.restore()?;
         let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
-        let response = session.send_message(message.text().unwrap()).await.unwrap();
-        let (send_message, tts_result) = self.chat_respond_from_bing(message, response).await;
-        status_sender.send(()).unwrap();
-        let send_message_response = self.telegram.send_message(&send_message).await;
-        self.telegram.send_voice(message.chat.id, &tts_result).await;
-        let key = format!("{}-{}", message.chat.id, send_message_response.id);
-        let session_str = serde_json::to_string(&session).unwrap();
-        let _: () = redis_connection
-            .set_ex(key, session_str, 60 * 60)
+        let response = session.send_message(text).await?;
+        let (send_message, tts_result) = self.chat_respond_from_bing(message, response).await?;
+        let _ = status_sender.send(());
+        let send_message_response = self.telegram.send_message(&send_message).await?;
+        let member_id = self.member(message.chat.id);
+        let mistakes = mistakes::parse(&send_message.text);
+        mistakes::record(store, member_id, &mistakes, review::now_ms()).await?;
+        let mistake_events = mistakes.iter().map(|it| EventKind::MistakeCorrected {
+            category: it.category,
+        });
+        let events = [EventKind::ChatTurn].into_iter().chain(mistake_events);
+        history::record(store, member_id, events).await?;
+        if let Some(tts_result) = &tts_result {
+            self.send_voice(message.chat.id, tts_result, store).await?;
+        }
+        let key = schema::chat_session_key(message.chat.id, send_message_response.id);
+        let session_str = serde_json::to_string(&StoredSession::new(
+            stored_session.backend,
+            session.as_ref(),
+        ))?;
+        store
+            .set_string(&key, &session_str, Some(schema::CHAT_SESSION_TTL_SECS))
             .await
-            .unwrap();
     }
 
-    async fn story(&self, message: &Message) {
-        if let Some(duolingo) = &self.duolingo {
-            let language = duolingo.languages.first().unwrap();
-            let words = &duolingo.vocabulary[duolingo.vocabulary.len() - 5..]
-                .iter()
-                .map(|it| it.word_string.clone())
-                .collect::<Vec<_>>()
-                .join(",");
-            let promote = format!("Please write a short story in {language} which is less than 200 words, the story should use simple words and these special words must be included: {words}. Wrap the story content in two '\"\"\"'s");
-            let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
-            let cookie_str = env::var("EDGE_GPT_COOKIE").unwrap();
-            let cookies: Vec<CookieInFile> = serde_json::from_str(&cookie_str).unwrap();
-            let mut session = ChatSession::create(ConversationStyle::Creative, &cookies)
-                .await
-                .unwrap();
-            let response = session.send_message(&promote).await.unwrap();
-            let (send_message, send_translation, tts_result) =
-                self.story_respond_from_bing(message, response).await;
-            status_sender.send(()).unwrap();
-            self.telegram.send_message(&send_message).await;
-            self.telegram.send_message(&send_translation).await;
-            self.telegram.send_voice(message.chat.id, &tts_result).await;
-        } else {
-            let respond = simple_respond_message(
-                message,
-                "Please use `/duolingo_login` to login to duolingo.",
+    async fn story(&self, message: &Message, store: &dyn StateStore) -> Result<()> {
+        let duolingo = self.duolingo()?;
+        if duolingo.vocabulary.is_empty() {
+            return Err(Error::EmptyVocabulary);
+        }
+        let mut words = duolingo.vocabulary[duolingo.vocabulary.len().saturating_sub(5)..]
+            .iter()
+            .map(|it| it.word_string.clone())
+            .collect::<Vec<_>>();
+        if !message.chat.is_private() {
+            words.extend(self.group_words(message.chat.id, store).await?);
+        }
+        let words = &words.join(",");
+        let prompts = Prompts::load(store).await?;
+        let promote = prompts.render(
+            Template::Story,
+            &self.prompt_context()?,
+            &[("words", words)],
+        );
+        let status_sender = self.telegram.start_sending_typing_status(message.chat.id);
+        let mut session = self
+            .chat_backend()?
+            .create_session(Creativity::Creative)
+            .await?;
+        let response = session.send_message(&promote).await?;
+        let (send_message, send_translation, tts_result) = self
+            .story_respond_from_bing(message, response, &prompts)
+            .await?;
+        let _ = status_sender.send(());
+        self.telegram.send_message(&send_message).await?;
+        self.telegram.send_message(&send_translation).await?;
+        history::record(
+            store,
+            self.member(message.chat.id),
+            [EventKind::StoryGenerated],
+        )
+        .await?;
+        if let Some(tts_result) = &tts_result {
+            self.send_voice(message.chat.id, tts_result, store).await?;
+        }
+        Ok(())
+    }
+
+    /// Recent words of the other group members learning the same language, for a group story.
+    async fn group_words(&self, chat_id: ChatId, store: &dyn StateStore) -> Result<Vec<String>> {
+        let language = self.duolingo()?.learning_language()?;
+        let mut words = Vec::new();
+        for (member_id, _) in group::members(store, chat_id).await? {
+            if member_id == self.member(chat_id) {
+                continue;
+            }
+            let Some(member) = bot_state::read(store, member_id).await? else {
+                continue;
+            };
+            let Some(duolingo) = &member.duolingo else {
+                continue;
+            };
+            if duolingo.learning_language().ok() != Some(language) {
+                continue;
+            }
+            let recent = duolingo
+                .vocabulary
+                .len()
+                .saturating_sub(GROUP_STORY_WORDS_PER_MEMBER);
+            words.extend(
+                duolingo.vocabulary[recent..]
+                    .iter()
+                    .map(|it| it.word_string.clone()),
             );
-            self.telegram.send_message(&respond).await;
         }
+        words.shuffle(&mut thread_rng());
+        words.truncate(GROUP_STORY_MAX_WORDS);
+        Ok(words)
     }
 }
 
-pub fn hide_translation(
-    bing_respond: &NewBingResponseMessage,
-    entries: &mut Vec<MessageEntity>,
-) -> String {
+/// The message replied to, if the reply is meant for the bot: any reply in a
+/// private chat, but only replies to the bot's own messages in groups.
+fn reply_to_bot(message: &Message) -> Option<&Message> {
+    let reply_to_message = message.reply_to_message()?;
+    let from_bot = reply_to_message.from().is_some_and(|it| it.is_bot);
+    (message.chat.is_private() || from_bot).then_some(reply_to_message)
+}
+
+pub fn hide_translation(bing_respond: &ChatReply, entries: &mut Vec<MessageEntity>) -> String {
     let origin_text = bing_respond.text.clone();
     let re = Regex::new(r"\(([^)]+)\)").unwrap();
     for m in re.find_iter(&origin_text) {
@@ -347,7 +1564,7 @@ pub fn extract_tts_part(bing_respond: &str) -> String {
     if let Some(mistake_start) = mistake_start {
         let mut rest = &bing_respond[mistake_start..];
         while let Some(next) = rest.find('•') {
-            let line_end = rest[next..].find('\n').unwrap();
+            let line_end = rest[next..].find('\n').unwrap_or(rest.len() - next);
             rest = &rest[next + line_end..];
         }
         rest.trim().to_string()
@@ -358,44 +1575,17 @@ pub fn extract_tts_part(bing_respond: &str) -> String {
 
 #[tokio::main]
 async fn main() {
-    let secret_str = env::var("SECRET").unwrap();
-    let redis_url = env::var("REDIS_URL").unwrap();
-    let telegram_token = env::var("TELEGRAM_TOKEN").unwrap();
-    let azure_tts_subscription_key = env::var("AZURE_TTS_SUBSCRIPTION_KEY").unwrap();
-
-    let redis_client = redis::Client::open(redis_url).unwrap();
-    let secret = hex::decode(secret_str).unwrap();
-    let request_encrypted = file::read("./request.json.encrypted");
-    let request_str = decrypt(&hex::decode(request_encrypted).unwrap(), &secret);
-    let request: Update = serde_json::from_str(&request_str).unwrap();
-    if let Some(chat) = request.chat() {
-        let chat_id = &chat.id;
-        if chat_id.is_user() {
-            let mut redis_connection = redis_client.get_async_connection().await.unwrap();
-            let mut bot = if let Ok(bot_str) = redis_connection
-                .get::<_, String>(format!("{chat_id}"))
-                .await
-            {
-                if let Ok(bot) = serde_json::from_str(&bot_str) {
-                    bot
-                } else {
-                    Bot::new(telegram_token, azure_tts_subscription_key).await
-                }
-            } else {
-                Bot::new(telegram_token, azure_tts_subscription_key).await
-            };
-            if let UpdateKind::Message(message) = &request.kind {
-                bot.handle(message, &mut redis_connection).await;
-            }
-            let bot_json = serde_json::to_string(&bot).unwrap();
-            let _: () = redis_connection
-                .set_ex(format!("{chat_id}"), bot_json, 60 * 60 * 24 * 30)
-                .await
-                .unwrap();
-        } else {
-            panic!("Not a user")
+    match env::args().nth(1).as_deref() {
+        None | Some("once") => runner::once().await,
+        Some("serve") => runner::serve().await,
+        Some("webhook") => runner::webhook().await,
+        Some("import") => dictionary::import().await,
+        Some("remind") => runner::remind().await,
+        Some("state") => bot_state::command().await,
+        Some(other) => {
+            panic!(
+                "Unknown subcommand: {other}, expected one of once, serve, webhook, import, remind, state"
+            )
         }
-    } else {
-        panic!("Not a chat")
-    };
+    }
 }
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use teloxide::types::ChatId;
+
+use crate::{
+    error::Result,
+    review::{Grade, ReviewCard},
+    store::{schema, StateStore},
+};
+
+/// Mistakes made at least this often are drilled in `/review`.
+pub const DRILL_MIN_COUNT: u32 = 2;
+
+/// The kinds of mistakes the correction format distinguishes.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// A word of the interface language was used.
+    ForeignWord,
+    Spelling,
+    Inflection,
+    Other,
+}
+
+impl Category {
+    pub fn label(self) -> &'static str {
+        match self {
+            Category::ForeignWord => "untranslated words",
+            Category::Spelling => "spelling",
+            Category::Inflection => "inflection",
+            Category::Other => "other",
+        }
+    }
+}
+
+/// One correction from a chat reply.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Mistake {
+    pub category: Category,
+    /// What was said, or for spelling mistakes the meaning of the word.
+    pub wrong: String,
+    pub correct: String,
+    /// The bullet as the model wrote it.
+    pub explanation: String,
+}
+
+fn clean(part: &str) -> String {
+    part.trim()
+        .trim_end_matches(['.', '!'])
+        .trim_matches(|c: char| "\"'“”‘’<>«»".contains(c))
+        .trim()
+        .to_string()
+}
+
+impl Mistake {
+    /// Recognizes the sentence patterns the `correction_format` prompt asks for.
+    fn parse(bullet: &str) -> Self {
+        let explanation = bullet.to_string();
+        let other = || Mistake {
+            category: Category::Other,
+            wrong: String::new(),
+            correct: String::new(),
+            explanation: explanation.clone(),
+        };
+        let (category, wrong, correct) = if let Some(rest) = bullet
+            .strip_prefix("The correct spell for ")
+            .or_else(|| bullet.strip_prefix("The correct spelling for "))
+        {
+            let Some((meaning, word)) = rest.split_once(" is ") else {
+                return other();
+            };
+            (Category::Spelling, meaning, word)
+        } else if let Some((_, rest)) = bullet
+            .strip_prefix("The ")
+            .and_then(|it| it.split_once(" word for "))
+        {
+            let Some((foreign, word)) = rest.split_once(" is ") else {
+                return other();
+            };
+            (Category::ForeignWord, foreign, word)
+        } else if let Some((wrong, rest)) = bullet.split_once(" should be ") {
+            let correct = rest.rsplit_once(" in ").map_or(rest, |(it, _)| it);
+            (Category::Inflection, wrong, correct)
+        } else {
+            return other();
+        };
+        Mistake {
+            category,
+            wrong: clean(wrong),
+            correct: clean(correct),
+            explanation,
+        }
+    }
+
+    /// The same mistake gets the same id however often it is made.
+    pub fn id(&self) -> String {
+        let identity = match self.category {
+            Category::Other => self.explanation.to_lowercase(),
+            _ => format!("{}:{}", self.wrong, self.correct).to_lowercase(),
+        };
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{:?}:{identity}", self.category));
+        hex::encode(&hasher.finalize()[..6])
+    }
+
+    /// Whether there is a correct form to ask for.
+    pub fn can_drill(&self) -> bool {
+        self.category != Category::Other && !self.wrong.is_empty() && !self.correct.is_empty()
+    }
+
+    /// The drill question, the answer is `correct`.
+    pub fn question(&self, language_name: &str) -> String {
+        match self.category {
+            Category::ForeignWord => {
+                format!("How do you say \"{}\" in {language_name}?", self.wrong)
+            }
+            Category::Spelling => format!("Spell the word for \"{}\".", self.wrong),
+            Category::Inflection => format!("\"{}\" should be…?", self.wrong),
+            Category::Other => self.explanation.clone(),
+        }
+    }
+}
+
+/// The `•` bullets listed after "Mistakes you made:" in a chat reply.
+fn bullets(reply: &str) -> Vec<&str> {
+    let Some(start) = reply.find("Mistakes you made:") else {
+        return Vec::new();
+    };
+    reply[start..]
+        .lines()
+        .skip(1)
+        .map(str::trim)
+        .skip_while(|it| it.is_empty())
+        .take_while(|it| it.starts_with('•'))
+        .map(|it| it.trim_start_matches('•').trim())
+        .collect()
+}
+
+pub fn parse(reply: &str) -> Vec<Mistake> {
+    bullets(reply).into_iter().map(Mistake::parse).collect()
+}
+
+/// A mistake as remembered for a chat, with its own review schedule.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MistakeRecord {
+    pub mistake: Mistake,
+    pub count: u32,
+    pub last_ms: u64,
+    pub card: ReviewCard,
+}
+
+pub async fn load_records(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+) -> Result<HashMap<String, MistakeRecord>> {
+    let raw = store.hash_get_all(&schema::mistakes_key(chat_id)).await?;
+    Ok(raw
+        .into_iter()
+        .filter_map(|(id, record)| Some((id, serde_json::from_str(&record).ok()?)))
+        .collect())
+}
+
+async fn save_record(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    id: &str,
+    record: &MistakeRecord,
+) -> Result<()> {
+    let fields = [(id.to_string(), serde_json::to_string(record)?)];
+    store
+        .hash_set(&schema::mistakes_key(chat_id), &fields)
+        .await
+}
+
+/// Counts the mistakes, making them due for a drill again.
+pub async fn record(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    mistakes: &[Mistake],
+    now_ms: u64,
+) -> Result<()> {
+    for mistake in mistakes {
+        let id = mistake.id();
+        let existing = store.hash_get(&schema::mistakes_key(chat_id), &id).await?;
+        let mut record = existing
+            .and_then(|it| serde_json::from_str(&it).ok())
+            .unwrap_or_else(|| MistakeRecord {
+                mistake: mistake.clone(),
+                count: 0,
+                last_ms: now_ms,
+                card: ReviewCard::default(),
+            });
+        record.count += 1;
+        record.last_ms = now_ms;
+        record.card.review(Grade::Again, now_ms);
+        save_record(store, chat_id, &id, &record).await?;
+    }
+    Ok(())
+}
+
+/// Grades a drill of the mistake `id`, returning the updated record.
+pub async fn review(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    id: &str,
+    grade: Grade,
+    now_ms: u64,
+) -> Result<Option<MistakeRecord>> {
+    let existing = store.hash_get(&schema::mistakes_key(chat_id), id).await?;
+    let Some(mut record) = existing.and_then(|it| serde_json::from_str::<MistakeRecord>(&it).ok())
+    else {
+        return Ok(None);
+    };
+    record.card.review(grade, now_ms);
+    save_record(store, chat_id, id, &record).await?;
+    Ok(Some(record))
+}
+
+/// The most overdue repeated mistake that can be drilled.
+pub fn next_due(
+    records: &HashMap<String, MistakeRecord>,
+    now_ms: u64,
+) -> Option<(&String, &MistakeRecord)> {
+    records
+        .iter()
+        .filter(|(_, it)| it.count >= DRILL_MIN_COUNT && it.mistake.can_drill())
+        .filter(|(_, it)| it.card.due_ms <= now_ms)
+        .min_by_key(|(_, it)| it.card.due_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPLY: &str = "You mean \"Jag har en hund som heter Max\".
+
+Mistakes you made:
+
+• The Swedish word for \"dog\" is \"hund\".
+• The correct spell for name is heter.
+• \"hade\" should be \"har\" in verb.
+• hundar should be hund in noun.
+• You don't need \"the\" here.
+
+Vad kul! Hur gammal är Max?
+
+(How fun! How old is Max?)";
+
+    fn mistake(category: Category, wrong: &str, correct: &str, explanation: &str) -> Mistake {
+        Mistake {
+            category,
+            wrong: wrong.to_string(),
+            correct: correct.to_string(),
+            explanation: explanation.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_the_correction_format() {
+        assert_eq!(
+            parse(REPLY),
+            [
+                mistake(
+                    Category::ForeignWord,
+                    "dog",
+                    "hund",
+                    "The Swedish word for \"dog\" is \"hund\"."
+                ),
+                mistake(
+                    Category::Spelling,
+                    "name",
+                    "heter",
+                    "The correct spell for name is heter."
+                ),
+                mistake(
+                    Category::Inflection,
+                    "hade",
+                    "har",
+                    "\"hade\" should be \"har\" in verb."
+                ),
+                mistake(
+                    Category::Inflection,
+                    "hundar",
+                    "hund",
+                    "hundar should be hund in noun."
+                ),
+                mistake(Category::Other, "", "", "You don't need \"the\" here."),
+            ]
+        );
+        assert!(parse(REPLY).iter().take(4).all(Mistake::can_drill));
+        assert!(!parse(REPLY)[4].can_drill());
+    }
+
+    #[test]
+    fn replies_off_the_template_are_kept_as_explanations() {
+        let reply =
+            "Mistakes you made:\n• The correct spelling for cat\n• The German word for it\n";
+        let mistakes = parse(reply);
+        assert_eq!(mistakes.len(), 2);
+        assert!(mistakes.iter().all(|it| it.category == Category::Other));
+        assert_eq!(mistakes[0].explanation, "The correct spelling for cat");
+        // Without the heading or bullets nothing counts as a mistake.
+        assert!(parse("You mean \"Jag är glad\".\n\nBra jobbat!").is_empty());
+        assert!(parse("Mistakes you made:\n- hundar should be hund in noun.").is_empty());
+    }
+
+    #[test]
+    fn the_same_mistake_has_the_same_id() {
+        let first = &parse("Mistakes you made:\n• Hundar should be hund in noun.")[0];
+        let again = &parse("Mistakes you made:\n• hundar should be \"Hund\" in noun!")[0];
+        assert_eq!(first.id(), again.id());
+        let other = &parse("Mistakes you made:\n• katter should be katt in noun.")[0];
+        assert_ne!(first.id(), other.id());
+    }
+}
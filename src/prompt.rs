@@ -0,0 +1,189 @@
+use std::{collections::HashMap, env};
+
+use crate::{
+    error::Result,
+    store::{schema, StateStore},
+    util::language_name,
+};
+
+pub const DEFAULT_LEVEL: &str = "A2";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Template {
+    Chat,
+    CorrectionFormat,
+    Story,
+    Translate,
+    Dictionary,
+    AnotherExample,
+}
+
+impl Template {
+    pub const ALL: [Template; 6] = [
+        Template::Chat,
+        Template::CorrectionFormat,
+        Template::Story,
+        Template::Translate,
+        Template::Dictionary,
+        Template::AnotherExample,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Template::Chat => "chat",
+            Template::CorrectionFormat => "correction_format",
+            Template::Story => "story",
+            Template::Translate => "translate",
+            Template::Dictionary => "dictionary",
+            Template::AnotherExample => "another_example",
+        }
+    }
+
+    fn builtin(self) -> &'static str {
+        match self {
+            Template::Chat => include_str!("../templates/chat.txt"),
+            Template::CorrectionFormat => include_str!("../templates/correction_format.txt"),
+            Template::Story => include_str!("../templates/story.txt"),
+            Template::Translate => include_str!("../templates/translate.txt"),
+            Template::Dictionary => include_str!("../templates/dictionary.txt"),
+            Template::AnotherExample => include_str!("../templates/another_example.txt"),
+        }
+    }
+}
+
+/// Who the prompt is rendered for.
+#[derive(Clone, Debug)]
+pub struct PromptContext {
+    pub target_language: String,
+    pub ui_language: String,
+    pub level: String,
+}
+
+impl PromptContext {
+    /// Builds the context from ISO 639-1 codes.
+    pub fn new(target_language: &str, ui_language: &str, level: Option<&str>) -> Self {
+        Self {
+            target_language: language_name(target_language),
+            ui_language: language_name(ui_language),
+            level: level.unwrap_or(DEFAULT_LEVEL).to_string(),
+        }
+    }
+}
+
+/// The prompt templates in effect.
+///
+/// Each template is looked up in the state store under `prompt-template-{name}`
+/// first, then in `{PROMPT_TEMPLATE_DIR}/{name}.txt`, then falls back to the
+/// built-in one.
+/// Placeholders are written as `{name}`, unknown ones are left untouched.
+#[derive(Clone, Debug, Default)]
+pub struct Prompts {
+    overrides: HashMap<Template, String>,
+}
+
+impl Prompts {
+    pub async fn load(store: &dyn StateStore) -> Result<Self> {
+        let keys = Template::ALL
+            .iter()
+            .map(|it| schema::prompt_template_key(it.name()))
+            .collect::<Vec<_>>();
+        let from_store = store.get_many(&keys).await?;
+        let template_dir = env::var("PROMPT_TEMPLATE_DIR").ok();
+        let mut overrides = HashMap::new();
+        for (template, from_store) in Template::ALL.into_iter().zip(from_store) {
+            let content = match (from_store, &template_dir) {
+                (Some(content), _) => Some(String::from_utf8_lossy(&content).into_owned()),
+                (None, Some(dir)) => {
+                    let path = format!("{dir}/{}.txt", template.name());
+                    tokio::fs::read_to_string(path).await.ok()
+                }
+                (None, None) => None,
+            };
+            if let Some(content) = content {
+                overrides.insert(template, content);
+            }
+        }
+        Ok(Self { overrides })
+    }
+
+    pub fn template(&self, template: Template) -> &str {
+        self.overrides
+            .get(&template)
+            .map(String::as_str)
+            .unwrap_or_else(|| template.builtin())
+    }
+
+    pub fn render(
+        &self,
+        template: Template,
+        context: &PromptContext,
+        extra: &[(&str, &str)],
+    ) -> String {
+        self.render_str(self.template(template), context, extra)
+    }
+
+    /// Renders a template given as a string, such as a `/persona`.
+    pub fn render_str(
+        &self,
+        template: &str,
+        context: &PromptContext,
+        extra: &[(&str, &str)],
+    ) -> String {
+        let correction_format = substitute(
+            self.template(Template::CorrectionFormat),
+            context,
+            &[("correction_format", "")],
+        );
+        let mut extra = extra.to_vec();
+        extra.push(("correction_format", &correction_format));
+        substitute(template, context, &extra)
+    }
+}
+
+/// Replaces every known `{name}` in one pass, so placeholders inside the
+/// values are left as they are.
+fn substitute(template: &str, context: &PromptContext, extra: &[(&str, &str)]) -> String {
+    let value_of = |name: &str| match name {
+        "target_language" => Some(context.target_language.as_str()),
+        "ui_language" => Some(context.ui_language.as_str()),
+        "level" => Some(context.level.as_str()),
+        _ => extra
+            .iter()
+            .find(|(it, _)| *it == name)
+            .map(|(_, value)| *value),
+    };
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template.trim_end();
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let placeholder = rest
+            .find('}')
+            .and_then(|end| Some((value_of(&rest[..end])?, end)));
+        match placeholder {
+            Some((value, end)) => {
+                result.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => result.push('{'),
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_not_substituted_again() {
+        let context = PromptContext::new("sv", "en", None);
+        let template = "{text} in {target_language} at {level}, {unknown} {{text}";
+        let rendered = substitute(template, &context, &[("text", "Say {level}")]);
+        assert_eq!(
+            rendered,
+            "Say {level} in Swedish at A2, {unknown} {Say {level}"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use serde::Deserialize;
+
+use super::{Assessment, PhonemeScore, PronunciationAssessor, WordScore};
+use crate::{
+    tts::{stt_url, AzureTTS},
+    util::new_reqwest_client,
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Recognition {
+    recognition_status: String,
+    #[serde(default, rename = "NBest")]
+    n_best: Vec<Best>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Best {
+    accuracy_score: f64,
+    fluency_score: f64,
+    completeness_score: f64,
+    pron_score: f64,
+    #[serde(default)]
+    words: Vec<ScoredWord>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ScoredWord {
+    word: String,
+    #[serde(default)]
+    accuracy_score: f64,
+    #[serde(default)]
+    error_type: String,
+    #[serde(default)]
+    phonemes: Vec<ScoredPhoneme>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ScoredPhoneme {
+    phoneme: String,
+    #[serde(default)]
+    accuracy_score: f64,
+}
+
+/// Uses the pronunciation assessment of the Azure speech-to-text REST API.
+#[async_trait]
+impl PronunciationAssessor for AzureTTS {
+    async fn assess(
+        &self,
+        voice: &Bytes,
+        reference_text: &str,
+        language: &str,
+    ) -> Option<Assessment> {
+        let locale = self.voice_for(language)?.locale.clone();
+        let parameters = serde_json::json!({
+            "ReferenceText": reference_text,
+            "GradingSystem": "HundredMark",
+            "Granularity": "Phoneme",
+            "Dimension": "Comprehensive",
+        });
+        let response = new_reqwest_client()
+            .post(stt_url(&self.region))
+            .query(&[("language", locale.as_str()), ("format", "detailed")])
+            .header("Ocp-Apim-Subscription-Key", &self.subscription_key)
+            .header("Content-Type", "audio/ogg; codecs=opus")
+            .header(
+                "Pronunciation-Assessment",
+                STANDARD.encode(parameters.to_string()),
+            )
+            .body(voice.clone())
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_success() {
+            println!("{:?}", &response);
+            return None;
+        }
+        let recognition: Recognition = response.json().await.ok()?;
+        if recognition.recognition_status != "Success" {
+            println!("Azure pronunciation assessment: {recognition:?}");
+            return None;
+        }
+        let best = recognition.n_best.into_iter().next()?;
+        Some(Assessment {
+            accuracy: best.accuracy_score,
+            fluency: best.fluency_score,
+            completeness: best.completeness_score,
+            pronunciation: best.pron_score,
+            words: best
+                .words
+                .into_iter()
+                .map(|word| WordScore {
+                    word: word.word,
+                    accuracy: word.accuracy_score,
+                    error_type: word.error_type,
+                    phonemes: word
+                        .phonemes
+                        .into_iter()
+                        .map(|phoneme| PhonemeScore {
+                            phoneme: phoneme.phoneme,
+                            accuracy: phoneme.accuracy_score,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,149 @@
+mod azure;
+mod transcript;
+
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+use crate::{
+    error::Result,
+    store::{schema, StateStore},
+};
+
+pub use transcript::WordCheck;
+
+/// Attempts kept per word in the history.
+const HISTORY_LENGTH: usize = 20;
+/// Phonemes scoring below this are reported as errors.
+const PHONEME_ERROR_THRESHOLD: f64 = 60.0;
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct PhonemeScore {
+    pub phoneme: String,
+    pub accuracy: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct WordScore {
+    pub word: String,
+    pub accuracy: f64,
+    /// `None`, `Omission`, `Insertion` or `Mispronunciation`.
+    pub error_type: String,
+    #[serde(default)]
+    pub phonemes: Vec<PhonemeScore>,
+}
+
+/// Scores between 0 and 100.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct Assessment {
+    pub accuracy: f64,
+    pub fluency: f64,
+    pub completeness: f64,
+    pub pronunciation: f64,
+    pub words: Vec<WordScore>,
+}
+
+impl Assessment {
+    /// The report sent back to the user, `expected_ipa` is shown next to
+    /// the phonemes that were pronounced badly.
+    pub fn to_telegram_text(&self, reference_text: &str, expected_ipa: &str) -> String {
+        let mut result = format!(
+            "🗣 {reference_text}\nPronunciation: {:.0}\nAccuracy: {:.0}\nFluency: {:.0}\nCompleteness: {:.0}",
+            self.pronunciation, self.accuracy, self.fluency, self.completeness
+        );
+        let mut errors = String::new();
+        for word in &self.words {
+            let bad_phonemes = word
+                .phonemes
+                .iter()
+                .filter(|it| it.accuracy < PHONEME_ERROR_THRESHOLD)
+                .map(|it| format!("{} ({:.0})", it.phoneme, it.accuracy))
+                .collect::<Vec<_>>();
+            if word.error_type != "None" {
+                let _ = writeln!(errors, "{}: {}", word.word, word.error_type);
+            } else if !bad_phonemes.is_empty() {
+                let _ = writeln!(errors, "{}: {}", word.word, bad_phonemes.join(", "));
+            }
+        }
+        if !errors.is_empty() {
+            let _ = write!(
+                result,
+                "\n\nExpected: {expected_ipa}\n{}",
+                errors.trim_end()
+            );
+        }
+        result
+    }
+}
+
+#[async_trait]
+pub trait PronunciationAssessor: Send + Sync {
+    /// Scores an OGG/Opus voice note of `reference_text` spoken in `language`
+    /// (ISO 639-1 code), or returns `None` when this engine can't do it right now.
+    async fn assess(
+        &self,
+        voice: &Bytes,
+        reference_text: &str,
+        language: &str,
+    ) -> Option<Assessment>;
+}
+
+/// Asks each engine in turn until one of them produces a score.
+pub struct Fallback<'a>(pub Vec<&'a dyn PronunciationAssessor>);
+
+#[async_trait]
+impl PronunciationAssessor for Fallback<'_> {
+    async fn assess(
+        &self,
+        voice: &Bytes,
+        reference_text: &str,
+        language: &str,
+    ) -> Option<Assessment> {
+        for engine in &self.0 {
+            if let Some(assessment) = engine.assess(voice, reference_text, language).await {
+                return Some(assessment);
+            }
+        }
+        None
+    }
+}
+
+/// One scored attempt at saying a word or its example sentence.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Attempt {
+    pub at_ms: u64,
+    pub reference_text: String,
+    pub assessment: Assessment,
+}
+
+pub async fn load_history(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    vocabulary_id: &str,
+) -> Result<Vec<Attempt>> {
+    let raw = store
+        .hash_get(&schema::pronunciation_key(chat_id), vocabulary_id)
+        .await?;
+    Ok(raw
+        .and_then(|it| serde_json::from_str(&it).ok())
+        .unwrap_or_default())
+}
+
+pub async fn save_attempt(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    vocabulary_id: &str,
+    attempt: Attempt,
+) -> Result<()> {
+    let mut history = load_history(store, chat_id, vocabulary_id).await?;
+    history.push(attempt);
+    let skip = history.len().saturating_sub(HISTORY_LENGTH);
+    let history = history.into_iter().skip(skip).collect::<Vec<_>>();
+    let fields = [(vocabulary_id.to_string(), serde_json::to_string(&history)?)];
+    store
+        .hash_set(&schema::pronunciation_key(chat_id), &fields)
+        .await
+}
@@ -0,0 +1,82 @@
+use std::fmt::Write;
+
+use bytes::Bytes;
+
+use crate::stt::SpeechRecognizer;
+
+/// Which words of the reference text a speech recognizer heard, the reply
+/// when no engine can score the pronunciation. It says nothing about how
+/// well the words were said, so it is never kept as an assessment.
+pub struct WordCheck {
+    pub recognized: usize,
+    pub missed: Vec<String>,
+}
+
+fn normalize(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+impl WordCheck {
+    /// Compares the words of `reference_text` with a transcript of `voice`,
+    /// or returns `None` when nothing was recognized.
+    pub async fn run(
+        recognizer: &dyn SpeechRecognizer,
+        voice: &Bytes,
+        reference_text: &str,
+        language: &str,
+    ) -> Option<Self> {
+        let heard = normalize(&recognizer.transcribe(voice, language).await?);
+        Self::compare(&heard, reference_text)
+    }
+
+    fn compare(heard: &[String], reference_text: &str) -> Option<Self> {
+        let expected = normalize(reference_text);
+        if expected.is_empty() {
+            return None;
+        }
+        let (found, missed): (Vec<_>, Vec<_>) =
+            expected.into_iter().partition(|word| heard.contains(word));
+        Some(Self {
+            recognized: found.len(),
+            missed,
+        })
+    }
+
+    pub fn to_telegram_text(&self, reference_text: &str) -> String {
+        let total = self.recognized + self.missed.len();
+        let mut result = format!(
+            "🗣 {reference_text}\nWords recognized: {}/{total}",
+            self.recognized
+        );
+        if !self.missed.is_empty() {
+            let _ = write!(result, "\nNot recognized: {}", self.missed.join(", "));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_the_words_heard_without_scores() {
+        let heard = normalize("Hunden, springer!");
+        let check = WordCheck::compare(&heard, "Hunden springer snabbt").unwrap();
+        assert_eq!(check.recognized, 2);
+        assert_eq!(check.missed, ["snabbt"]);
+        let text = check.to_telegram_text("Hunden springer snabbt");
+        assert!(text.contains("Words recognized: 2/3"), "{text}");
+        assert!(text.contains("Not recognized: snabbt"), "{text}");
+        assert!(!text.contains("Accuracy") && !text.contains("Pronunciation"));
+        assert!(WordCheck::compare(&heard, "...").is_none());
+    }
+}
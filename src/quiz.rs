@@ -0,0 +1,253 @@
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+use crate::{
+    bing_dictionary::Word,
+    error::Result,
+    store::{schema, StateStore},
+};
+
+pub const DEFAULT_QUESTIONS: u32 = 5;
+pub const MAX_QUESTIONS: u32 = 20;
+const MAX_OPTIONS: usize = 4;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionKind {
+    /// Pick the meaning of the word.
+    Meaning,
+    /// Pick the word for the meaning.
+    Reverse,
+    /// Pick the word missing from its example sentence.
+    Cloze,
+    /// Pick the word that was only heard.
+    Listening,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Question {
+    pub kind: QuestionKind,
+    pub vocabulary_id: String,
+    pub prompt: String,
+    pub options: Vec<String>,
+    pub answer: usize,
+}
+
+impl Question {
+    /// Builds a question about `target` with the other `words` as distractors,
+    /// or `None` when they leave no wrong option to pick.
+    /// Listening questions are only asked when `can_listen`.
+    pub fn generate(
+        vocabulary_id: &str,
+        target: &Word,
+        words: &[&Word],
+        can_listen: bool,
+        rng: &mut impl Rng,
+    ) -> Option<Self> {
+        let mut kinds = vec![QuestionKind::Meaning, QuestionKind::Reverse];
+        let cloze = cloze(target);
+        if cloze.is_some() {
+            kinds.push(QuestionKind::Cloze);
+        }
+        if can_listen {
+            kinds.push(QuestionKind::Listening);
+        }
+        kinds.shuffle(rng);
+        // Another kind may still work when all the meanings or spellings match.
+        let (kind, mut options) = kinds.into_iter().find_map(|kind| {
+            let options = distractors(kind, target, words);
+            (!options.is_empty()).then_some((kind, options))
+        })?;
+        options.shuffle(rng);
+        options.truncate(MAX_OPTIONS - 1);
+        let correct = answer_of(kind, target).to_string();
+        options.push(correct.clone());
+        options.shuffle(rng);
+        let prompt = match kind {
+            QuestionKind::Meaning => format!("What does \"{}\" mean?", target.spell),
+            QuestionKind::Reverse => format!("Which word means \"{}\"?", target.meaning),
+            QuestionKind::Cloze => format!("Fill in the gap:\n{}", cloze.unwrap_or_default()),
+            QuestionKind::Listening => "Which word did you hear?".to_string(),
+        };
+        Some(Self {
+            kind,
+            vocabulary_id: vocabulary_id.to_string(),
+            prompt,
+            answer: options.iter().position(|it| *it == correct).unwrap(),
+            options,
+        })
+    }
+}
+
+fn answer_of(kind: QuestionKind, word: &Word) -> &str {
+    match kind {
+        QuestionKind::Meaning => &word.meaning,
+        _ => &word.spell,
+    }
+}
+
+/// The answers of `words` that differ from the one of `target` and from each
+/// other, ignoring case and surrounding spaces.
+fn distractors(kind: QuestionKind, target: &Word, words: &[&Word]) -> Vec<String> {
+    let normalize = |it: &str| it.trim().to_lowercase();
+    let correct = normalize(answer_of(kind, target));
+    let mut options = words
+        .iter()
+        .map(|it| answer_of(kind, it).trim())
+        .filter(|it| !it.is_empty() && normalize(it) != correct)
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    options.sort_by_key(|it| normalize(it));
+    options.dedup_by_key(|it| normalize(it));
+    options
+}
+
+/// The example sentence with the word, or one of its forms, blanked out.
+fn cloze(word: &Word) -> Option<String> {
+    let sentence = &word.example_sentence;
+    let forms = word.inflections.iter().map(|it| it.word.as_str());
+    let (start, end) = [word.spell.as_str()]
+        .into_iter()
+        .chain(forms)
+        .filter(|it| !it.is_empty())
+        .filter_map(|form| find_word(sentence, form))
+        .max_by_key(|(start, end)| end - start)?;
+    Some(format!("{}____{}", &sentence[..start], &sentence[end..]))
+}
+
+/// The byte range of the first whole-word occurrence of `word` in `text`,
+/// ignoring case.
+fn find_word(text: &str, word: &str) -> Option<(usize, usize)> {
+    let is_word_char = |it: char| it.is_alphanumeric();
+    text.char_indices().find_map(|(start, _)| {
+        if text[..start].chars().next_back().is_some_and(is_word_char) {
+            return None;
+        }
+        let mut rest = text[start..].chars();
+        let matches = word.chars().all(|it| {
+            rest.next()
+                .is_some_and(|other| other.to_lowercase().eq(it.to_lowercase()))
+        });
+        let end = text.len() - rest.as_str().len();
+        (matches && !rest.next().is_some_and(is_word_char)).then_some((start, end))
+    })
+}
+
+/// A running `/quiz`.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct QuizSession {
+    pub total: u32,
+    pub asked: u32,
+    pub correct: u32,
+    pub current: Option<Question>,
+    /// Who started the quiz, its questions are about their words.
+    #[serde(default)]
+    pub host: Option<ChatId>,
+}
+
+pub async fn load_session(store: &dyn StateStore, chat_id: ChatId) -> Result<Option<QuizSession>> {
+    let session = store.get_string(&schema::quiz_session_key(chat_id)).await?;
+    Ok(session.and_then(|it| serde_json::from_str(&it).ok()))
+}
+
+pub async fn save_session(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    session: &QuizSession,
+) -> Result<()> {
+    store
+        .set_string(
+            &schema::quiz_session_key(chat_id),
+            &serde_json::to_string(session)?,
+            Some(schema::QUIZ_SESSION_TTL_SECS),
+        )
+        .await
+}
+
+pub async fn end_session(store: &dyn StateStore, chat_id: ChatId) -> Result<()> {
+    store.delete(&[schema::quiz_session_key(chat_id)]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::bing_dictionary::Inflection;
+
+    fn word(spell: &str, meaning: &str) -> Word {
+        Word {
+            spell: spell.to_string(),
+            meaning: meaning.to_string(),
+            ..Word::default()
+        }
+    }
+
+    fn with_example(spell: &str, sentence: &str, forms: &[&str]) -> Word {
+        Word {
+            example_sentence: sentence.to_string(),
+            inflections: forms
+                .iter()
+                .map(|it| Inflection {
+                    form: String::new(),
+                    word: it.to_string(),
+                })
+                .collect(),
+            ..word(spell, "")
+        }
+    }
+
+    #[test]
+    fn distractors_are_distinct() {
+        let target = word("hund", "dog");
+        let words = [
+            word("vovve", "Dog "),
+            word("katt", "cat"),
+            word("kisse", "Cat"),
+            word("mus", "mouse"),
+        ];
+        let words = words.iter().collect::<Vec<_>>();
+        let meanings = distractors(QuestionKind::Meaning, &target, &words);
+        assert_eq!(meanings, ["cat", "mouse"]);
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let question = Question::generate("v", &target, &words, false, &mut rng).unwrap();
+            let mut options = question.options.clone();
+            options.sort_by_key(|it| it.to_lowercase());
+            options.dedup_by_key(|it| it.to_lowercase());
+            assert_eq!(options.len(), question.options.len());
+            let expected = match question.kind {
+                QuestionKind::Meaning => 3,
+                _ => MAX_OPTIONS,
+            };
+            assert_eq!(question.options.len(), expected);
+        }
+    }
+
+    #[test]
+    fn words_without_wrong_options_are_skipped() {
+        let target = word("hund", "dog");
+        let same = word("Hund", " DOG");
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(Question::generate("v", &target, &[&target, &same], true, &mut rng).is_none());
+        // Only the meaning is shared, so the word is asked for by its meaning.
+        let synonym = word("vovve", "dog");
+        let question = Question::generate("v", &target, &[&target, &synonym], false, &mut rng);
+        let question = question.unwrap();
+        assert_eq!(question.kind, QuestionKind::Reverse);
+        assert_eq!(question.options.len(), 2);
+        assert_eq!(question.options[question.answer], "hund");
+    }
+
+    #[test]
+    fn cloze_blanks_whole_words() {
+        let hund = with_example("hund", "Hundarna och hunden såg en hund.", &["hunden"]);
+        assert_eq!(cloze(&hund).unwrap(), "Hundarna och ____ såg en hund.");
+        let hund = with_example("hund", "Hundarna sov.", &[]);
+        assert_eq!(cloze(&hund), None);
+        // Lowercasing "İ" takes a byte more, the gap still lands on the word.
+        let stad = with_example("stad", "İstanbul är en STAD.", &[]);
+        assert_eq!(cloze(&stad).unwrap(), "İstanbul är en ____.");
+    }
+}
@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+use crate::{
+    error::Result,
+    store::{schema, StateStore},
+    timezone::TimeZone,
+};
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+const DAY_MINUTES: u32 = 24 * 60;
+
+/// A daily push at a local time.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Reminder {
+    /// Minutes after local midnight.
+    pub minute: u32,
+    pub time_zone: String,
+    /// Local minutes from which, and until which, nothing is pushed.
+    #[serde(default)]
+    pub quiet_hours: Option<(u32, u32)>,
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl Reminder {
+    fn in_quiet_hours(&self, minute: u32) -> bool {
+        match self.quiet_hours {
+            Some((start, end)) if start <= end => start <= minute && minute < end,
+            Some((start, end)) => minute >= start || minute < end,
+            None => false,
+        }
+    }
+
+    /// Whether a push at `now_ms` would fall into the quiet hours.
+    pub fn is_quiet(&self, time_zone: &TimeZone, now_ms: u64) -> bool {
+        let local = time_zone.to_local(now_ms as i64 / 1000);
+        self.in_quiet_hours((local.rem_euclid(DAY_SECS) / 60) as u32)
+    }
+
+    /// When the reminder is due next after `after_ms`, pushed back to the end
+    /// of the quiet hours when it falls into them.
+    pub fn next_due_ms(&self, time_zone: &TimeZone, after_ms: u64) -> u64 {
+        let after = after_ms as i64 / 1000;
+        let today = time_zone.to_local(after).div_euclid(DAY_SECS);
+        let minute = match self.quiet_hours {
+            Some((_, end)) if self.in_quiet_hours(self.minute) => end,
+            _ => self.minute,
+        };
+        // The end of quiet hours spanning midnight belongs to the next day.
+        let day_shift = i64::from(minute < self.minute);
+        (today - 1..=today + 1)
+            .map(|day| time_zone.to_utc((day + day_shift) * DAY_SECS + i64::from(minute) * 60))
+            .find(|it| *it > after)
+            .unwrap_or(after + DAY_SECS) as u64
+            * 1000
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "Daily reminder at {} ({})",
+            format_minute(self.minute),
+            self.time_zone
+        );
+        if let Some((start, end)) = self.quiet_hours {
+            text.push_str(&format!(
+                ", quiet from {} to {}",
+                format_minute(start),
+                format_minute(end)
+            ));
+        }
+        if self.paused {
+            text.push_str(", paused");
+        }
+        text.push('.');
+        text
+    }
+}
+
+/// Parses `HH:MM` into minutes after midnight.
+pub fn parse_minute(s: &str) -> Option<u32> {
+    let (hours, minutes) = s.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+pub fn format_minute(minute: u32) -> String {
+    let minute = minute % DAY_MINUTES;
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+/// Schedules the reminder of `chat_id` at `due_ms`, or stops it when `None`.
+pub async fn schedule(store: &dyn StateStore, chat_id: ChatId, due_ms: Option<u64>) -> Result<()> {
+    let member = chat_id.to_string();
+    match due_ms {
+        Some(due_ms) => {
+            store
+                .sorted_set_add(schema::REMINDERS_KEY, &member, due_ms as f64)
+                .await
+        }
+        None => {
+            store
+                .sorted_set_remove(schema::REMINDERS_KEY, &member)
+                .await
+        }
+    }
+}
+
+/// The chats whose reminder is due at `now_ms`.
+pub async fn due_chats(store: &dyn StateStore, now_ms: u64) -> Result<Vec<ChatId>> {
+    let chat_ids = store
+        .sorted_set_up_to(schema::REMINDERS_KEY, now_ms as f64)
+        .await?;
+    Ok(chat_ids
+        .into_iter()
+        .filter_map(|it| Some(ChatId(it.parse().ok()?)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stockholm() -> TimeZone {
+        TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap()
+    }
+
+    fn reminder(minute: u32, quiet_hours: Option<(u32, u32)>) -> Reminder {
+        Reminder {
+            minute,
+            time_zone: "Europe/Stockholm".to_string(),
+            quiet_hours,
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn next_due_keeps_the_local_time_across_dst() {
+        let reminder = reminder(8 * 60 + 30, None);
+        // From 2024-03-30 09:00 CET to 2024-03-31 08:30 CEST.
+        assert_eq!(
+            reminder.next_due_ms(&stockholm(), 1_711_785_600_000),
+            1_711_866_600_000
+        );
+        // From 2024-10-26 09:00 CEST to 2024-10-27 08:30 CET.
+        assert_eq!(
+            reminder.next_due_ms(&stockholm(), 1_729_926_000_000),
+            1_730_014_200_000
+        );
+    }
+
+    #[test]
+    fn next_due_waits_for_the_end_of_quiet_hours() {
+        // 08:30 falls into 22:00 to 09:00, so 2024-03-31 09:00 CEST.
+        let morning = reminder(8 * 60 + 30, Some((22 * 60, 9 * 60)));
+        assert_eq!(
+            morning.next_due_ms(&stockholm(), 1_711_785_600_000),
+            1_711_868_400_000
+        );
+        // 23:00 falls into 22:00 to 07:00, which ends the next morning,
+        // here 2024-10-27 07:00 CET.
+        let late = reminder(23 * 60, Some((22 * 60, 7 * 60)));
+        assert_eq!(
+            late.next_due_ms(&stockholm(), 1_729_926_000_000),
+            1_730_008_800_000
+        );
+        assert!(late.is_quiet(&stockholm(), 1_730_008_800_000 - 1));
+        assert!(!late.is_quiet(&stockholm(), 1_730_008_800_000));
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+use crate::{
+    duolingo::Vocabulary,
+    error::Result,
+    store::{schema, StateStore},
+};
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+const RELEARN_DELAY_MS: u64 = 10 * 60 * 1000;
+const MIN_EASE: f64 = 1.3;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Grade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl TryFrom<&str> for Grade {
+    fn try_from(s: &str) -> std::result::Result<Self, Self::Error> {
+        match s {
+            "again" => Ok(Self::Again),
+            "hard" => Ok(Self::Hard),
+            "good" => Ok(Self::Good),
+            "easy" => Ok(Self::Easy),
+            _ => Err(()),
+        }
+    }
+
+    type Error = ();
+}
+
+impl Grade {
+    pub const ALL: [Grade; 4] = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Grade::Again => "again",
+            Grade::Hard => "hard",
+            Grade::Good => "good",
+            Grade::Easy => "easy",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Grade::Again => "Again",
+            Grade::Hard => "Hard",
+            Grade::Good => "Good",
+            Grade::Easy => "Easy",
+        }
+    }
+}
+
+/// SM-2 style scheduling state of a single vocabulary.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ReviewCard {
+    pub ease: f64,
+    pub interval_days: f64,
+    pub repetitions: u32,
+    pub due_ms: u64,
+}
+
+impl Default for ReviewCard {
+    fn default() -> Self {
+        Self {
+            ease: 2.5,
+            interval_days: 0.0,
+            repetitions: 0,
+            due_ms: 0,
+        }
+    }
+}
+
+impl ReviewCard {
+    pub fn review(&mut self, grade: Grade, now_ms: u64) {
+        if grade == Grade::Again {
+            self.repetitions = 0;
+            self.interval_days = 0.0;
+            self.ease = (self.ease - 0.2).max(MIN_EASE);
+            self.due_ms = now_ms + RELEARN_DELAY_MS;
+            return;
+        }
+        let next_interval = match self.repetitions {
+            0 => 1.0,
+            1 => 6.0,
+            _ => self.interval_days * self.ease,
+        };
+        self.interval_days = match grade {
+            Grade::Hard => (self.interval_days * 1.2).max(1.0),
+            Grade::Good => next_interval,
+            Grade::Easy => next_interval * 1.3,
+            Grade::Again => unreachable!(),
+        };
+        self.ease = match grade {
+            Grade::Hard => (self.ease - 0.15).max(MIN_EASE),
+            Grade::Easy => self.ease + 0.15,
+            _ => self.ease,
+        };
+        self.repetitions += 1;
+        self.due_ms = now_ms + (self.interval_days * DAY_MS) as u64;
+    }
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+pub async fn load_schedule(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+) -> Result<HashMap<String, ReviewCard>> {
+    let raw = store
+        .hash_get_all(&schema::review_schedule_key(chat_id))
+        .await?;
+    Ok(raw
+        .into_iter()
+        .filter_map(|(id, card)| Some((id, serde_json::from_str(&card).ok()?)))
+        .collect())
+}
+
+pub async fn save_card(
+    store: &dyn StateStore,
+    chat_id: ChatId,
+    vocabulary_id: &str,
+    card: &ReviewCard,
+) -> Result<()> {
+    let fields = [(vocabulary_id.to_string(), serde_json::to_string(card)?)];
+    store
+        .hash_set(&schema::review_schedule_key(chat_id), &fields)
+        .await
+}
+
+/// The most overdue vocabulary, or the least recently practiced new one.
+/// Returns the due time of the earliest card instead when nothing is due.
+pub fn next_due<'a>(
+    vocabulary: &'a [Vocabulary],
+    schedule: &HashMap<String, ReviewCard>,
+    now_ms: u64,
+) -> std::result::Result<&'a Vocabulary, Option<u64>> {
+    let overdue = vocabulary
+        .iter()
+        .filter_map(|it| Some((it, schedule.get(&it.id)?.due_ms)))
+        .filter(|(_, due_ms)| *due_ms <= now_ms)
+        .min_by_key(|(_, due_ms)| *due_ms);
+    if let Some((vocabulary, _)) = overdue {
+        return Ok(vocabulary);
+    }
+    if let Some(new) = vocabulary.iter().find(|it| !schedule.contains_key(&it.id)) {
+        return Ok(new);
+    }
+    Err(schedule.values().map(|it| it.due_ms).min())
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    convert::Infallible,
+    env,
+    net::SocketAddr,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ezio::prelude::*;
+use futures_util::FutureExt;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use teloxide::types::{ChatId, MessageKind, Update, UpdateKind};
+
+use crate::{
+    error::{Error, Result},
+    group, menu_commands, reminder,
+    review::now_ms,
+    store::{self, bot_state, schema, StateStore},
+    telegram::Telegram,
+    tts,
+    util::decrypt,
+    Bot,
+};
+
+const LONG_POLLING_TIMEOUT_SECS: u32 = 50;
+const REMINDER_INTERVAL_SECS: u64 = 60;
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+pub async fn handle_update(update: &Update, store: &dyn StateStore) -> Result<()> {
+    let chat = update
+        .chat()
+        .ok_or_else(|| Error::UnsupportedUpdate("Not a chat".to_string()))?;
+    if chat.is_channel() {
+        return Err(Error::UnsupportedUpdate(format!(
+            "Not a user or group: {}",
+            chat.id
+        )));
+    }
+    // State is per member, in private chats the member is the chat.
+    let user = update
+        .user()
+        .ok_or_else(|| Error::UnsupportedUpdate("No sender".to_string()))?;
+    let member_id = ChatId::from(user.id);
+    if !chat.is_private() {
+        group::record_member(store, chat.id, member_id, &user.first_name).await?;
+    }
+    let mut bot = match bot_state::load(store, member_id).await? {
+        Some(bot) => bot,
+        None => {
+            let telegram_token = env::var("TELEGRAM_TOKEN").unwrap();
+            Bot::new(telegram_token).await
+        }
+    };
+    bot.member_id = Some(member_id);
+    let message = match &update.kind {
+        UpdateKind::Message(message) => Some(message),
+        UpdateKind::CallbackQuery(query) => query.message.as_ref(),
+        _ => None,
+    };
+    bot.telegram.thread_id = message
+        .filter(|it| matches!(&it.kind, MessageKind::Common(common) if common.is_topic_message))
+        .and_then(|it| it.thread_id);
+    match &update.kind {
+        UpdateKind::Message(message) => bot.handle(message, store).await,
+        UpdateKind::CallbackQuery(query) => bot.handle_callback_query(query, store).await,
+        _ => {}
+    }
+    bot_state::save(store, member_id, &bot).await
+}
+
+/// Handles an update, logging instead of failing, so one bad update can't
+/// stop the updates after it.
+async fn handle_update_logged(update: &Update, store: &dyn StateStore) {
+    match AssertUnwindSafe(handle_update(update, store))
+        .catch_unwind()
+        .await
+    {
+        Ok(Ok(())) => {}
+        Ok(Err(reason)) => println!("Ignored update {}: {reason}", update.id),
+        Err(_) => println!("Update {} panicked", update.id),
+    }
+}
+
+/// Sends the daily reminders that are due.
+pub async fn send_due_reminders(store: &dyn StateStore) -> Result<()> {
+    for chat_id in reminder::due_chats(store, now_ms()).await? {
+        let bot = match bot_state::read(store, chat_id).await {
+            Ok(Some(bot)) => bot,
+            Ok(None) => {
+                reminder::schedule(store, chat_id, None).await?;
+                continue;
+            }
+            Err(error) => {
+                println!("Failed to load the state of chat {chat_id}: {error}");
+                continue;
+            }
+        };
+        if let Err(error) = bot.send_reminder(chat_id, store).await {
+            println!("Failed to send reminder to chat {chat_id}: {error}");
+        }
+        // Someone who asked for reminders is still around.
+        store
+            .expire(&schema::bot_key(chat_id), schema::BOT_TTL_SECS)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Sends due reminders every [`REMINDER_INTERVAL_SECS`] alongside a server.
+async fn send_reminders_forever(store: Arc<dyn StateStore>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(REMINDER_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        if let Err(error) = send_due_reminders(store.as_ref()).await {
+            println!("Failed to send reminders: {error}");
+        }
+    }
+}
+
+/// Sends the due reminders once, for running from cron.
+pub async fn remind() {
+    let store = store::from_env().await.unwrap();
+    tts::load_azure(&store).await;
+    send_due_reminders(store.as_ref()).await.unwrap();
+}
+
+/// Handles the single update stored encrypted in `./request.json.encrypted`.
+pub async fn once() {
+    let secret_str = env::var("SECRET").unwrap();
+
+    let store = store::from_env().await.unwrap();
+    tts::load_azure(&store).await;
+    let secret = hex::decode(secret_str).unwrap();
+    let request_encrypted = file::read("./request.json.encrypted");
+    let request_str = decrypt(&hex::decode(request_encrypted).unwrap(), &secret);
+    let request: Update = serde_json::from_str(&request_str).unwrap();
+    handle_update(&request, store.as_ref()).await.unwrap();
+}
+
+async fn register_commands(telegram: &Telegram) {
+    if let Err(error) = telegram.set_my_commands(menu_commands()).await {
+        println!("Failed to register the command menu: {error}");
+    }
+}
+
+/// Runs a `getUpdates` long-polling loop forever.
+pub async fn serve() {
+    let store = store::from_env().await.unwrap();
+    let telegram = Telegram::from_env();
+    telegram.delete_webhook().await.unwrap();
+    register_commands(&telegram).await;
+    tokio::spawn(tts::load_azure_forever(store.clone()));
+    tokio::spawn(send_reminders_forever(store.clone()));
+    let mut offset = None;
+    loop {
+        let updates = match telegram
+            .get_updates(offset, LONG_POLLING_TIMEOUT_SECS)
+            .await
+        {
+            Ok(updates) => updates,
+            Err(error) => {
+                println!("Failed to get updates: {error}");
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        for update in updates {
+            offset = Some(update.id + 1);
+            handle_update_logged(&update, store.as_ref()).await;
+        }
+    }
+}
+
+/// Serves Telegram webhook calls on `WEBHOOK_ADDR` (default `0.0.0.0:8080`).
+///
+/// When `TELEGRAM_WEBHOOK_SECRET` is set, requests whose
+/// `X-Telegram-Bot-Api-Secret-Token` header doesn't match are rejected.
+/// When `WEBHOOK_URL` is set, the webhook is registered with Telegram on startup.
+/// Updates are acknowledged right away and handled in the background.
+pub async fn webhook() {
+    let store = store::from_env().await.unwrap();
+    let secret_token = env::var("TELEGRAM_WEBHOOK_SECRET").ok();
+    let addr: SocketAddr = env::var("WEBHOOK_ADDR")
+        .unwrap_or_else(|_| "0.0.0.0:8080".to_string())
+        .parse()
+        .unwrap();
+    let telegram = Telegram::from_env();
+    if let Ok(webhook_url) = env::var("WEBHOOK_URL") {
+        telegram
+            .set_webhook(&webhook_url, secret_token.as_deref())
+            .await
+            .unwrap();
+    }
+    register_commands(&telegram).await;
+    tokio::spawn(tts::load_azure_forever(store.clone()));
+    tokio::spawn(send_reminders_forever(store.clone()));
+    let queues = Arc::new(UpdateQueues::new(store));
+    let make_service = make_service_fn(move |_| {
+        let queues = queues.clone();
+        let secret_token = secret_token.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_webhook_request(request, queues.clone(), secret_token.clone())
+            }))
+        }
+    });
+    Server::bind(&addr).serve(make_service).await.unwrap();
+}
+
+async fn handle_webhook_request(
+    request: Request<Body>,
+    queues: Arc<UpdateQueues>,
+    secret_token: Option<String>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST {
+        return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    if let Some(secret_token) = &secret_token {
+        let provided = request
+            .headers()
+            .get(SECRET_TOKEN_HEADER)
+            .and_then(|it| it.to_str().ok());
+        if provided != Some(secret_token.as_str()) {
+            return Ok(status_response(StatusCode::UNAUTHORIZED));
+        }
+    }
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return Ok(status_response(StatusCode::BAD_REQUEST)),
+    };
+    let update: Update = match serde_json::from_slice(&body) {
+        Ok(update) => update,
+        Err(_) => return Ok(status_response(StatusCode::BAD_REQUEST)),
+    };
+    queues.push(update);
+    Ok(status_response(StatusCode::OK))
+}
+
+/// Handles updates in the background, one at a time per member so their
+/// state isn't loaded and saved by two updates at once, members in parallel.
+pub struct UpdateQueues {
+    store: Arc<dyn StateStore>,
+    /// The updates waiting per member, present while a task handles them.
+    queues: Mutex<HashMap<Option<ChatId>, VecDeque<Update>>>,
+}
+
+impl UpdateQueues {
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            store,
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn push(self: &Arc<Self>, update: Update) {
+        let member_id = update.user().map(|it| ChatId::from(it.id));
+        match self.queues.lock().unwrap().entry(member_id) {
+            Entry::Occupied(mut queue) => queue.get_mut().push_back(update),
+            Entry::Vacant(queue) => {
+                queue.insert(VecDeque::from([update]));
+                tokio::spawn(self.clone().drain(member_id));
+            }
+        }
+    }
+
+    async fn drain(self: Arc<Self>, member_id: Option<ChatId>) {
+        loop {
+            let update = {
+                let mut queues = self.queues.lock().unwrap();
+                let Some(update) = queues.get_mut(&member_id).and_then(VecDeque::pop_front) else {
+                    queues.remove(&member_id);
+                    return;
+                };
+                update
+            };
+            handle_update_logged(&update, self.store.as_ref()).await;
+        }
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
@@ -0,0 +1,172 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+use crate::{
+    history::{Event, EventKind},
+    mistakes::Category,
+    review::Grade,
+    timezone::{self, TimeZone},
+};
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+/// Days shown in the words per day breakdown and chart.
+pub const RECENT_DAYS: i64 = 14;
+const TOP_MISTAKES: usize = 3;
+
+/// What `/stats` reports, days are local days of the learner's time zone.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub streak_days: u32,
+    /// Distinct words seen per day over the last [`RECENT_DAYS`], oldest first.
+    pub words_per_day: Vec<u32>,
+    /// The day of the month of each of `words_per_day`.
+    pub days_of_month: Vec<u32>,
+    pub quiz_answers: u32,
+    pub quiz_correct: u32,
+    pub reviews: u32,
+    pub reviews_passed: u32,
+    pub chat_turns: u32,
+    pub stories: u32,
+    /// Mistake categories, the most frequent first.
+    pub mistakes: Vec<(Category, u32)>,
+}
+
+impl Stats {
+    pub fn from_events(events: &[Event], now_ms: u64, time_zone: &TimeZone) -> Self {
+        let local_day = |ms: u64| time_zone.to_local(ms as i64 / 1000).div_euclid(DAY_SECS);
+        let today = local_day(now_ms);
+        let mut stats = Stats::default();
+        let mut active_days = HashSet::new();
+        let mut words_by_day: HashMap<i64, HashSet<&str>> = HashMap::new();
+        let mut mistakes: BTreeMap<Category, u32> = BTreeMap::new();
+        for event in events {
+            let day = local_day(event.at_ms);
+            active_days.insert(day);
+            let vocabulary_id = match &event.kind {
+                EventKind::WordShown { vocabulary_id } => Some(vocabulary_id),
+                EventKind::Reviewed {
+                    vocabulary_id,
+                    grade,
+                } => {
+                    stats.reviews += 1;
+                    if *grade != Grade::Again {
+                        stats.reviews_passed += 1;
+                    }
+                    Some(vocabulary_id)
+                }
+                EventKind::QuizAnswered {
+                    vocabulary_id,
+                    correct,
+                } => {
+                    stats.quiz_answers += 1;
+                    if *correct {
+                        stats.quiz_correct += 1;
+                    }
+                    Some(vocabulary_id)
+                }
+                EventKind::ChatTurn => {
+                    stats.chat_turns += 1;
+                    None
+                }
+                EventKind::StoryGenerated => {
+                    stats.stories += 1;
+                    None
+                }
+                EventKind::MistakeCorrected { category } => {
+                    *mistakes.entry(*category).or_default() += 1;
+                    None
+                }
+            };
+            if let Some(vocabulary_id) = vocabulary_id {
+                words_by_day.entry(day).or_default().insert(vocabulary_id);
+            }
+        }
+        let mut day = if active_days.contains(&today) {
+            today
+        } else {
+            today - 1
+        };
+        while active_days.contains(&day) {
+            stats.streak_days += 1;
+            day -= 1;
+        }
+        let recent_days = today - RECENT_DAYS + 1..=today;
+        stats.words_per_day = recent_days
+            .clone()
+            .map(|day| words_by_day.get(&day).map_or(0, |it| it.len() as u32))
+            .collect();
+        stats.days_of_month = recent_days.map(timezone::day_of_month).collect();
+        stats.mistakes = mistakes.into_iter().collect();
+        stats.mistakes.sort_by_key(|(_, count)| Reverse(*count));
+        stats
+    }
+
+    pub fn to_text(&self) -> String {
+        let percent = |part: u32, total: u32| match total {
+            0 => "-".to_string(),
+            _ => format!("{:.0}%", part as f64 * 100.0 / total as f64),
+        };
+        let words_per_day = self
+            .words_per_day
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut text = format!(
+            "Streak: {} days\nWords per day (last {RECENT_DAYS} days): {words_per_day}\nQuiz accuracy: {} of {} ({})\nReviews remembered: {} of {} ({})\nChat turns: {}\nStories: {}",
+            self.streak_days,
+            self.quiz_correct,
+            self.quiz_answers,
+            percent(self.quiz_correct, self.quiz_answers),
+            self.reviews_passed,
+            self.reviews,
+            percent(self.reviews_passed, self.reviews),
+            self.chat_turns,
+            self.stories,
+        );
+        if !self.mistakes.is_empty() {
+            let mistakes = self
+                .mistakes
+                .iter()
+                .take(TOP_MISTAKES)
+                .map(|(category, count)| format!("{} ({count})", category.label()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            text.push_str(&format!("\nMost frequent mistakes: {mistakes}"));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(at_ms: u64, vocabulary_id: &str) -> Event {
+        Event {
+            at_ms,
+            kind: EventKind::WordShown {
+                vocabulary_id: vocabulary_id.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn days_are_local_days() {
+        let sydney = TimeZone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        // 2024-06-01 23:00 and 2024-06-02 01:00 UTC are both the 2nd in Sydney.
+        let events = [shown(1_717_282_800_000, "a"), shown(1_717_290_000_000, "b")];
+        let now_ms = 1_717_290_000_000;
+        let stats = Stats::from_events(&events, now_ms, &sydney);
+        assert_eq!(stats.streak_days, 1);
+        assert_eq!(stats.words_per_day[RECENT_DAYS as usize - 1], 2);
+        assert_eq!(stats.days_of_month[RECENT_DAYS as usize - 1], 2);
+        assert_eq!(stats.days_of_month[0], 20);
+        let stats = Stats::from_events(&events, now_ms, &TimeZone::UTC);
+        assert_eq!(stats.streak_days, 2);
+        assert_eq!(stats.words_per_day[RECENT_DAYS as usize - 2..], [1, 1]);
+        assert_eq!(stats.days_of_month[RECENT_DAYS as usize - 2..], [1, 2]);
+    }
+}
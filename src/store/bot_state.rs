@@ -0,0 +1,196 @@
+//! The `Bot` state of each member, wrapped in a versioned envelope so older
+//! state is migrated on load instead of being replaced by a fresh `Bot`.
+
+use std::env;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use teloxide::types::ChatId;
+
+use super::{
+    schema::{self, SCHEMA_VERSION},
+    StateStore,
+};
+use crate::{
+    error::{Error, Result},
+    review::now_ms,
+    store, Bot,
+};
+
+/// `MIGRATIONS[n]` turns the state of schema version `n + 1` into `n + 2`,
+/// so every bump of [`SCHEMA_VERSION`] comes with one.
+const MIGRATIONS: [fn(Value) -> Value; SCHEMA_VERSION as usize - 1] = [drop_azure_voices];
+
+/// Version 1 kept a copy of the Azure key and voice list with every member,
+/// now the bot shares one.
+fn drop_azure_voices(mut bot: Value) -> Value {
+    if let Some(fields) = bot.as_object_mut() {
+        fields.remove("azure_tts");
+    }
+    bot
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    bot: Value,
+}
+
+/// A stored state, read at the version it was written with.
+pub struct Decoded {
+    pub version: u32,
+    pub bot: Bot,
+}
+
+/// Reads a stored state of any known version, the bare `Bot` JSON of version
+/// 1 included.
+pub fn decode(blob: &str) -> Result<Decoded> {
+    let value = serde_json::from_str::<Value>(blob)?;
+    let Envelope { version, mut bot } = match value.get("version") {
+        Some(_) => serde_json::from_value(value)?,
+        None => Envelope {
+            version: 1,
+            bot: value,
+        },
+    };
+    if version > SCHEMA_VERSION {
+        return Err(Error::UnsupportedSchema(version.to_string()));
+    }
+    for migrate in &MIGRATIONS[version.saturating_sub(1) as usize..] {
+        bot = migrate(bot);
+    }
+    Ok(Decoded {
+        version,
+        bot: serde_json::from_value(bot)?,
+    })
+}
+
+pub fn encode(bot: &Bot) -> Result<String> {
+    Ok(serde_json::to_string(&Envelope {
+        version: SCHEMA_VERSION,
+        bot: serde_json::to_value(bot)?,
+    })?)
+}
+
+/// The state of a member, for reading only, as state that can't be read is
+/// left as it is.
+pub async fn read(store: &dyn StateStore, member_id: ChatId) -> Result<Option<Bot>> {
+    let Some(blob) = store.get_string(&schema::bot_key(member_id)).await? else {
+        return Ok(None);
+    };
+    match decode(&blob) {
+        Ok(decoded) => Ok(Some(decoded.bot)),
+        Err(error @ Error::UnsupportedSchema(_)) => Err(error),
+        Err(error) => {
+            println!("Skipped the unreadable state of {member_id}: {error}");
+            Ok(None)
+        }
+    }
+}
+
+/// The state of a member, to be saved again after handling their update.
+///
+/// State that can't be read is moved under a quarantine key and the member
+/// starts afresh, state from a newer build is left alone and fails instead.
+pub async fn load(store: &dyn StateStore, member_id: ChatId) -> Result<Option<Bot>> {
+    let Some(blob) = store.get_string(&schema::bot_key(member_id)).await? else {
+        return Ok(None);
+    };
+    match decode(&blob) {
+        Ok(decoded) => Ok(Some(decoded.bot)),
+        Err(error @ Error::UnsupportedSchema(_)) => Err(error),
+        Err(error) => {
+            let key = quarantine(store, member_id, &blob).await?;
+            println!("Moved the unreadable state of {member_id} to {key}: {error}");
+            Ok(None)
+        }
+    }
+}
+
+pub async fn save(store: &dyn StateStore, member_id: ChatId, bot: &Bot) -> Result<()> {
+    store
+        .set_string(
+            &schema::bot_key(member_id),
+            &encode(bot)?,
+            Some(schema::BOT_TTL_SECS),
+        )
+        .await
+}
+
+async fn quarantine(store: &dyn StateStore, member_id: ChatId, blob: &str) -> Result<String> {
+    let key = schema::quarantine_key(member_id, now_ms());
+    store
+        .set_string(&key, blob, Some(schema::QUARANTINE_TTL_SECS))
+        .await?;
+    store.delete(&[schema::bot_key(member_id)]).await?;
+    Ok(key)
+}
+
+/// Every member with stored state, or only `member_id`.
+async fn members(store: &dyn StateStore, member_id: Option<ChatId>) -> Result<Vec<ChatId>> {
+    if let Some(member_id) = member_id {
+        return Ok(vec![member_id]);
+    }
+    let mut members = store
+        .scan(schema::BOT_KEY_PREFIX)
+        .await?
+        .iter()
+        .filter_map(|it| schema::bot_member_id(it))
+        .collect::<Vec<_>>();
+    members.sort_by_key(|it| it.0);
+    Ok(members)
+}
+
+/// Prints one line per member: the stored JSON, its version and contents, or
+/// the result of migrating it to [`SCHEMA_VERSION`].
+pub async fn command() {
+    let args = env::args().skip(2).collect::<Vec<_>>();
+    let (action, member_id) = match &args[..] {
+        [action] => (action.as_str(), None),
+        [action, member_id] => (action.as_str(), Some(ChatId(member_id.parse().unwrap()))),
+        _ => panic!("Usage: state <dump|inspect|migrate> [member_id]"),
+    };
+    let store = store::from_env().await.unwrap();
+    let store = store.as_ref();
+    for member_id in members(store, member_id).await.unwrap() {
+        let Some(blob) = store.get_string(&schema::bot_key(member_id)).await.unwrap() else {
+            println!("{member_id}: no state");
+            continue;
+        };
+        match action {
+            "dump" => {
+                let state = serde_json::from_str(&blob).unwrap_or(Value::String(blob));
+                println!(
+                    "{}",
+                    serde_json::json!({ "member_id": member_id.0, "state": state })
+                );
+            }
+            "inspect" => match decode(&blob) {
+                Ok(Decoded { version, bot }) => {
+                    let course = bot
+                        .duolingo
+                        .as_ref()
+                        .and_then(|it| it.learning_language().ok())
+                        .map_or("not logged in".to_string(), |it| format!("learning {it}"));
+                    println!("{member_id}: version {version}, {course}");
+                }
+                Err(error) => println!("{member_id}: unreadable, {error}"),
+            },
+            "migrate" => match decode(&blob) {
+                Ok(Decoded { version, .. }) if version == SCHEMA_VERSION => {
+                    println!("{member_id}: already at version {version}")
+                }
+                Ok(Decoded { version, bot }) => {
+                    save(store, member_id, &bot).await.unwrap();
+                    println!("{member_id}: migrated from version {version}");
+                }
+                Err(error @ Error::UnsupportedSchema(_)) => println!("{member_id}: {error}"),
+                Err(error) => {
+                    let key = quarantine(store, member_id, &blob).await.unwrap();
+                    println!("{member_id}: unreadable, moved to {key}, {error}");
+                }
+            },
+            other => panic!("Unknown state action: {other}, expected dump, inspect or migrate"),
+        }
+    }
+}
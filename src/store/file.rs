@@ -0,0 +1,546 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, Write as _},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha1::{Digest, Sha1};
+
+use super::StateStore;
+use crate::{
+    error::{Error, Result},
+    review::now_ms,
+};
+
+/// The log isn't rewritten while shorter than this.
+const COMPACT_MIN_LINES: usize = 1000;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+enum Value {
+    Bytes(#[serde(with = "base64_bytes")] Vec<u8>),
+    Hash(HashMap<String, String>),
+    List(VecDeque<String>),
+    SortedSet(HashMap<String, f64>),
+}
+
+impl Value {
+    /// Binary values, audio in practice, are kept in files of their own.
+    fn is_blob(&self) -> bool {
+        matches!(self, Value::Bytes(bytes) if std::str::from_utf8(bytes).is_err())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    value: Value,
+    #[serde(default)]
+    expires_at_ms: Option<u64>,
+}
+
+impl Entry {
+    fn is_live(&self, now_ms: u64) -> bool {
+        self.expires_at_ms.is_none_or(|at| at > now_ms)
+    }
+}
+
+/// A line of the log: the entry now at `key`, `None` once it is gone.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    key: String,
+    entry: Option<Entry>,
+    /// The value is in the blob file of `key` rather than here.
+    #[serde(default)]
+    blob: bool,
+}
+
+impl Record {
+    fn new(key: &str, entry: Option<&Entry>) -> Self {
+        let blob = entry.is_some_and(|it| it.value.is_blob());
+        let entry = entry.map(|it| match blob {
+            true => Entry {
+                value: Value::Bytes(Vec::new()),
+                expires_at_ms: it.expires_at_ms,
+            },
+            false => it.clone(),
+        });
+        Self {
+            key: key.to_string(),
+            entry,
+            blob,
+        }
+    }
+}
+
+/// A change to the files, done off the async threads in the order queued.
+enum Write {
+    /// Log lines to append.
+    Append(Vec<u8>),
+    Blob(String, Vec<u8>),
+    /// Replaces the log with these entries and drops the blobs of any others.
+    Rewrite(HashMap<String, Entry>),
+}
+
+struct State {
+    entries: HashMap<String, Entry>,
+    /// Lines in the log, more than the entries once keys change again.
+    logged: usize,
+}
+
+/// Keeps everything in memory and appends each change to a log file, for a
+/// single process without a Redis server.
+///
+/// The log is rewritten from the live entries when it has grown to twice
+/// their number, binary values are kept beside it in `{name}.blobs`.
+pub struct FileStore {
+    files: Arc<Files>,
+    state: Mutex<State>,
+    /// The writes not done yet, queued under the `state` lock so they are
+    /// done in the order of the changes.
+    queue: Arc<Mutex<Vec<Write>>>,
+}
+
+/// The log and blob paths, with the open log held while writing.
+struct Files {
+    path: PathBuf,
+    blob_dir: PathBuf,
+    log: Mutex<fs::File>,
+}
+
+impl Files {
+    fn blob_path(&self, key: &str) -> PathBuf {
+        self.blob_dir.join(hex::encode(Sha1::digest(key)))
+    }
+
+    /// Does the queued writes, as one batch at a time holds the log.
+    fn write(&self, queue: &Mutex<Vec<Write>>) -> io::Result<()> {
+        let mut log = self.log.lock().unwrap();
+        let writes = std::mem::take(&mut *queue.lock().unwrap());
+        for write in writes {
+            match write {
+                Write::Append(lines) => log.write_all(&lines)?,
+                Write::Blob(key, bytes) => {
+                    fs::create_dir_all(&self.blob_dir)?;
+                    fs::write(self.blob_path(&key), bytes)?;
+                }
+                Write::Rewrite(entries) => *log = self.rewrite(&entries)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the log afresh, aside and renamed so a crash never leaves half
+    /// a file, and returns it opened for appending.
+    fn rewrite(&self, entries: &HashMap<String, Entry>) -> io::Result<fs::File> {
+        let mut lines = Vec::new();
+        for (key, entry) in entries {
+            serde_json::to_writer(&mut lines, &Record::new(key, Some(entry)))?;
+            lines.push(b'\n');
+        }
+        let temporary_path = self.path.with_extension("tmp");
+        fs::write(&temporary_path, lines)?;
+        fs::rename(&temporary_path, &self.path)?;
+        let blobs = entries
+            .iter()
+            .filter(|(_, entry)| entry.value.is_blob())
+            .map(|(key, _)| self.blob_path(key))
+            .collect::<HashSet<_>>();
+        if let Ok(files) = fs::read_dir(&self.blob_dir) {
+            for file in files {
+                let path = file?.path();
+                if !blobs.contains(&path) {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        fs::File::options().append(true).open(&self.path)
+    }
+}
+
+impl FileStore {
+    /// Loads the log at `path`, or a whole state file of earlier versions,
+    /// and compacts it.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let blob_dir = path.with_extension("blobs");
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(Error::StateFile(error)),
+        };
+        let placeholder = fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(Error::StateFile)?;
+        let files = Files {
+            path,
+            blob_dir,
+            log: Mutex::new(placeholder),
+        };
+        let mut entries = HashMap::new();
+        // A line cut short by a crash is skipped.
+        for line in content.split(|it| *it == b'\n') {
+            if let Ok(record) = serde_json::from_slice::<Record>(line) {
+                let entry = match record.entry {
+                    Some(mut entry) if record.blob => {
+                        match fs::read(files.blob_path(&record.key)) {
+                            Ok(bytes) => {
+                                entry.value = Value::Bytes(bytes);
+                                Some(entry)
+                            }
+                            Err(_) => None,
+                        }
+                    }
+                    entry => entry,
+                };
+                match entry {
+                    Some(entry) => entries.insert(record.key, entry),
+                    None => entries.remove(&record.key),
+                };
+            } else if let Ok(state) = serde_json::from_slice::<HashMap<String, Entry>>(line) {
+                entries.extend(state);
+            }
+        }
+        let now = now_ms();
+        entries.retain(|_, it| it.is_live(now));
+        let write = || -> io::Result<fs::File> {
+            for (key, entry) in &entries {
+                if let Value::Bytes(bytes) = &entry.value {
+                    if entry.value.is_blob() && !files.blob_path(key).exists() {
+                        fs::create_dir_all(&files.blob_dir)?;
+                        fs::write(files.blob_path(key), bytes)?;
+                    }
+                }
+            }
+            files.rewrite(&entries)
+        };
+        *files.log.lock().unwrap() = write().map_err(Error::StateFile)?;
+        Ok(Self {
+            files: Arc::new(files),
+            state: Mutex::new(State {
+                logged: entries.len(),
+                entries,
+            }),
+            queue: Arc::default(),
+        })
+    }
+
+    /// Reads the live entry at `key`.
+    fn read<T>(&self, key: &str, read: impl FnOnce(Option<&Value>) -> T) -> T {
+        let state = self.state.lock().unwrap();
+        let now = now_ms();
+        read(
+            state
+                .entries
+                .get(key)
+                .filter(|it| it.is_live(now))
+                .map(|it| &it.value),
+        )
+    }
+
+    /// Changes the entries at `keys`, then logs them.
+    async fn write<T>(
+        &self,
+        keys: &[&str],
+        write: impl FnOnce(&mut HashMap<String, Entry>) -> T,
+    ) -> Result<T> {
+        let result = {
+            let mut state = self.state.lock().unwrap();
+            let result = write(&mut state.entries);
+            let mut writes = Vec::new();
+            let mut lines = Vec::new();
+            for key in keys {
+                let entry = state.entries.get(*key);
+                if let Some(Entry {
+                    value: value @ Value::Bytes(bytes),
+                    ..
+                }) = entry
+                {
+                    if value.is_blob() {
+                        writes.push(Write::Blob(key.to_string(), bytes.clone()));
+                    }
+                }
+                serde_json::to_writer(&mut lines, &Record::new(key, entry))?;
+                lines.push(b'\n');
+            }
+            writes.push(Write::Append(lines));
+            state.logged += keys.len();
+            if state.logged > COMPACT_MIN_LINES && state.logged > 2 * state.entries.len() {
+                let now = now_ms();
+                state.entries.retain(|_, it| it.is_live(now));
+                state.logged = state.entries.len();
+                writes.push(Write::Rewrite(state.entries.clone()));
+            }
+            self.queue.lock().unwrap().extend(writes);
+            result
+        };
+        let (files, queue) = (self.files.clone(), self.queue.clone());
+        tokio::task::spawn_blocking(move || files.write(&queue))
+            .await
+            .unwrap_or_else(|error| Err(io::Error::other(error)))
+            .map_err(Error::StateFile)?;
+        Ok(result)
+    }
+
+    /// Changes the value at `key`, creating it with `default` if missing or
+    /// of another type.
+    async fn update<T>(
+        &self,
+        key: &str,
+        default: Value,
+        update: impl FnOnce(&mut Value) -> T,
+    ) -> Result<T> {
+        self.write(&[key], |entries| {
+            let now = now_ms();
+            let entry = entries
+                .entry(key.to_string())
+                .and_modify(|it| {
+                    if !it.is_live(now) {
+                        it.expires_at_ms = None;
+                        it.value = default.clone();
+                    }
+                })
+                .or_insert_with(|| Entry {
+                    value: default.clone(),
+                    expires_at_ms: None,
+                });
+            if std::mem::discriminant(&entry.value) != std::mem::discriminant(&default) {
+                entry.value = default;
+            }
+            update(&mut entry.value)
+        })
+        .await
+    }
+}
+
+fn sorted_by_score(set: &HashMap<String, f64>) -> Vec<(String, f64)> {
+    let mut members = set
+        .iter()
+        .map(|(member, score)| (member.clone(), *score))
+        .collect::<Vec<_>>();
+    members.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    members
+}
+
+#[async_trait]
+impl StateStore for FileStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.read(key, |value| match value {
+            Some(Value::Bytes(bytes)) => Some(bytes.clone()),
+            _ => None,
+        }))
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.get(key).await?);
+        }
+        Ok(values)
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl_secs: Option<usize>) -> Result<()> {
+        let entry = Entry {
+            value: Value::Bytes(value.to_vec()),
+            expires_at_ms: ttl_secs.map(|it| now_ms() + it as u64 * 1000),
+        };
+        self.write(&[key], |entries| {
+            entries.insert(key.to_string(), entry);
+        })
+        .await
+    }
+
+    async fn delete(&self, keys: &[String]) -> Result<()> {
+        let keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+        self.write(&keys, |entries| {
+            for key in &keys {
+                entries.remove(*key);
+            }
+        })
+        .await
+    }
+
+    async fn expire(&self, key: &str, ttl_secs: usize) -> Result<()> {
+        self.write(&[key], |entries| {
+            let now = now_ms();
+            if let Some(entry) = entries.get_mut(key).filter(|it| it.is_live(now)) {
+                entry.expires_at_ms = Some(now + ttl_secs as u64 * 1000);
+            }
+        })
+        .await
+    }
+
+    async fn scan(&self, prefix: &str) -> Result<Vec<String>> {
+        let state = self.state.lock().unwrap();
+        let now = now_ms();
+        Ok(state
+            .entries
+            .iter()
+            .filter(|(key, entry)| key.starts_with(prefix) && entry.is_live(now))
+            .map(|(key, _)| key.clone())
+            .collect())
+    }
+
+    async fn rename(&self, key: &str, new_key: &str) -> Result<()> {
+        self.write(&[key, new_key], |entries| {
+            if let Some(entry) = entries.remove(key) {
+                entries.insert(new_key.to_string(), entry);
+            }
+        })
+        .await
+    }
+
+    async fn increment(&self, key: &str, by: i64) -> Result<i64> {
+        self.update(key, Value::Bytes(b"0".to_vec()), |value| match value {
+            Value::Bytes(bytes) => {
+                let count = String::from_utf8_lossy(bytes)
+                    .parse::<i64>()
+                    .unwrap_or_default()
+                    + by;
+                *bytes = count.to_string().into_bytes();
+                count
+            }
+            _ => by,
+        })
+        .await
+    }
+
+    async fn hash_get(&self, key: &str, field: &str) -> Result<Option<String>> {
+        Ok(self.read(key, |value| match value {
+            Some(Value::Hash(hash)) => hash.get(field).cloned(),
+            _ => None,
+        }))
+    }
+
+    async fn hash_get_all(&self, key: &str) -> Result<HashMap<String, String>> {
+        Ok(self.read(key, |value| match value {
+            Some(Value::Hash(hash)) => hash.clone(),
+            _ => HashMap::new(),
+        }))
+    }
+
+    async fn hash_set(&self, key: &str, fields: &[(String, String)]) -> Result<()> {
+        self.update(key, Value::Hash(HashMap::new()), |value| {
+            if let Value::Hash(hash) = value {
+                hash.extend(fields.iter().cloned());
+            }
+        })
+        .await
+    }
+
+    async fn hash_delete(&self, key: &str, field: &str) -> Result<bool> {
+        self.write(&[key], |entries| match entries.get_mut(key) {
+            Some(Entry {
+                value: Value::Hash(hash),
+                ..
+            }) => hash.remove(field).is_some(),
+            _ => false,
+        })
+        .await
+    }
+
+    async fn list_push(&self, key: &str, values: &[String], max_len: usize) -> Result<()> {
+        self.update(key, Value::List(VecDeque::new()), |value| {
+            if let Value::List(list) = value {
+                list.extend(values.iter().cloned());
+                let excess = list.len().saturating_sub(max_len);
+                list.drain(..excess);
+            }
+        })
+        .await
+    }
+
+    async fn list_all(&self, key: &str) -> Result<Vec<String>> {
+        Ok(self.read(key, |value| match value {
+            Some(Value::List(list)) => list.iter().cloned().collect(),
+            _ => Vec::new(),
+        }))
+    }
+
+    async fn sorted_set_add(&self, key: &str, member: &str, score: f64) -> Result<()> {
+        self.update(key, Value::SortedSet(HashMap::new()), |value| {
+            if let Value::SortedSet(set) = value {
+                set.insert(member.to_string(), score);
+            }
+        })
+        .await
+    }
+
+    async fn sorted_set_increment(&self, key: &str, member: &str, by: f64) -> Result<()> {
+        self.update(key, Value::SortedSet(HashMap::new()), |value| {
+            if let Value::SortedSet(set) = value {
+                *set.entry(member.to_string()).or_default() += by;
+            }
+        })
+        .await
+    }
+
+    async fn sorted_set_remove(&self, key: &str, member: &str) -> Result<()> {
+        self.write(&[key], |entries| {
+            if let Some(Entry {
+                value: Value::SortedSet(set),
+                ..
+            }) = entries.get_mut(key)
+            {
+                set.remove(member);
+            }
+        })
+        .await
+    }
+
+    async fn sorted_set_up_to(&self, key: &str, max_score: f64) -> Result<Vec<String>> {
+        Ok(self.read(key, |value| match value {
+            Some(Value::SortedSet(set)) => sorted_by_score(set)
+                .into_iter()
+                .filter(|(_, score)| *score <= max_score)
+                .map(|(member, _)| member)
+                .collect(),
+            _ => Vec::new(),
+        }))
+    }
+
+    async fn sorted_set_top(&self, key: &str, count: usize) -> Result<Vec<(String, f64)>> {
+        Ok(self.read(key, |value| match value {
+            Some(Value::SortedSet(set)) => {
+                sorted_by_score(set).into_iter().rev().take(count).collect()
+            }
+            _ => Vec::new(),
+        }))
+    }
+
+    async fn sorted_set_bottom(&self, key: &str, count: usize) -> Result<Vec<String>> {
+        Ok(self.read(key, |value| match value {
+            Some(Value::SortedSet(set)) => sorted_by_score(set)
+                .into_iter()
+                .take(count)
+                .map(|(member, _)| member)
+                .collect(),
+            _ => Vec::new(),
+        }))
+    }
+}
+
+/// Binary values as base64 strings, to keep the file compact.
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        bytes: &[u8],
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(serde::de::Error::custom)
+    }
+}
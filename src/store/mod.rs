@@ -0,0 +1,82 @@
+pub mod bot_state;
+mod file;
+mod redis;
+pub mod schema;
+
+use std::{collections::HashMap, env, sync::Arc};
+
+use async_trait::async_trait;
+
+pub use self::{file::FileStore, redis::RedisStore};
+use crate::error::Result;
+
+/// Where user state, conversation sessions, caches and schedules are kept.
+///
+/// The operations mirror the Redis data types: plain values with an optional
+/// expiry, hashes, capped lists and sorted sets. Keys and expiries are named
+/// in [`schema`].
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// The values of `keys`, in the same order.
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>>;
+    /// Stores `value`, expiring it after `ttl_secs` when given.
+    async fn set(&self, key: &str, value: &[u8], ttl_secs: Option<usize>) -> Result<()>;
+    async fn delete(&self, keys: &[String]) -> Result<()>;
+    /// Restarts the expiry of `key` at `ttl_secs`.
+    async fn expire(&self, key: &str, ttl_secs: usize) -> Result<()>;
+    /// Every key starting with `prefix`, fetched in batches so a big store
+    /// isn't blocked meanwhile.
+    async fn scan(&self, prefix: &str) -> Result<Vec<String>>;
+    /// Moves the value and expiry of `key` to `new_key`.
+    async fn rename(&self, key: &str, new_key: &str) -> Result<()>;
+    /// Adds `by` to the integer at `key`, missing counts as 0, and returns it.
+    async fn increment(&self, key: &str, by: i64) -> Result<i64>;
+
+    async fn hash_get(&self, key: &str, field: &str) -> Result<Option<String>>;
+    async fn hash_get_all(&self, key: &str) -> Result<HashMap<String, String>>;
+    async fn hash_set(&self, key: &str, fields: &[(String, String)]) -> Result<()>;
+    /// Removes `field`, returning whether it was there.
+    async fn hash_delete(&self, key: &str, field: &str) -> Result<bool>;
+
+    /// Appends `values`, keeping only the latest `max_len` items.
+    async fn list_push(&self, key: &str, values: &[String], max_len: usize) -> Result<()>;
+    async fn list_all(&self, key: &str) -> Result<Vec<String>>;
+
+    async fn sorted_set_add(&self, key: &str, member: &str, score: f64) -> Result<()>;
+    async fn sorted_set_increment(&self, key: &str, member: &str, by: f64) -> Result<()>;
+    async fn sorted_set_remove(&self, key: &str, member: &str) -> Result<()>;
+    /// The members scored at most `max_score`, lowest first.
+    async fn sorted_set_up_to(&self, key: &str, max_score: f64) -> Result<Vec<String>>;
+    /// The `count` highest scored members with their scores, highest first.
+    async fn sorted_set_top(&self, key: &str, count: usize) -> Result<Vec<(String, f64)>>;
+    /// The `count` lowest scored members, lowest first.
+    async fn sorted_set_bottom(&self, key: &str, count: usize) -> Result<Vec<String>>;
+}
+
+impl dyn StateStore + '_ {
+    pub async fn get_string(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .get(key)
+            .await?
+            .map(|it| String::from_utf8_lossy(&it).into_owned()))
+    }
+
+    pub async fn set_string(&self, key: &str, value: &str, ttl_secs: Option<usize>) -> Result<()> {
+        self.set(key, value.as_bytes(), ttl_secs).await
+    }
+}
+
+/// Picks the store from `STATE_STORE`: `redis` (the default, at `REDIS_URL`)
+/// or `fs` (the file `STATE_FILE`, default `./state.json`, for a single
+/// process without a Redis server), and checks its schema version.
+pub async fn from_env() -> Result<Arc<dyn StateStore>> {
+    let store: Arc<dyn StateStore> = match env::var("STATE_STORE").as_deref() {
+        Ok("fs") => Arc::new(FileStore::open(
+            env::var("STATE_FILE").unwrap_or_else(|_| "./state.json".to_string()),
+        )?),
+        _ => Arc::new(RedisStore::open(&env::var("REDIS_URL").unwrap()).await?),
+    };
+    schema::check_version(store.as_ref()).await?;
+    Ok(store)
+}
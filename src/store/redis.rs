@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+
+use super::StateStore;
+use crate::error::Result;
+
+/// Keeps everything in Redis, sharing one multiplexed connection.
+pub struct RedisStore {
+    connection: MultiplexedConnection,
+}
+
+impl RedisStore {
+    pub async fn open(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            connection: client.get_multiplexed_async_connection().await?,
+        })
+    }
+
+    fn connection(&self) -> MultiplexedConnection {
+        self.connection.clone()
+    }
+}
+
+#[async_trait]
+impl StateStore for RedisStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.connection().get(key).await?)
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        // `get` would send a single key as GET, whose reply isn't a list.
+        Ok(redis::cmd("MGET")
+            .arg(keys)
+            .query_async(&mut self.connection())
+            .await?)
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl_secs: Option<usize>) -> Result<()> {
+        let mut connection = self.connection();
+        match ttl_secs {
+            Some(ttl_secs) => connection.set_ex::<_, _, ()>(key, value, ttl_secs).await?,
+            None => connection.set::<_, _, ()>(key, value).await?,
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, keys: &[String]) -> Result<()> {
+        if !keys.is_empty() {
+            self.connection().del::<_, ()>(keys).await?;
+        }
+        Ok(())
+    }
+
+    async fn expire(&self, key: &str, ttl_secs: usize) -> Result<()> {
+        self.connection().expire::<_, ()>(key, ttl_secs).await?;
+        Ok(())
+    }
+
+    async fn scan(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut pattern = String::with_capacity(prefix.len() + 1);
+        for c in prefix.chars() {
+            if "*?[]\\".contains(c) {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('*');
+        let mut connection = self.connection();
+        let mut keys = connection.scan_match::<_, String>(pattern).await?;
+        let mut result = Vec::new();
+        while let Some(key) = keys.next_item().await {
+            result.push(key);
+        }
+        Ok(result)
+    }
+
+    async fn rename(&self, key: &str, new_key: &str) -> Result<()> {
+        self.connection().rename::<_, _, ()>(key, new_key).await?;
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, by: i64) -> Result<i64> {
+        Ok(self.connection().incr(key, by).await?)
+    }
+
+    async fn hash_get(&self, key: &str, field: &str) -> Result<Option<String>> {
+        Ok(self.connection().hget(key, field).await?)
+    }
+
+    async fn hash_get_all(&self, key: &str) -> Result<HashMap<String, String>> {
+        Ok(self.connection().hgetall(key).await?)
+    }
+
+    async fn hash_set(&self, key: &str, fields: &[(String, String)]) -> Result<()> {
+        if !fields.is_empty() {
+            self.connection()
+                .hset_multiple::<_, _, _, ()>(key, fields)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn hash_delete(&self, key: &str, field: &str) -> Result<bool> {
+        let removed: usize = self.connection().hdel(key, field).await?;
+        Ok(removed > 0)
+    }
+
+    async fn list_push(&self, key: &str, values: &[String], max_len: usize) -> Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let mut connection = self.connection();
+        connection.rpush::<_, _, ()>(key, values).await?;
+        connection
+            .ltrim::<_, ()>(key, -(max_len as isize), -1)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_all(&self, key: &str) -> Result<Vec<String>> {
+        Ok(self.connection().lrange(key, 0, -1).await?)
+    }
+
+    async fn sorted_set_add(&self, key: &str, member: &str, score: f64) -> Result<()> {
+        self.connection()
+            .zadd::<_, _, _, ()>(key, member, score)
+            .await?;
+        Ok(())
+    }
+
+    async fn sorted_set_increment(&self, key: &str, member: &str, by: f64) -> Result<()> {
+        self.connection()
+            .zincr::<_, _, _, ()>(key, member, by)
+            .await?;
+        Ok(())
+    }
+
+    async fn sorted_set_remove(&self, key: &str, member: &str) -> Result<()> {
+        self.connection().zrem::<_, _, ()>(key, member).await?;
+        Ok(())
+    }
+
+    async fn sorted_set_up_to(&self, key: &str, max_score: f64) -> Result<Vec<String>> {
+        Ok(self
+            .connection()
+            .zrangebyscore(key, "-inf", max_score)
+            .await?)
+    }
+
+    async fn sorted_set_top(&self, key: &str, count: usize) -> Result<Vec<(String, f64)>> {
+        Ok(self
+            .connection()
+            .zrevrange_withscores(key, 0, count as isize - 1)
+            .await?)
+    }
+
+    async fn sorted_set_bottom(&self, key: &str, count: usize) -> Result<Vec<String>> {
+        Ok(self.connection().zrange(key, 0, count as isize - 1).await?)
+    }
+}
@@ -0,0 +1,166 @@
+//! The keys everything is stored under, how long it is kept, and the version
+//! of this layout.
+
+use teloxide::types::{ChatId, MessageId};
+
+use super::StateStore;
+use crate::error::{Error, Result};
+
+/// The version of the layout below and of the `Bot` state, bumped when stored
+/// data changes shape.
+///
+/// Version 2 keeps the `Bot` state under [`bot_key`] in the envelope of
+/// [`super::bot_state`], without the Azure voices.
+pub const SCHEMA_VERSION: u32 = 2;
+const SCHEMA_VERSION_KEY: &str = "schema-version";
+
+pub const BOT_TTL_SECS: usize = 60 * 60 * 24 * 30;
+pub const CHAT_SESSION_TTL_SECS: usize = 60 * 60;
+pub const CARD_TTL_SECS: usize = 60 * 60 * 24 * 7;
+pub const QUIZ_SESSION_TTL_SECS: usize = 60 * 60;
+pub const WORD_TTL_SECS: usize = 60 * 60 * 24 * 30;
+pub const VOICE_FILE_TTL_SECS: usize = 60 * 60 * 24 * 30;
+pub const QUARANTINE_TTL_SECS: usize = 60 * 60 * 24 * 90;
+/// How long synthesized audio is kept unused, unless configured otherwise.
+pub const AUDIO_TTL_SECS: usize = 60 * 60 * 24 * 90;
+
+/// Sorted set of the chats with an active reminder, scored by when it is due.
+pub const REMINDERS_KEY: &str = "reminders";
+
+pub const BOT_KEY_PREFIX: &str = "bot-";
+
+/// The `Bot` state of a member, which is the chat in private chats.
+pub fn bot_key(member_id: ChatId) -> String {
+    format!("{BOT_KEY_PREFIX}{member_id}")
+}
+
+/// The member whose `Bot` state is at `key`, if it is such a key.
+pub fn bot_member_id(key: &str) -> Option<ChatId> {
+    key.strip_prefix(BOT_KEY_PREFIX)?.parse().ok().map(ChatId)
+}
+
+/// Unreadable `Bot` state, kept aside for a look by hand.
+pub fn quarantine_key(member_id: ChatId, at_ms: u64) -> String {
+    format!("quarantine-{member_id}-{at_ms}")
+}
+
+/// The conversation continued by replying to the bot's `message_id`.
+pub fn chat_session_key(chat_id: ChatId, message_id: MessageId) -> String {
+    format!("{chat_id}-{message_id}")
+}
+
+pub fn card_key(chat_id: ChatId, message_id: MessageId) -> String {
+    format!("card-{chat_id}-{message_id}")
+}
+
+pub fn quiz_session_key(chat_id: ChatId) -> String {
+    format!("quiz-{chat_id}")
+}
+
+/// List of history events.
+pub fn events_key(chat_id: ChatId) -> String {
+    format!("events-{chat_id}")
+}
+
+/// Hash of review schedules by vocabulary id.
+pub fn review_schedule_key(chat_id: ChatId) -> String {
+    format!("review-{chat_id}")
+}
+
+/// Hash of mistake records by mistake id.
+pub fn mistakes_key(chat_id: ChatId) -> String {
+    format!("mistakes-{chat_id}")
+}
+
+/// Hash of pronunciation histories by vocabulary id.
+pub fn pronunciation_key(chat_id: ChatId) -> String {
+    format!("pronunciation-{chat_id}")
+}
+
+/// Hash of member names by user id.
+pub fn group_members_key(chat_id: ChatId) -> String {
+    format!("group-members-{chat_id}")
+}
+
+/// Sorted set of user ids by correct quiz answers.
+pub fn quiz_leaderboard_key(chat_id: ChatId) -> String {
+    format!("quiz-leaderboard-{chat_id}")
+}
+
+pub fn prompt_template_key(name: &str) -> String {
+    format!("prompt-template-{name}")
+}
+
+/// A looked-up word, the same word for a different course or interface
+/// language is a different entry.
+pub fn word_key(language: &str, ui_language: &str, spell: &str) -> String {
+    format!(
+        "word-{language}-{ui_language}-{}",
+        spell.trim().to_lowercase()
+    )
+}
+
+/// The audio of the spelling and the example sentence of a word.
+pub fn word_voice_keys(word_key: &str) -> [String; 2] {
+    [
+        format!("{word_key}-spell-voice"),
+        format!("{word_key}-sentence-voice"),
+    ]
+}
+
+/// Synthesized audio, by the cache key of what was synthesized.
+pub fn audio_key(cache_key: &str) -> String {
+    format!("tts-audio-{cache_key}")
+}
+
+/// Sorted set of the cached audio, scored by its last use.
+pub const AUDIO_INDEX_KEY: &str = "tts-audio-index";
+/// Hash of the size of each cached audio.
+pub const AUDIO_SIZES_KEY: &str = "tts-audio-sizes";
+/// The sum of [`AUDIO_SIZES_KEY`].
+pub const AUDIO_BYTES_KEY: &str = "tts-audio-bytes";
+
+/// The Telegram `file_id` of uploaded audio, by its digest.
+pub fn voice_file_key(digest: &str) -> String {
+    format!("voice-file-{digest}")
+}
+
+/// Hash of an imported dictionary by lowercased word.
+pub fn dictionary_key(language: &str, ui_language: &str) -> String {
+    format!("dictionary-{language}-{ui_language}")
+}
+
+/// Brings an older store up to [`SCHEMA_VERSION`] and records it, so older
+/// builds refuse it, and refuses a store written by a newer version.
+///
+/// A store without a version predates versioning, which is version 1. The
+/// `Bot` state itself is migrated as it is read.
+pub async fn check_version(store: &dyn StateStore) -> Result<()> {
+    let version = match store.get_string(SCHEMA_VERSION_KEY).await? {
+        Some(version) => match version.parse::<u32>() {
+            Ok(version) if version <= SCHEMA_VERSION => version,
+            _ => return Err(Error::UnsupportedSchema(version)),
+        },
+        None => 1,
+    };
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+    if version < 2 {
+        move_bot_keys(store).await?;
+    }
+    let version = SCHEMA_VERSION.to_string();
+    store.set_string(SCHEMA_VERSION_KEY, &version, None).await
+}
+
+/// Version 1 kept the `Bot` state under the bare member id.
+async fn move_bot_keys(store: &dyn StateStore) -> Result<()> {
+    for key in store.scan("").await? {
+        if key.parse::<i64>().is_ok() {
+            store
+                .rename(&key, &format!("{BOT_KEY_PREFIX}{key}"))
+                .await?;
+        }
+    }
+    Ok(())
+}
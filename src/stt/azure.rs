@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::Deserialize;
+
+use super::SpeechRecognizer;
+use crate::{
+    tts::{stt_url, AzureTTS},
+    util::new_reqwest_client,
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Recognition {
+    recognition_status: String,
+    #[serde(default)]
+    display_text: String,
+}
+
+/// The same Azure Speech resource does both directions, so the recognizer
+/// reuses the TTS key, region and voice list (to find the locale).
+#[async_trait]
+impl SpeechRecognizer for AzureTTS {
+    async fn transcribe(&self, voice: &Bytes, language: &str) -> Option<String> {
+        let locale = self.voice_for(language)?.locale.clone();
+        let response = new_reqwest_client()
+            .post(stt_url(&self.region))
+            .query(&[("language", locale.as_str()), ("format", "simple")])
+            .header("Ocp-Apim-Subscription-Key", &self.subscription_key)
+            .header("Content-Type", "audio/ogg; codecs=opus")
+            .body(voice.clone())
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_success() {
+            println!("{:?}", &response);
+            return None;
+        }
+        let recognition: Recognition = response.json().await.ok()?;
+        if recognition.recognition_status != "Success" || recognition.display_text.is_empty() {
+            println!("Azure STT: {recognition:?}");
+            return None;
+        }
+        Some(recognition.display_text)
+    }
+}
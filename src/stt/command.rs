@@ -0,0 +1,62 @@
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use super::SpeechRecognizer;
+
+/// Runs a local engine such as whisper.cpp through `sh -c`.
+///
+/// The OGG/Opus voice note is written to the command's stdin and `{language}`
+/// in the command line is replaced by the ISO 639-1 code. The command must
+/// print the transcript to stdout, e.g.
+/// `ffmpeg -i - -ar 16000 -f wav /tmp/voice.wav -y && whisper-cli -m ggml-base.bin -l {language} -nt -np -f /tmp/voice.wav`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CommandSTT {
+    command: String,
+}
+
+impl CommandSTT {
+    pub fn new(command: impl ToString) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+
+    /// Reads the command line from `STT_COMMAND`, `None` if it is not set.
+    pub fn from_env() -> Option<Self> {
+        std::env::var("STT_COMMAND").ok().map(Self::new)
+    }
+}
+
+#[async_trait]
+impl SpeechRecognizer for CommandSTT {
+    async fn transcribe(&self, voice: &Bytes, language: &str) -> Option<String> {
+        if !language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return None;
+        }
+        let command_line = self.command.replace("{language}", language);
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&command_line)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut stdin = child.stdin.take()?;
+        stdin.write_all(voice).await.ok()?;
+        drop(stdin);
+        let output = child.wait_with_output().await.ok()?;
+        if !output.status.success() {
+            println!("`{command_line}` failed with {}", output.status);
+            return None;
+        }
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+}
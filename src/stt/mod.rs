@@ -0,0 +1,34 @@
+mod azure;
+mod command;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+pub use command::CommandSTT;
+
+#[async_trait]
+pub trait SpeechRecognizer: Send + Sync {
+    /// Transcribes an OGG/Opus voice note spoken in `language` (ISO 639-1 code),
+    /// or returns `None` when this engine can't do it right now.
+    async fn transcribe(&self, voice: &Bytes, language: &str) -> Option<String>;
+}
+
+/// Asks each engine in turn until one of them recognizes the speech.
+pub struct Fallback<'a>(pub Vec<&'a dyn SpeechRecognizer>);
+
+#[async_trait]
+impl SpeechRecognizer for Fallback<'_> {
+    async fn transcribe(&self, voice: &Bytes, language: &str) -> Option<String> {
+        for engine in &self.0 {
+            if let Some(text) = engine.transcribe(voice, language).await {
+                return Some(text);
+            }
+        }
+        None
+    }
+}
+
+/// Whether Azure should be tried at all, `STT_ENGINE=command` skips it.
+pub fn azure_enabled() -> bool {
+    std::env::var("STT_ENGINE").as_deref() != Ok("command")
+}
@@ -1,10 +1,11 @@
 use std::mem;
 
-use edge_gpt::NewBingResponseMessage;
 use regex::Regex;
 use teloxide::types::{MessageEntity, MessageEntityKind};
 
-pub fn fix_unordered_list(answer: &mut NewBingResponseMessage) {
+use crate::llm::ChatReply;
+
+pub fn fix_unordered_list(answer: &mut ChatReply) {
     answer.text.insert(0, '\n');
     let re = Regex::new("\n[-]").unwrap();
     answer.text = re.replace_all(&answer.text, "\n•").to_string();
@@ -53,7 +54,7 @@ impl DetachedMatch {
     }
 }
 
-pub fn fix_attributions(answer: &mut NewBingResponseMessage, entries: &mut Vec<MessageEntity>) {
+pub fn fix_attributions(answer: &mut ChatReply, entries: &mut Vec<MessageEntity>) {
     let mut text = mem::take(&mut answer.text);
     let re = Regex::new(r"\[\^(\d+)\^\]").unwrap();
 
@@ -78,7 +79,7 @@ pub fn fix_attributions(answer: &mut NewBingResponseMessage, entries: &mut Vec<M
     answer.text = text;
 }
 
-pub fn fix_bold(answer: &mut NewBingResponseMessage, entries: &mut Vec<MessageEntity>) {
+pub fn fix_bold(answer: &mut ChatReply, entries: &mut Vec<MessageEntity>) {
     let mut text = mem::take(&mut answer.text);
     let re = Regex::new(r"\*\*([^\*]+)\*\*").unwrap();
     while let Some(m) = re.find(&text).map(DetachedMatch::from) {
@@ -1,73 +1,374 @@
 mod format;
 use bytes::Bytes;
 
-use reqwest::multipart::{Form, Part};
-use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use reqwest::{
+    multipart::{Form, Part},
+    RequestBuilder,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
 use teloxide::{
-    payloads::{SendChatAction, SendMessage},
-    types::{ChatAction, ChatId, Message, ParseMode},
+    payloads::{
+        AnswerCallbackQuery, EditMessageReplyMarkup, EditMessageText, GetFile, GetUpdates,
+        SendChatAction, SendMessage, SetMyCommands,
+    },
+    types::{
+        BotCommand, ChatAction, ChatId, File, Message, ParseMode, Recipient, ResponseParameters,
+        Update,
+    },
 };
 use tokio::{
     sync::broadcast::{self, Sender},
-    time::interval,
+    time::{interval, sleep},
 };
 
-use crate::util::new_reqwest_client;
+use crate::{
+    error::{Error, Result},
+    util::new_reqwest_client,
+};
 
 pub use format::*;
+
+/// Attempts of a call that Telegram keeps answering with 429 Too Many Requests.
+const MAX_ATTEMPTS: u32 = 3;
+/// Longer waits asked for by Telegram fail the call instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Spacing between messages to one chat, Telegram allows about one a second
+/// in private chats and twenty a minute in groups.
+const PRIVATE_CHAT_INTERVAL: Duration = Duration::from_secs(1);
+const GROUP_CHAT_INTERVAL: Duration = Duration::from_secs(3);
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Telegram {
     pub token: String,
+    /// The Bot API server, `TELEGRAM_API_URL` or `https://api.telegram.org`,
+    /// so a self-hosted `telegram-bot-api` or a fake one in tests can be used.
+    #[serde(skip, default = "api_url_from_env")]
+    pub api_url: String,
+    /// The forum topic that messages are sent to, if any.
+    #[serde(skip)]
+    pub thread_id: Option<i32>,
+}
+
+const DEFAULT_API_URL: &str = "https://api.telegram.org";
+
+fn api_url_from_env() -> String {
+    std::env::var("TELEGRAM_API_URL")
+        .map(|it| it.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| DEFAULT_API_URL.to_string())
+}
+
+/// What files are sent as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadKind {
+    Photo,
+    Audio,
+    Document,
+}
+
+/// A file to upload.
+#[derive(Clone, Debug)]
+pub struct Upload {
+    pub kind: UploadKind,
+    pub file_name: String,
+    pub data: Bytes,
+    pub caption: Option<String>,
+}
+
+impl Upload {
+    pub fn new(kind: UploadKind, file_name: impl ToString, data: impl Into<Bytes>) -> Self {
+        Self {
+            kind,
+            file_name: file_name.to_string(),
+            data: data.into(),
+            caption: None,
+        }
+    }
+
+    fn part(&self) -> Part {
+        Part::stream(self.data.clone()).file_name(self.file_name.clone())
+    }
+}
+
+/// The envelope of every Bot API reply.
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    #[serde(default)]
+    result: serde_json::Value,
+    #[serde(default)]
+    description: String,
+    error_code: Option<u16>,
+    parameters: Option<ResponseParameters>,
+}
+
+/// One connection pool shared by all calls.
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(new_reqwest_client)
+}
+
+/// Waits until another message may be sent to `chat_id`.
+async fn throttle(chat_id: ChatId) {
+    static NEXT_SEND: OnceLock<Mutex<HashMap<ChatId, Instant>>> = OnceLock::new();
+    let interval = if chat_id.is_user() {
+        PRIVATE_CHAT_INTERVAL
+    } else {
+        GROUP_CHAT_INTERVAL
+    };
+    let wait = {
+        let mut next_send = NEXT_SEND.get_or_init(Default::default).lock().unwrap();
+        let now = Instant::now();
+        next_send.retain(|_, it| *it > now);
+        let at = next_send.get(&chat_id).copied().unwrap_or(now);
+        next_send.insert(chat_id, at + interval);
+        at - now
+    };
+    if !wait.is_zero() {
+        sleep(wait).await;
+    }
 }
 
 impl Telegram {
     pub fn new(token: impl ToString) -> Self {
         Self {
             token: token.to_string(),
+            api_url: api_url_from_env(),
+            thread_id: None,
         }
     }
 
+    pub fn with_api_url(mut self, api_url: impl ToString) -> Self {
+        self.api_url = api_url.to_string().trim_end_matches('/').to_string();
+        self
+    }
+
     pub fn from_env() -> Self {
         Self::new(std::env::var("TELEGRAM_TOKEN").unwrap())
     }
 
-    pub async fn send_message(&self, message: &SendMessage) -> Message {
-        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.token);
-        let result = new_reqwest_client()
-            .post(&url)
-            .json(message)
-            .send()
-            .await
-            .unwrap();
-        if !result.status().is_success() {
-            println!("{:?}", &result);
+    /// Calls `method`, waiting and retrying when rate limited. Calls that send
+    /// to `chat_id` are spaced out per chat.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        chat_id: Option<ChatId>,
+        build: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<T> {
+        let url = format!("{}/bot{}/{method}", self.api_url, self.token);
+        let mut attempt = 1;
+        loop {
+            if let Some(chat_id) = chat_id {
+                throttle(chat_id).await;
+            }
+            let response: ApiResponse = build(client().post(&url)).send().await?.json().await?;
+            if response.ok {
+                return Ok(serde_json::from_value(response.result)?);
+            }
+            match response.parameters {
+                Some(ResponseParameters::RetryAfter(retry_after))
+                    if response.error_code == Some(429) =>
+                {
+                    if attempt >= MAX_ATTEMPTS || retry_after > MAX_RETRY_AFTER {
+                        return Err(Error::TelegramRateLimited {
+                            method,
+                            retry_after,
+                        });
+                    }
+                    println!("Telegram asked to retry {method} after {retry_after:?}");
+                    sleep(retry_after).await;
+                    attempt += 1;
+                }
+                Some(ResponseParameters::MigrateToChatId(new_chat_id)) => {
+                    return Err(Error::Telegram {
+                        method,
+                        description: format!(
+                            "{} (the chat is now {new_chat_id})",
+                            response.description
+                        ),
+                    });
+                }
+                _ => {
+                    return Err(Error::Telegram {
+                        method,
+                        description: response.description,
+                    });
+                }
+            }
         }
-        let result: serde_json::Value = result.json().await.unwrap();
-        serde_json::from_value(result["result"].clone()).unwrap()
-    }
-
-    pub async fn send_voice(&self, chat_id: ChatId, voice: &Bytes) {
-        let url = format!("https://api.telegram.org/bot{}/sendVoice", self.token);
-        let part = Part::bytes(voice.to_vec()).file_name("voice.ogg");
-        let form = Form::new()
-            .text("chat_id", chat_id.to_string())
-            .part("voice", part)
-            .text("disable_notification", "true");
-        let result = new_reqwest_client()
-            .post(&url)
-            .multipart(form)
-            .send()
+    }
+
+    async fn call_json<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        chat_id: Option<ChatId>,
+        payload: &impl Serialize,
+    ) -> Result<T> {
+        self.call(method, chat_id, |it| it.json(payload)).await
+    }
+
+    /// A multipart call to `chat_id`, in the current topic.
+    async fn call_multipart<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        chat_id: ChatId,
+        form: impl Fn() -> Form,
+    ) -> Result<T> {
+        self.call(method, Some(chat_id), |it| {
+            let form = form().text("chat_id", chat_id.to_string());
+            let form = match self.thread_id {
+                Some(thread_id) => form.text("message_thread_id", thread_id.to_string()),
+                None => form,
+            };
+            it.multipart(form)
+        })
+        .await
+    }
+
+    pub async fn send_message(&self, message: &SendMessage) -> Result<Message> {
+        let mut message = message.clone();
+        message.message_thread_id = message.message_thread_id.or(self.thread_id);
+        let chat_id = match &message.chat_id {
+            Recipient::Id(chat_id) => Some(*chat_id),
+            Recipient::ChannelUsername(_) => None,
+        };
+        self.call_json("sendMessage", chat_id, &message).await
+    }
+
+    pub async fn send_voice(&self, chat_id: ChatId, voice: &Bytes) -> Result<Message> {
+        self.call_multipart("sendVoice", chat_id, || {
+            Form::new()
+                .part("voice", Part::stream(voice.clone()).file_name("voice.ogg"))
+                .text("disable_notification", "true")
+        })
+        .await
+    }
+
+    /// Sends a voice note that was uploaded before, by its `file_id`.
+    pub async fn send_voice_by_file_id(&self, chat_id: ChatId, file_id: &str) -> Result<Message> {
+        let payload = serde_json::json!({
+            "chat_id": chat_id,
+            "voice": file_id,
+            "disable_notification": true,
+            "message_thread_id": self.thread_id,
+        });
+        self.call_json("sendVoice", Some(chat_id), &payload).await
+    }
+
+    /// Sends a photo, audio file or document.
+    pub async fn send_upload(&self, chat_id: ChatId, upload: &Upload) -> Result<Message> {
+        let (method, field) = match upload.kind {
+            UploadKind::Photo => ("sendPhoto", "photo"),
+            UploadKind::Audio => ("sendAudio", "audio"),
+            UploadKind::Document => ("sendDocument", "document"),
+        };
+        self.call_multipart(method, chat_id, || {
+            let form = Form::new().part(field, upload.part());
+            match &upload.caption {
+                Some(caption) => form.text("caption", caption.clone()),
+                None => form,
+            }
+        })
+        .await
+    }
+
+    pub async fn send_photo(
+        &self,
+        chat_id: ChatId,
+        photo: Vec<u8>,
+        caption: Option<String>,
+    ) -> Result<Message> {
+        let mut upload = Upload::new(UploadKind::Photo, "photo.png", photo);
+        upload.caption = caption;
+        self.send_upload(chat_id, &upload).await
+    }
+
+    pub async fn edit_message_text(&self, message: &EditMessageText) -> Result<Message> {
+        self.call_json("editMessageText", None, message).await
+    }
+
+    pub async fn edit_message_reply_markup(
+        &self,
+        message: &EditMessageReplyMarkup,
+    ) -> Result<Message> {
+        self.call_json("editMessageReplyMarkup", None, message)
             .await
-            .unwrap();
-        if !result.status().is_success() {
-            println!("{:?}", result);
+    }
+
+    pub async fn answer_callback_query(
+        &self,
+        callback_query_id: &str,
+        text: Option<String>,
+    ) -> Result<bool> {
+        let mut payload = AnswerCallbackQuery::new(callback_query_id);
+        payload.text = text;
+        self.call_json("answerCallbackQuery", None, &payload).await
+    }
+
+    pub async fn get_updates(&self, offset: Option<i32>, timeout: u32) -> Result<Vec<Update>> {
+        let mut payload = GetUpdates::new();
+        payload.offset = offset;
+        payload.timeout = Some(timeout);
+        self.call_json("getUpdates", None, &payload).await
+    }
+
+    pub async fn get_file(&self, file_id: &str) -> Result<File> {
+        self.call_json("getFile", None, &GetFile::new(file_id))
+            .await
+    }
+
+    /// Downloads a file previously returned by [`Telegram::get_file`].
+    ///
+    /// A server running with `--local` answers with absolute paths on its
+    /// disk instead, those are read directly.
+    pub async fn download_file(&self, file: &File) -> Result<Bytes> {
+        if file.path.starts_with('/') {
+            return tokio::fs::read(&file.path)
+                .await
+                .map(Bytes::from)
+                .map_err(|error| Error::Telegram {
+                    method: "downloadFile",
+                    description: error.to_string(),
+                });
         }
+        let url = format!("{}/file/bot{}/{}", self.api_url, self.token, file.path);
+        let response = client().get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Telegram {
+                method: "downloadFile",
+                description: response.status().to_string(),
+            });
+        }
+        Ok(response.bytes().await?)
+    }
+
+    pub async fn set_webhook(&self, url: &str, secret_token: Option<&str>) -> Result<bool> {
+        let mut payload = serde_json::json!({ "url": url });
+        if let Some(secret_token) = secret_token {
+            payload["secret_token"] = secret_token.into();
+        }
+        self.call_json("setWebhook", None, &payload).await
+    }
+
+    /// Removes the webhook, which `getUpdates` refuses to work alongside.
+    pub async fn delete_webhook(&self) -> Result<bool> {
+        self.call_json("deleteWebhook", None, &serde_json::json!({}))
+            .await
+    }
+
+    /// Sets the command list shown in Telegram's menu.
+    pub async fn set_my_commands(&self, commands: Vec<BotCommand>) -> Result<bool> {
+        self.call_json("setMyCommands", None, &SetMyCommands::new(commands))
+            .await
     }
 
     pub fn start_sending_typing_status(&self, chat_id: ChatId) -> Sender<()> {
         let (stop_typing_action_tx, mut stop_typing_action_rx) = broadcast::channel(1);
-        let token = self.token.clone();
+        let telegram = self.clone();
         tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(5));
             loop {
@@ -76,13 +377,10 @@ impl Telegram {
                         break;
                     }
                     _ = interval.tick() => {
-                        let message = SendChatAction::new(chat_id, ChatAction::Typing);
-                        new_reqwest_client()
-                            .post(format!("https://api.telegram.org/bot{token}/sendChatAction"))
-                            .json(&message)
-                            .send()
-                            .await
-                            .unwrap();
+                        let mut message = SendChatAction::new(chat_id, ChatAction::Typing);
+                        message.message_thread_id = telegram.thread_id;
+                        let _: Result<bool> =
+                            telegram.call_json("sendChatAction", None, &message).await;
                     }
                 }
             }
@@ -91,16 +389,53 @@ impl Telegram {
     }
 }
 
+/// The command name of `/command` or `/command@bot_name`, or `None` when it is
+/// addressed to another bot than `TELEGRAM_BOT_USERNAME`.
+pub fn command_name(command: &str) -> Option<&str> {
+    let Some((name, bot_name)) = command.split_once('@') else {
+        return Some(command);
+    };
+    match std::env::var("TELEGRAM_BOT_USERNAME") {
+        Ok(username) if !username.eq_ignore_ascii_case(bot_name) => None,
+        _ => Some(name),
+    }
+}
+
+/// The characters MarkdownV2 reserves, which must be escaped anywhere.
+const MARKDOWN_RESERVED: &str = "\\_*[]()~`>#+-=|{}.!\"";
+
+/// Escapes `text` for MarkdownV2, so it shows as written.
 pub fn escape(text: &str) -> String {
-    text.replace('\"', "\\\"")
-        .replace('{', "\\{")
-        .replace('}', "\\}")
-        .replace('_', "\\_")
-        .replace('.', "\\.")
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MARKDOWN_RESERVED.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
+
 pub fn simple_respond_message(to_message: &Message, text: &str) -> SendMessage {
     let mut result = SendMessage::new(to_message.chat.id, escape(text));
     result.reply_to_message_id = Some(to_message.id);
     result.parse_mode = Some(ParseMode::MarkdownV2);
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_covers_every_reserved_character() {
+        let reserved = "_*[]()~`>#+-=|{}.!\\";
+        let escaped = escape(reserved);
+        let expected = reserved
+            .chars()
+            .map(|it| format!("\\{it}"))
+            .collect::<String>();
+        assert_eq!(escaped, expected);
+        assert_eq!(escape("hej på dig"), "hej på dig");
+    }
+}
@@ -0,0 +1,86 @@
+use serde_json::json;
+use teloxide::types::ChatId;
+
+use super::{flows::state, Harness};
+use crate::{
+    error::Error,
+    store::{bot_state, schema},
+};
+
+const REMIND: &str = "/remind 09:00 Europe/Stockholm";
+
+#[tokio::test]
+async fn version_1_state_is_moved_and_keeps_the_login() {
+    let harness = Harness::start().await;
+    harness.log_in(106, 106).await;
+    let mut bare = state(&harness, 106);
+    bare["azure_tts"] = json!({"subscription_key": "secret", "voices": []});
+    let store = harness.store.as_ref();
+    store.delete(&["bot-106".to_string()]).await.unwrap();
+    store
+        .set_string("106", &bare.to_string(), None)
+        .await
+        .unwrap();
+    store.set_string("schema-version", "1", None).await.unwrap();
+
+    schema::check_version(store).await.unwrap();
+    assert_eq!(harness.redis.string("schema-version").unwrap(), "2");
+    assert!(harness.redis.string("106").is_none());
+    harness.send_text(106, 106, REMIND, None).await.unwrap();
+
+    let bot = state(&harness, 106);
+    assert_eq!(bot["duolingo"]["active_language"], "sv");
+    assert_eq!(bot["reminder"]["minute"], 9 * 60);
+    assert!(bot.get("azure_tts").is_none());
+}
+
+#[tokio::test]
+async fn unreadable_state_is_quarantined() {
+    let harness = Harness::start().await;
+    let broken = r#"{"duolingo": 1}"#;
+    harness
+        .store
+        .set_string("bot-107", broken, None)
+        .await
+        .unwrap();
+
+    harness.send_text(107, 107, REMIND, None).await.unwrap();
+
+    let quarantined = harness.redis.keys("quarantine-107-");
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(harness.redis.string(&quarantined[0]).unwrap(), broken);
+    assert!(state(&harness, 107)["duolingo"].is_null());
+}
+
+#[tokio::test]
+async fn newer_state_is_left_alone() {
+    let harness = Harness::start().await;
+    let newer = r#"{"version": 99, "bot": {}}"#;
+    harness
+        .store
+        .set_string("bot-108", newer, None)
+        .await
+        .unwrap();
+
+    let result = harness.send_text(108, 108, REMIND, None).await;
+
+    assert!(matches!(result, Err(Error::UnsupportedSchema(_))));
+    assert_eq!(harness.redis.string("bot-108").unwrap(), newer);
+}
+
+#[tokio::test]
+async fn reading_another_members_state_leaves_it_alone() {
+    let harness = Harness::start().await;
+    let broken = r#"{"duolingo": 1}"#;
+    harness
+        .store
+        .set_string("bot-115", broken, None)
+        .await
+        .unwrap();
+
+    let bot = bot_state::read(harness.store.as_ref(), ChatId(115)).await;
+
+    assert!(bot.unwrap().is_none());
+    assert_eq!(harness.redis.string("bot-115").unwrap(), broken);
+    assert!(harness.redis.keys("quarantine-").is_empty());
+}
@@ -0,0 +1,136 @@
+use std::{
+    convert::Infallible,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Response, Server,
+};
+
+/// A request received by a [`FakeHttp`].
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub path: String,
+    pub query: String,
+    pub body: Bytes,
+}
+
+impl Request {
+    /// A parameter of the query string, without percent-decoding.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .find_map(|it| it.strip_prefix(name)?.strip_prefix('='))
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+
+    /// A text field of a `multipart/form-data` body.
+    pub fn form_field(&self, name: &str) -> Option<String> {
+        let body = String::from_utf8_lossy(&self.body);
+        let header = format!("name=\"{name}\"\r\n\r\n");
+        let start = body.find(&header)? + header.len();
+        let end = start + body[start..].find("\r\n--")?;
+        Some(body[start..end].to_string())
+    }
+
+    /// A field of either a JSON or a multipart body, as JSON.
+    pub fn field(&self, name: &str) -> serde_json::Value {
+        match self.json().get(name) {
+            Some(value) => value.clone(),
+            None => self
+                .form_field(name)
+                .map(|it| serde_json::from_str(&it).unwrap_or(serde_json::Value::String(it)))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub struct Reply {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    pub fn json(value: serde_json::Value) -> Self {
+        Self {
+            status: 200,
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    pub fn bytes(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            body: body.into(),
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            body: Vec::new(),
+        }
+    }
+}
+
+/// A local HTTP server answering with `handler` and recording every request.
+pub struct FakeHttp {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl FakeHttp {
+    pub fn start(handler: impl Fn(&Request) -> Reply + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+        let recorded = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let handler = handler.clone();
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let handler = handler.clone();
+                    let recorded = recorded.clone();
+                    async move {
+                        let (parts, body) = request.into_parts();
+                        let request = Request {
+                            path: parts.uri.path().to_string(),
+                            query: parts.uri.query().unwrap_or_default().to_string(),
+                            body: hyper::body::to_bytes(body).await.unwrap_or_default(),
+                        };
+                        let reply = handler(&request);
+                        recorded.lock().unwrap().push(request);
+                        let mut response = Response::new(Body::from(reply.body));
+                        *response.status_mut() = reply.status.try_into().unwrap();
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+        let server = Server::from_tcp(listener).unwrap().serve(make_service);
+        tokio::spawn(server);
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The requests whose path ends with `/{name}`, such as a Bot API method.
+    pub fn calls(&self, name: &str) -> Vec<Request> {
+        let suffix = format!("/{name}");
+        self.requests()
+            .into_iter()
+            .filter(|it| it.path.ends_with(&suffix))
+            .collect()
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    String(Vec<u8>),
+    Hash(BTreeMap<Vec<u8>, Vec<u8>>),
+    List(VecDeque<Vec<u8>>),
+    /// Members with their scores, kept sorted by score then member.
+    SortedSet(Vec<(f64, Vec<u8>)>),
+}
+
+enum Reply {
+    Ok,
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Option<Vec<u8>>>),
+    /// A whole `SCAN`, the final cursor and the keys.
+    Scan(Vec<Option<Vec<u8>>>),
+    Error(String),
+}
+
+type Data = Arc<Mutex<HashMap<Vec<u8>, Value>>>;
+
+/// An in-memory server speaking enough of the Redis protocol for the bot.
+/// Expiry times are accepted and ignored.
+pub struct FakeRedis {
+    pub url: String,
+    data: Data,
+}
+
+impl FakeRedis {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}/", listener.local_addr().unwrap());
+        let data = Data::default();
+        let served = data.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, served.clone()));
+            }
+        });
+        Self { url, data }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.data.lock().unwrap().get(key.as_bytes()).cloned()
+    }
+
+    pub fn string(&self, key: &str) -> Option<String> {
+        match self.get(key)? {
+            Value::String(value) => Some(String::from_utf8(value).unwrap()),
+            other => panic!("{key} holds {other:?}"),
+        }
+    }
+
+    pub fn hash(&self, key: &str) -> BTreeMap<String, String> {
+        match self.get(key) {
+            Some(Value::Hash(hash)) => hash
+                .into_iter()
+                .map(|(field, value)| {
+                    (
+                        String::from_utf8(field).unwrap(),
+                        String::from_utf8(value).unwrap(),
+                    )
+                })
+                .collect(),
+            None => BTreeMap::new(),
+            Some(other) => panic!("{key} holds {other:?}"),
+        }
+    }
+
+    pub fn list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::List(list)) => list
+                .into_iter()
+                .map(|it| String::from_utf8(it).unwrap())
+                .collect(),
+            None => Vec::new(),
+            Some(other) => panic!("{key} holds {other:?}"),
+        }
+    }
+
+    pub fn sorted_set(&self, key: &str) -> Vec<(String, f64)> {
+        match self.get(key) {
+            Some(Value::SortedSet(set)) => set
+                .into_iter()
+                .map(|(score, member)| (String::from_utf8(member).unwrap(), score))
+                .collect(),
+            None => Vec::new(),
+            Some(other) => panic!("{key} holds {other:?}"),
+        }
+    }
+
+    /// The keys starting with `prefix`.
+    pub fn keys(&self, prefix: &str) -> Vec<String> {
+        let mut keys = self
+            .data
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|it| it.starts_with(prefix.as_bytes()))
+            .map(|it| String::from_utf8(it.clone()).unwrap())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+}
+
+async fn serve(stream: TcpStream, data: Data) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    while let Some(command) = read_command(&mut reader).await {
+        let reply = execute(&data, &command);
+        let mut out = Vec::new();
+        write_reply(&mut out, reply);
+        if writer.write_all(&out).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn read_line(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>) -> Option<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await.ok()? == 0 {
+        return None;
+    }
+    Some(line.trim_end().to_string())
+}
+
+async fn read_command(
+    reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>,
+) -> Option<Vec<Vec<u8>>> {
+    let header = read_line(reader).await?;
+    let count: usize = header.strip_prefix('*')?.parse().ok()?;
+    let mut arguments = Vec::with_capacity(count);
+    for _ in 0..count {
+        let length: usize = read_line(reader).await?.strip_prefix('$')?.parse().ok()?;
+        let mut argument = vec![0; length + 2];
+        reader.read_exact(&mut argument).await.ok()?;
+        argument.truncate(length);
+        arguments.push(argument);
+    }
+    Some(arguments)
+}
+
+fn write_reply(out: &mut Vec<u8>, reply: Reply) {
+    fn bulk(out: &mut Vec<u8>, value: Option<Vec<u8>>) {
+        match value {
+            Some(value) => {
+                out.extend(format!("${}\r\n", value.len()).into_bytes());
+                out.extend(value);
+                out.extend(b"\r\n");
+            }
+            None => out.extend(b"$-1\r\n"),
+        }
+    }
+    match reply {
+        Reply::Ok => out.extend(b"+OK\r\n"),
+        Reply::Integer(value) => out.extend(format!(":{value}\r\n").into_bytes()),
+        Reply::Bulk(value) => bulk(out, value),
+        Reply::Array(values) => {
+            out.extend(format!("*{}\r\n", values.len()).into_bytes());
+            for value in values {
+                bulk(out, value);
+            }
+        }
+        Reply::Scan(keys) => {
+            out.extend(b"*2\r\n");
+            bulk(out, Some(b"0".to_vec()));
+            write_reply(out, Reply::Array(keys));
+        }
+        Reply::Error(message) => out.extend(format!("-ERR {message}\r\n").into_bytes()),
+    }
+}
+
+fn text(argument: &[u8]) -> String {
+    String::from_utf8_lossy(argument).to_string()
+}
+
+fn score_text(score: f64) -> Vec<u8> {
+    if score.fract() == 0.0 {
+        format!("{}", score as i64).into_bytes()
+    } else {
+        score.to_string().into_bytes()
+    }
+}
+
+fn parse_score(argument: &[u8]) -> f64 {
+    match text(argument).as_str() {
+        "-inf" => f64::NEG_INFINITY,
+        "+inf" | "inf" => f64::INFINITY,
+        other => other.parse().unwrap_or_default(),
+    }
+}
+
+/// Resolves Redis' inclusive, possibly negative, `start` and `stop` over `len` items.
+fn range(len: usize, start: &[u8], stop: &[u8]) -> std::ops::Range<usize> {
+    let resolve = |index: &[u8]| {
+        let index: i64 = text(index).parse().unwrap_or_default();
+        if index < 0 {
+            (len as i64 + index).max(-1)
+        } else {
+            index
+        }
+    };
+    let start = resolve(start).max(0) as usize;
+    let stop = (resolve(stop) + 1).min(len as i64).max(0) as usize;
+    start.min(stop)..stop
+}
+
+fn matches(pattern: &[u8], key: &[u8]) -> bool {
+    match (pattern.split_first(), key.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            matches(rest, key) || (!key.is_empty() && matches(pattern, &key[1..]))
+        }
+        (Some((b'?', rest)), Some((_, key_rest))) => matches(rest, key_rest),
+        (Some((b'\\', [escaped, rest @ ..])), Some((actual, key_rest))) => {
+            escaped == actual && matches(rest, key_rest)
+        }
+        (Some((expected, rest)), Some((actual, key_rest))) => {
+            expected == actual && matches(rest, key_rest)
+        }
+        _ => false,
+    }
+}
+
+fn execute(data: &Data, command: &[Vec<u8>]) -> Reply {
+    let mut data = data.lock().unwrap();
+    let name = text(&command[0]).to_uppercase();
+    let args = &command[1..];
+    macro_rules! typed {
+        ($key:expr, $variant:ident, $default:expr) => {{
+            let value = data
+                .entry($key.clone())
+                .or_insert_with(|| Value::$variant($default));
+            match value {
+                Value::$variant(inner) => inner,
+                _ => return Reply::Error("WRONGTYPE".to_string()),
+            }
+        }};
+    }
+    match (name.as_str(), args) {
+        ("PING", _) => Reply::Bulk(Some(b"PONG".to_vec())),
+        ("GET", [key]) => match data.get(key) {
+            Some(Value::String(value)) => Reply::Bulk(Some(value.clone())),
+            _ => Reply::Bulk(None),
+        },
+        ("MGET", keys) => Reply::Array(
+            keys.iter()
+                .map(|key| match data.get(key) {
+                    Some(Value::String(value)) => Some(value.clone()),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        ("SET", [key, value, ..]) | ("SETEX", [key, _, value]) => {
+            data.insert(key.clone(), Value::String(value.clone()));
+            Reply::Ok
+        }
+        ("INCRBY", [key, increment]) => {
+            let value = match data.get(key) {
+                Some(Value::String(value)) => text(value).parse().unwrap_or_default(),
+                _ => 0,
+            } + text(increment).parse::<i64>().unwrap_or_default();
+            data.insert(key.clone(), Value::String(value.to_string().into_bytes()));
+            Reply::Integer(value)
+        }
+        ("DEL", keys) => Reply::Integer(
+            keys.iter()
+                .filter(|key| data.remove(*key).is_some())
+                .count() as i64,
+        ),
+        ("EXPIRE", [key, _]) => Reply::Integer(data.contains_key(key) as i64),
+        ("KEYS", [pattern]) => Reply::Array(
+            data.keys()
+                .filter(|key| matches(pattern, key))
+                .map(|key| Some(key.clone()))
+                .collect(),
+        ),
+        ("SCAN", [_, options @ ..]) => {
+            let pattern = options
+                .chunks(2)
+                .find(|it| text(&it[0]).eq_ignore_ascii_case("match"))
+                .and_then(|it| it.get(1))
+                .map_or(b"*".as_slice(), Vec::as_slice);
+            Reply::Scan(
+                data.keys()
+                    .filter(|key| matches(pattern, key))
+                    .map(|key| Some(key.clone()))
+                    .collect(),
+            )
+        }
+        ("RENAME", [key, new_key]) => match data.remove(key) {
+            Some(value) => {
+                data.insert(new_key.clone(), value);
+                Reply::Ok
+            }
+            None => Reply::Error("no such key".to_string()),
+        },
+        ("HGET", [key, field]) => match data.get(key) {
+            Some(Value::Hash(hash)) => Reply::Bulk(hash.get(field).cloned()),
+            _ => Reply::Bulk(None),
+        },
+        ("HMGET", [key, fields @ ..]) => {
+            let hash = match data.get(key) {
+                Some(Value::Hash(hash)) => hash.clone(),
+                _ => BTreeMap::new(),
+            };
+            Reply::Array(fields.iter().map(|it| hash.get(it).cloned()).collect())
+        }
+        ("HSET" | "HMSET", [key, pairs @ ..]) => {
+            let hash = typed!(key, Hash, BTreeMap::new());
+            let added = pairs
+                .chunks(2)
+                .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+                .count();
+            if name == "HMSET" {
+                Reply::Ok
+            } else {
+                Reply::Integer(added as i64)
+            }
+        }
+        ("HDEL", [key, fields @ ..]) => {
+            let hash = typed!(key, Hash, BTreeMap::new());
+            Reply::Integer(
+                fields
+                    .iter()
+                    .filter(|it| hash.remove(*it).is_some())
+                    .count() as i64,
+            )
+        }
+        ("HGETALL" | "HVALS", [key]) => {
+            let hash = match data.get(key) {
+                Some(Value::Hash(hash)) => hash.clone(),
+                _ => BTreeMap::new(),
+            };
+            Reply::Array(if name == "HVALS" {
+                hash.into_values().map(Some).collect()
+            } else {
+                hash.into_iter()
+                    .flat_map(|(field, value)| [Some(field), Some(value)])
+                    .collect()
+            })
+        }
+        ("RPUSH", [key, values @ ..]) => {
+            let list = typed!(key, List, VecDeque::new());
+            list.extend(values.iter().cloned());
+            Reply::Integer(list.len() as i64)
+        }
+        ("LTRIM", [key, start, stop]) => {
+            let list = typed!(key, List, VecDeque::new());
+            let kept = range(list.len(), start, stop);
+            *list = list.drain(kept).collect();
+            Reply::Ok
+        }
+        ("LRANGE", [key, start, stop]) => match data.get(key) {
+            Some(Value::List(list)) => Reply::Array(
+                list.range(range(list.len(), start, stop))
+                    .map(|it| Some(it.clone()))
+                    .collect(),
+            ),
+            _ => Reply::Array(Vec::new()),
+        },
+        ("ZADD" | "ZINCRBY", [key, pairs @ ..]) => {
+            let set = typed!(key, SortedSet, Vec::new());
+            let mut added = 0;
+            let mut last_score = 0.0;
+            for pair in pairs.chunks(2) {
+                let (score, member) = (parse_score(&pair[0]), &pair[1]);
+                let previous = set
+                    .iter()
+                    .position(|(_, it)| it == member)
+                    .map(|index| set.remove(index).0);
+                if previous.is_none() {
+                    added += 1;
+                }
+                last_score = if name == "ZINCRBY" {
+                    previous.unwrap_or_default() + score
+                } else {
+                    score
+                };
+                set.push((last_score, member.clone()));
+            }
+            set.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+            if name == "ZINCRBY" {
+                Reply::Bulk(Some(score_text(last_score)))
+            } else {
+                Reply::Integer(added)
+            }
+        }
+        ("ZREM", [key, members @ ..]) => {
+            let set = typed!(key, SortedSet, Vec::new());
+            let before = set.len();
+            set.retain(|(_, it)| !members.contains(it));
+            Reply::Integer((before - set.len()) as i64)
+        }
+        ("ZRANGE" | "ZREVRANGE", [key, start, stop, options @ ..]) => {
+            let mut set = match data.get(key) {
+                Some(Value::SortedSet(set)) => set.clone(),
+                _ => Vec::new(),
+            };
+            if name == "ZREVRANGE" {
+                set.reverse();
+            }
+            let with_scores = options
+                .iter()
+                .any(|it| it.eq_ignore_ascii_case(b"WITHSCORES"));
+            Reply::Array(
+                set[range(set.len(), start, stop)]
+                    .iter()
+                    .flat_map(|(score, member)| {
+                        let score = with_scores.then(|| score_text(*score));
+                        [Some(member.clone())].into_iter().chain(score.map(Some))
+                    })
+                    .collect(),
+            )
+        }
+        ("ZRANGEBYSCORE", [key, min, max]) => match data.get(key) {
+            Some(Value::SortedSet(set)) => {
+                let (min, max) = (parse_score(min), parse_score(max));
+                Reply::Array(
+                    set.iter()
+                        .filter(|(score, _)| min <= *score && *score <= max)
+                        .map(|(_, member)| Some(member.clone()))
+                        .collect(),
+                )
+            }
+            _ => Reply::Array(Vec::new()),
+        },
+        _ => Reply::Error(format!("unsupported command {name}")),
+    }
+}
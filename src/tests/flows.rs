@@ -0,0 +1,322 @@
+use std::{sync::Arc, time::Duration};
+
+use serde_json::Value;
+
+use crate::{runner::UpdateQueues, store::schema::SCHEMA_VERSION};
+
+use super::{lookup_reply, Harness, DUOLINGO_NAME, GERMAN_WORD, WORD};
+
+/// The `Bot` JSON stored for `member_id`, without its envelope.
+pub fn state(harness: &Harness, member_id: i64) -> Value {
+    let state = harness
+        .redis
+        .string(&format!("bot-{member_id}"))
+        .expect("no state stored");
+    let mut envelope = serde_json::from_str::<Value>(&state).unwrap();
+    assert_eq!(envelope["version"], SCHEMA_VERSION);
+    envelope["bot"].take()
+}
+
+fn entities_of_type<'a>(message: &'a Value, kind: &str) -> Vec<&'a Value> {
+    message["entities"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|it| it["type"] == kind)
+        .collect()
+}
+
+#[tokio::test]
+async fn duolingo_login_stores_the_course() {
+    let harness = Harness::start().await;
+    harness.log_in(101, 101).await;
+
+    let paths = harness
+        .duolingo
+        .requests()
+        .into_iter()
+        .map(|it| it.path)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        [
+            format!("/users/{DUOLINGO_NAME}"),
+            "/vocabulary/overview".to_string()
+        ]
+    );
+    let duolingo = &state(&harness, 101)["duolingo"];
+    assert_eq!(duolingo["active_language"], "sv");
+    // The German word listed alongside isn't of this course.
+    assert_eq!(duolingo["vocabulary"].as_array().unwrap().len(), 1);
+    assert_eq!(duolingo["vocabulary"][0]["word_string"], WORD.1);
+    assert!(harness.sent_messages().is_empty());
+}
+
+#[tokio::test]
+async fn random_word_sends_a_card_with_voices() {
+    let harness = Harness::start().await;
+    harness.llm.set_responses([lookup_reply()]);
+    harness.log_in(102, 102).await;
+    harness
+        .send_text(102, 102, "/random_word", None)
+        .await
+        .unwrap();
+
+    let messages = harness.sent_messages();
+    assert_eq!(messages.len(), 1, "{messages:?}");
+    let card = &messages[0];
+    assert_eq!(card["chat_id"], 102);
+    assert!(card["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("hund (en, noun)\n/hɵnd/\ndog"));
+    let bold = entities_of_type(card, "bold");
+    assert_eq!(
+        (&bold[0]["offset"], &bold[0]["length"]),
+        (&0.into(), &4.into())
+    );
+    assert_eq!(entities_of_type(card, "spoiler").len(), 2);
+    assert!(card["reply_markup"]["inline_keyboard"].is_array());
+
+    let voices = harness.telegram.calls("sendVoice");
+    assert_eq!(voices.len(), 2);
+    assert!(voices.iter().all(|it| it.field("chat_id") == 102
+        && String::from_utf8_lossy(&it.body).contains(r#"filename="voice.ogg""#)));
+    let synthesized = harness
+        .azure
+        .calls("v1")
+        .iter()
+        .map(|it| String::from_utf8_lossy(&it.body).to_string())
+        .collect::<Vec<_>>();
+    assert!(synthesized.iter().any(|it| it.contains(">hund</voice>")));
+    assert!(synthesized
+        .iter()
+        .any(|it| it.contains(">Hunden skäller.</voice>")));
+
+    assert!(harness.redis.string("word-sv-en-hund").is_some());
+    assert_eq!(harness.redis.keys("card-102-").len(), 3);
+    let events = harness.redis.list("events-102");
+    assert_eq!(events.len(), 1);
+    assert!(events[0].contains(r#""kind":"word_shown""#));
+    assert!(events[0].contains(WORD.0));
+}
+
+#[tokio::test]
+async fn chat_replies_continue_the_session() {
+    let harness = Harness::start().await;
+    harness
+        .llm
+        .set_responses(["Hej! Hur mår du?", "Vad bra! (How nice!)"]);
+    harness.log_in(103, 103).await;
+    harness.send_text(103, 103, "/chat", None).await.unwrap();
+
+    let sessions = harness.redis.keys("103-");
+    assert_eq!(sessions.len(), 1);
+    let opener_id = sessions[0].strip_prefix("103-").unwrap().parse().unwrap();
+    harness
+        .send_text(103, 103, "Jag mår bra.", Some(opener_id))
+        .await
+        .unwrap();
+
+    let messages = harness.sent_messages();
+    let texts = messages.iter().map(|it| &it["text"]).collect::<Vec<_>>();
+    assert_eq!(texts, ["Hej! Hur mår du?", "Vad bra! (How nice!)"]);
+    let translation = entities_of_type(&messages[1], "spoiler");
+    assert_eq!(translation.len(), 1);
+    assert_eq!(harness.telegram.calls("sendVoice").len(), 2);
+    assert_eq!(harness.redis.keys("103-").len(), 2);
+    let events = harness.redis.list("events-103");
+    assert!(events.iter().any(|it| it.contains(r#""kind":"chat_turn""#)));
+}
+
+#[tokio::test]
+async fn remind_schedules_the_daily_push() {
+    let harness = Harness::start().await;
+    harness
+        .send_text(104, 104, "/remind 08:30 Europe/Stockholm", None)
+        .await
+        .unwrap();
+
+    let messages = harness.sent_messages();
+    // Replies are MarkdownV2, with the parentheses escaped.
+    assert!(messages[0]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with(r"Daily reminder at 08:30 \(Europe/Stockholm\)"));
+    let schedule = harness.redis.sorted_set("reminders");
+    assert_eq!(schedule.len(), 1);
+    assert_eq!(schedule[0].0, "104");
+    assert!(schedule[0].1 > crate::review::now_ms() as f64);
+    assert_eq!(state(&harness, 104)["reminder"]["minute"], 8 * 60 + 30);
+}
+
+#[tokio::test]
+async fn group_state_belongs_to_the_member() {
+    let harness = Harness::start().await;
+    harness.log_in(-1005, 105).await;
+    harness
+        .send_text(-1005, 105, "/leaderboard", None)
+        .await
+        .unwrap();
+
+    assert!(harness.redis.string("bot-105").is_some());
+    assert!(harness.redis.string("bot--1005").is_none());
+    let members = harness.redis.hash("group-members--1005");
+    assert_eq!(members.get("105").map(String::as_str), Some("User 105"));
+    let messages = harness.sent_messages();
+    assert_eq!(messages[0]["chat_id"], -1005);
+    assert!(messages[0]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("No quiz answers yet"));
+}
+
+#[tokio::test]
+async fn help_lists_the_commands() {
+    let harness = Harness::start().await;
+    harness.send_text(109, 109, "/help", None).await.unwrap();
+
+    let messages = harness.sent_messages();
+    let text = messages[0]["text"].as_str().unwrap();
+    assert!(text.contains(r"/random\_word \- Show a random word"));
+    assert!(text.contains(r"/help \- List what I can do"));
+}
+
+#[tokio::test]
+async fn new_users_need_no_azure() {
+    let harness = Harness::start().await;
+    std::env::remove_var("AZURE_TTS_SUBSCRIPTION_KEY");
+    std::env::set_var("TTS_ENGINE", "command");
+    harness
+        .send_text(110, 110, "/remind 07:00 Europe/Stockholm", None)
+        .await
+        .unwrap();
+
+    assert!(harness.azure.requests().is_empty());
+    assert!(state(&harness, 110).get("azure_tts").is_none());
+}
+
+#[tokio::test]
+async fn language_selects_the_course_without_switching_duolingo() {
+    let harness = Harness::start().await;
+    harness.log_in(111, 111).await;
+    harness
+        .send_text(111, 111, "/language de", None)
+        .await
+        .unwrap();
+
+    let requests = harness.duolingo.requests();
+    assert!(requests.iter().all(|it| it.path != "/switch_language"));
+    let last = requests.last().unwrap();
+    assert_eq!(last.query_param("learning_language"), Some("de"));
+    assert_eq!(last.query_param("from_language"), Some("en"));
+    let duolingo = &state(&harness, 111)["duolingo"];
+    assert_eq!(duolingo["active_language"], "de");
+    let vocabulary = duolingo["vocabulary"].as_array().unwrap();
+    assert_eq!(vocabulary.len(), 1);
+    assert_eq!(vocabulary[0]["id"], GERMAN_WORD.0);
+}
+
+#[tokio::test]
+async fn users_cannot_pick_the_scripted_backend() {
+    let harness = Harness::start().await;
+    harness
+        .send_text(112, 112, "/backend scripted", None)
+        .await
+        .unwrap();
+
+    let messages = harness.sent_messages();
+    assert!(messages[0]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("Unknown backend"));
+    assert!(state(&harness, 112)["llm_backend"].is_null());
+}
+
+#[tokio::test]
+async fn webhook_updates_of_a_member_run_in_order() {
+    let harness = Harness::start().await;
+    let queues = Arc::new(UpdateQueues::new(harness.store.clone()));
+    for text in ["/help", "/remind 06:00 Europe/Stockholm", "/start"] {
+        let update = harness.text_update(113, 113, text, None);
+        queues.push(harness.update(update));
+    }
+
+    let mut messages = harness.sent_messages();
+    for _ in 0..100 {
+        if messages.len() == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        messages = harness.sent_messages();
+    }
+    let texts = messages
+        .iter()
+        .map(|it| it["text"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(texts[0].starts_with("/random"), "{texts:?}");
+    assert!(texts[1].starts_with("Daily reminder at 06:00"), "{texts:?}");
+    assert!(texts[2].starts_with("Hello"), "{texts:?}");
+    assert_eq!(state(&harness, 113)["reminder"]["minute"], 6 * 60);
+}
+
+#[tokio::test]
+async fn voice_replies_outside_a_conversation_are_not_transcribed() {
+    let harness = Harness::start().await;
+    harness.log_in(114, 114).await;
+    let mut update = harness.text_update(114, 114, "", Some(5000));
+    let message = update["message"].as_object_mut().unwrap();
+    message.remove("text");
+    message.insert(
+        "voice".to_string(),
+        serde_json::json!({
+            "file_id": "voice-file",
+            "file_unique_id": "voice",
+            "duration": 1,
+            "mime_type": "audio/ogg",
+        }),
+    );
+    harness.send(update).await.unwrap();
+
+    assert!(harness.telegram.calls("getFile").is_empty());
+    let messages = harness.sent_messages();
+    assert!(messages[0]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("This conversation has expired"));
+}
+
+#[tokio::test]
+async fn reviews_only_grade_the_members_own_words() {
+    let harness = Harness::start().await;
+    harness.log_in(-1016, 116).await;
+    let press = |data: &str| {
+        let message = harness.text_update(-1016, 116, "…", None)["message"].take();
+        serde_json::json!({
+            "callback_query": {
+                "id": data,
+                "from": {"id": 116, "is_bot": false, "first_name": "User 116"},
+                "chat_instance": "-1016",
+                "data": data,
+                "message": message,
+            }
+        })
+    };
+    harness.send(press("review:v-other:good")).await.unwrap();
+    harness
+        .send(press(&format!("review:{}:good", WORD.0)))
+        .await
+        .unwrap();
+
+    let schedule = harness.redis.hash("review-116");
+    assert_eq!(schedule.keys().collect::<Vec<_>>(), [WORD.0]);
+    let answers = harness.telegram.calls("answerCallbackQuery");
+    assert_eq!(
+        answers[0].json()["text"],
+        "This card is from someone else's vocabulary."
+    );
+    assert!(answers[1].json()["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("Next review"));
+}
@@ -0,0 +1,274 @@
+//! End-to-end tests driving updates through [`runner::handle_update`] against
+//! local stand-ins for Telegram, Azure, Duolingo, the language model and Redis.
+
+mod bot_state;
+mod fake_http;
+pub mod fake_redis;
+mod flows;
+mod store;
+
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+use serde_json::{json, Value};
+use teloxide::types::Update;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{error::Result, llm::ScriptedBackend, runner, store::StateStore};
+use fake_http::{FakeHttp, Reply, Request};
+use fake_redis::FakeRedis;
+
+const BOT_USER_ID: i64 = 1;
+const TELEGRAM_TOKEN: &str = "123:test";
+pub const DUOLINGO_NAME: &str = "anna";
+pub const AUDIO: &[u8] = b"OggS fake audio";
+
+/// The word in the fake Duolingo vocabulary, with its id.
+pub const WORD: (&str, &str) = ("v-hund", "hund");
+/// A word of the German course, which the fake Duolingo lists alongside.
+pub const GERMAN_WORD: (&str, &str) = ("v-katze", "Katze");
+
+/// A dictionary reply of the language model for [`WORD`].
+pub fn lookup_reply() -> String {
+    json!({
+        "spell": WORD.1,
+        "pronunciation": "/hɵnd/",
+        "part_of_speech": "noun",
+        "article": "en",
+        "meaning": "dog",
+        "inflections": [{"form": "plural", "word": "hundar"}],
+        "examples": [{"sentence": "Hunden skäller.", "translation": "The dog barks."}],
+    })
+    .to_string()
+}
+
+/// The environment is process wide, so tests using it take turns.
+async fn serial() -> MutexGuard<'static, ()> {
+    static SERIAL: OnceLock<Mutex<()>> = OnceLock::new();
+    SERIAL.get_or_init(Default::default).lock().await
+}
+
+pub struct Harness {
+    pub telegram: FakeHttp,
+    pub azure: FakeHttp,
+    pub duolingo: FakeHttp,
+    pub redis: FakeRedis,
+    pub llm: ScriptedBackend,
+    store: Arc<dyn StateStore>,
+    update_id: AtomicI32,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Harness {
+    pub async fn start() -> Self {
+        let serial = serial().await;
+        let telegram = FakeHttp::start(fake_telegram());
+        let azure = FakeHttp::start(fake_azure);
+        let duolingo = FakeHttp::start(fake_duolingo);
+        let redis = FakeRedis::start().await;
+        for (name, value) in [
+            ("TELEGRAM_TOKEN", TELEGRAM_TOKEN),
+            ("TELEGRAM_API_URL", &telegram.url),
+            ("TELEGRAM_BOT_USERNAME", "lara_bot"),
+            ("AZURE_SPEECH_ENDPOINT", &azure.url),
+            ("AZURE_TTS_SUBSCRIPTION_KEY", "azure-key"),
+            ("DUOLINGO_URL", &duolingo.url),
+            ("LLM_BACKEND", "scripted"),
+            ("TTS_CACHE", "off"),
+            ("REDIS_URL", &redis.url),
+        ] {
+            env::set_var(name, value);
+        }
+        for name in [
+            "STATE_STORE",
+            "TTS_ENGINE",
+            "STT_ENGINE",
+            "TTS_COMMAND",
+            "STT_COMMAND",
+        ] {
+            env::remove_var(name);
+        }
+        let llm = ScriptedBackend::from_env().unwrap();
+        llm.set_responses(Vec::<String>::new());
+        let store = crate::store::from_env().await.unwrap();
+        crate::tts::load_azure(&store).await;
+        Self {
+            telegram,
+            azure,
+            duolingo,
+            redis,
+            llm,
+            store,
+            update_id: AtomicI32::new(1),
+            _serial: serial,
+        }
+    }
+
+    /// Numbers `update` and parses it.
+    pub fn update(&self, update: Value) -> Update {
+        let mut update = update;
+        update["update_id"] = self.update_id.fetch_add(1, Ordering::SeqCst).into();
+        // `UpdateKind` only deserializes from borrowed keys, so not from a `Value`.
+        serde_json::from_str(&update.to_string()).unwrap()
+    }
+
+    pub async fn send(&self, update: Value) -> Result<()> {
+        runner::handle_update(&self.update(update), self.store.as_ref()).await
+    }
+
+    /// `user_id` writes `text` in `chat_id`, replying to the bot's message
+    /// `reply_to` if given.
+    pub async fn send_text(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        text: &str,
+        reply_to: Option<i32>,
+    ) -> Result<()> {
+        let update = self.text_update(chat_id, user_id, text, reply_to);
+        self.send(update).await
+    }
+
+    /// The update of [`Harness::send_text`].
+    pub fn text_update(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        text: &str,
+        reply_to: Option<i32>,
+    ) -> Value {
+        let mut message = json!({
+            "message_id": self.update_id.load(Ordering::SeqCst) + 1000,
+            "date": 0,
+            "chat": chat(chat_id),
+            "from": user(user_id),
+            "text": text,
+        });
+        if text.starts_with('/') {
+            let length = text.find(' ').unwrap_or(text.len());
+            message["entities"] = json!([{"type": "bot_command", "offset": 0, "length": length}]);
+        }
+        if let Some(reply_to) = reply_to {
+            message["reply_to_message"] = json!({
+                "message_id": reply_to,
+                "date": 0,
+                "chat": chat(chat_id),
+                "from": user(BOT_USER_ID),
+                "text": "…",
+            });
+        }
+        json!({ "message": message })
+    }
+
+    /// Logs `user_id` in to the fake Duolingo.
+    pub async fn log_in(&self, chat_id: i64, user_id: i64) {
+        let text = format!("/duolingo_login {DUOLINGO_NAME} jwt");
+        self.send_text(chat_id, user_id, &text, None).await.unwrap();
+    }
+
+    /// The `sendMessage` calls made so far, without the chat actions.
+    pub fn sent_messages(&self) -> Vec<Value> {
+        self.telegram
+            .calls("sendMessage")
+            .iter()
+            .map(Request::json)
+            .collect()
+    }
+}
+
+fn user(id: i64) -> Value {
+    json!({"id": id, "is_bot": id == BOT_USER_ID, "first_name": format!("User {id}")})
+}
+
+fn chat(id: i64) -> Value {
+    if id > 0 {
+        json!({"id": id, "type": "private", "first_name": format!("User {id}")})
+    } else {
+        json!({"id": id, "type": "supergroup", "title": "Study group"})
+    }
+}
+
+fn ok(result: Value) -> Reply {
+    Reply::json(json!({"ok": true, "result": result}))
+}
+
+/// Answers the Bot API methods the bot uses, numbering the messages it sends.
+fn fake_telegram() -> impl Fn(&Request) -> Reply + Send + Sync {
+    let message_id = AtomicI32::new(1);
+    move |request| {
+        if request.path.starts_with("/file/") {
+            return Reply::bytes(AUDIO);
+        }
+        let method = request.path.rsplit('/').next().unwrap_or_default();
+        let mut message = json!({
+            "message_id": message_id.fetch_add(1, Ordering::SeqCst),
+            "date": 0,
+            "chat": chat(request.field("chat_id").as_i64().unwrap_or_default()),
+            "from": user(BOT_USER_ID),
+        });
+        match method {
+            "sendVoice" => {
+                message["voice"] = json!({
+                    "file_id": "voice-file",
+                    "file_unique_id": "voice",
+                    "duration": 1,
+                    "mime_type": "audio/ogg",
+                });
+                ok(message)
+            }
+            "sendMessage" | "editMessageText" => {
+                message["text"] = request.field("text");
+                ok(message)
+            }
+            "editMessageReplyMarkup" => {
+                message["text"] = json!("…");
+                ok(message)
+            }
+            "getFile" => ok(json!({
+                "file_id": request.field("file_id"),
+                "file_unique_id": "file",
+                "file_size": AUDIO.len(),
+                "file_path": "voice/file.oga",
+            })),
+            _ => ok(json!(true)),
+        }
+    }
+}
+
+fn fake_azure(request: &Request) -> Reply {
+    match request.path.as_str() {
+        "/cognitiveservices/voices/list" => Reply::json(json!([{
+            "Name": "Microsoft Server Speech Text to Speech Voice (sv-SE, SofieNeural)",
+            "ShortName": "sv-SE-SofieNeural",
+            "Gender": "Female",
+            "Locale": "sv-SE",
+        }])),
+        // Distinct audio per text, as the bot reuses uploads of the same audio.
+        "/cognitiveservices/v1" => Reply::bytes([AUDIO, &request.body].concat()),
+        _ => Reply::status(404),
+    }
+}
+
+fn fake_duolingo(request: &Request) -> Reply {
+    match request.path.as_str() {
+        // Whatever course is asked for, like Duolingo may.
+        "/vocabulary/overview" => Reply::json(json!({
+            "learning_language": "sv",
+            "from_language": "en",
+            "vocab_overview": [
+                {"id": WORD.0, "word_string": WORD.1, "last_practiced_ms": 0},
+                {"id": GERMAN_WORD.0, "word_string": GERMAN_WORD.1, "last_practiced_ms": 0, "language": "de"},
+            ],
+        })),
+        path if path == format!("/users/{DUOLINGO_NAME}") => Reply::json(json!({
+            "language_data": {"sv": {}, "de": {}},
+            "ui_language": "en",
+        })),
+        _ => Reply::status(404),
+    }
+}
@@ -0,0 +1,151 @@
+use std::{env, fs};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::json;
+
+use super::fake_redis::FakeRedis;
+use crate::store::{FileStore, RedisStore, StateStore};
+
+/// Runs the same operations against a backend, so both behave alike.
+async fn exercise(store: &dyn StateStore) {
+    store.set_string("plain", "value", None).await.unwrap();
+    assert_eq!(
+        store.get_string("plain").await.unwrap().as_deref(),
+        Some("value")
+    );
+    let many = store
+        .get_many(&["plain".to_string(), "missing".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(many, [Some(b"value".to_vec()), None]);
+
+    let fields = [
+        ("a".to_string(), "1".to_string()),
+        ("b".to_string(), "2".to_string()),
+    ];
+    store.hash_set("hash", &fields).await.unwrap();
+    assert_eq!(
+        store.hash_get("hash", "b").await.unwrap().as_deref(),
+        Some("2")
+    );
+    assert_eq!(store.hash_get_all("hash").await.unwrap().len(), 2);
+
+    let items = ["1", "2", "3"].map(String::from);
+    store.list_push("list", &items, 2).await.unwrap();
+    assert_eq!(store.list_all("list").await.unwrap(), ["2", "3"]);
+
+    store.sorted_set_add("set", "x", 3.0).await.unwrap();
+    store.sorted_set_add("set", "y", 1.0).await.unwrap();
+    store.sorted_set_increment("set", "y", 4.0).await.unwrap();
+    store.sorted_set_add("set", "z", 2.0).await.unwrap();
+    store.sorted_set_remove("set", "z").await.unwrap();
+    assert_eq!(store.sorted_set_up_to("set", 3.0).await.unwrap(), ["x"]);
+    assert_eq!(
+        store.sorted_set_top("set", 1).await.unwrap(),
+        [("y".to_string(), 5.0)]
+    );
+
+    store.rename("plain", "moved").await.unwrap();
+    assert!(store.get("plain").await.unwrap().is_none());
+    let mut keys = store.scan("").await.unwrap();
+    keys.sort();
+    assert_eq!(keys, ["hash", "list", "moved", "set"]);
+    assert_eq!(store.scan("li").await.unwrap(), ["list"]);
+
+    store.delete(&["hash".to_string()]).await.unwrap();
+    assert!(store.hash_get_all("hash").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn redis_store_keeps_state() {
+    let redis = FakeRedis::start().await;
+    let store = RedisStore::open(&redis.url).await.unwrap();
+    exercise(&store).await;
+}
+
+#[tokio::test]
+async fn file_store_keeps_state_across_restarts() {
+    let path = env::temp_dir().join(format!("lara-state-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let store = FileStore::open(&path).unwrap();
+    exercise(&store).await;
+    store.set("expired", b"gone", Some(0)).await.unwrap();
+
+    let reopened = FileStore::open(&path).unwrap();
+    let store: &dyn StateStore = &reopened;
+    assert_eq!(
+        store.get_string("moved").await.unwrap().as_deref(),
+        Some("value")
+    );
+    assert!(store.get("expired").await.unwrap().is_none());
+    assert_eq!(store.list_all("list").await.unwrap(), ["2", "3"]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn file_store_keeps_audio_out_of_the_log() {
+    let path = env::temp_dir().join(format!("lara-state-audio-{}.json", std::process::id()));
+    let blob_dir = path.with_extension("blobs");
+    let store = FileStore::open(&path).unwrap();
+    let audio = b"OggS\xff\xfe audio".to_vec();
+    store.set("voice", &audio, None).await.unwrap();
+    store.set("gone", &audio, None).await.unwrap();
+    store.delete(&["gone".to_string()]).await.unwrap();
+    let log = fs::read_to_string(&path).unwrap();
+    assert!(!log.contains(&STANDARD.encode(&audio)));
+    assert_eq!(fs::read_dir(&blob_dir).unwrap().count(), 2);
+
+    let reopened = FileStore::open(&path).unwrap();
+    assert_eq!(reopened.get("voice").await.unwrap(), Some(audio));
+    assert!(reopened.get("gone").await.unwrap().is_none());
+    // Reopening compacts the log and drops the blobs nothing refers to.
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    assert_eq!(fs::read_dir(&blob_dir).unwrap().count(), 1);
+    fs::remove_file(&path).unwrap();
+    fs::remove_dir_all(&blob_dir).unwrap();
+}
+
+#[tokio::test]
+async fn file_store_compacts_its_log() {
+    let path = env::temp_dir().join(format!("lara-state-log-{}.json", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let store = FileStore::open(&path).unwrap();
+    for count in 0..3000 {
+        let value = count.to_string();
+        let store: &dyn StateStore = &store;
+        store.set_string("counter", &value, None).await.unwrap();
+    }
+    let lines = fs::read_to_string(&path).unwrap().lines().count();
+    assert!(lines <= 1001, "{lines} lines");
+    let reopened = FileStore::open(&path).unwrap();
+    let store: &dyn StateStore = &reopened;
+    assert_eq!(
+        store.get_string("counter").await.unwrap().as_deref(),
+        Some("2999")
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn file_store_reads_the_earlier_whole_file_format() {
+    let path = env::temp_dir().join(format!("lara-state-old-{}.json", std::process::id()));
+    let state = json!({
+        "plain": {"value": {"type": "bytes", "value": STANDARD.encode("value")}},
+        "voice": {"value": {"type": "bytes", "value": STANDARD.encode(b"\xff\xfe")}},
+        "list": {"value": {"type": "list", "value": ["1"]}, "expires_at_ms": null},
+    });
+    fs::write(&path, state.to_string()).unwrap();
+    let reopened = FileStore::open(&path).unwrap();
+    let store: &dyn StateStore = &reopened;
+    assert_eq!(
+        store.get_string("plain").await.unwrap().as_deref(),
+        Some("value")
+    );
+    assert_eq!(
+        store.get("voice").await.unwrap(),
+        Some(b"\xff\xfe".to_vec())
+    );
+    assert_eq!(store.list_all("list").await.unwrap(), ["1"]);
+    fs::remove_file(&path).unwrap();
+    fs::remove_dir_all(path.with_extension("blobs")).unwrap();
+}
@@ -0,0 +1,420 @@
+use std::{env, fs};
+
+const DEFAULT_ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// An IANA time zone, such as `Europe/Stockholm`.
+///
+/// Only the POSIX `TZ` rule at the end of the zone's TZif file is used, which
+/// describes the current offsets and is all that scheduling ahead needs.
+/// Files are read from `ZONEINFO_DIR`, `/usr/share/zoneinfo` by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeZone {
+    /// Seconds east of UTC outside of daylight saving time.
+    std_offset: i64,
+    dst: Option<Dst>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Dst {
+    offset: i64,
+    start: Transition,
+    end: Transition,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Transition {
+    date: RuleDate,
+    /// Local time of the switch, in seconds after midnight.
+    time: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RuleDate {
+    /// `Jn`, 1 to 365, never counting February 29.
+    Julian(i64),
+    /// `n`, 0 to 365, counting February 29.
+    Ordinal(i64),
+    /// `Mm.w.d`, weekday `d` (0 is Sunday) of week `w` (5 is the last) of month `m`.
+    MonthWeekDay { month: i64, week: i64, weekday: i64 },
+}
+
+impl TimeZone {
+    pub const UTC: Self = Self {
+        std_offset: 0,
+        dst: None,
+    };
+
+    pub fn load(name: &str) -> Option<Self> {
+        let dir = env::var("ZONEINFO_DIR").unwrap_or_else(|_| DEFAULT_ZONEINFO_DIR.to_string());
+        Self::load_from(&dir, name)
+    }
+
+    fn load_from(dir: &str, name: &str) -> Option<Self> {
+        let valid_name = !name.is_empty()
+            && !name.starts_with('/')
+            && !name.split('/').any(|it| it.is_empty() || it == "..")
+            && name
+                .chars()
+                .all(|it| it.is_ascii_alphanumeric() || "/_-+".contains(it));
+        if !valid_name {
+            return None;
+        }
+        let data = fs::read(format!("{dir}/{name}")).ok()?;
+        // Version 2+ files end with the rule on a line of its own.
+        if !data.starts_with(b"TZif") || data.get(4).copied().unwrap_or(0) < b'2' {
+            return None;
+        }
+        let footer = data.strip_suffix(b"\n")?;
+        let start = footer.iter().rposition(|it| *it == b'\n')? + 1;
+        Self::parse(std::str::from_utf8(&footer[start..]).ok()?)
+    }
+
+    /// Parses a POSIX `TZ` rule such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+    pub fn parse(rule: &str) -> Option<Self> {
+        let mut parser = Parser(rule.as_bytes());
+        parser.name()?;
+        // POSIX offsets count west of UTC.
+        let std_offset = -parser.time()?;
+        if parser.0.is_empty() {
+            return Some(Self {
+                std_offset,
+                dst: None,
+            });
+        }
+        parser.name()?;
+        let offset = match parser.0.first() {
+            Some(b',') => std_offset + 60 * 60,
+            _ => -parser.time()?,
+        };
+        parser.expect(b',')?;
+        let start = parser.transition()?;
+        parser.expect(b',')?;
+        let end = parser.transition()?;
+        if !parser.0.is_empty() {
+            return None;
+        }
+        Some(Self {
+            std_offset,
+            dst: Some(Dst { offset, start, end }),
+        })
+    }
+
+    /// Seconds east of UTC in effect at the Unix time `utc_secs`.
+    pub fn offset_at(&self, utc_secs: i64) -> i64 {
+        let Some(dst) = &self.dst else {
+            return self.std_offset;
+        };
+        let (year, _, _) = civil_from_days((utc_secs + self.std_offset).div_euclid(DAY_SECS));
+        let start = dst.start.local_secs(year) - self.std_offset;
+        let end = dst.end.local_secs(year) - dst.offset;
+        let in_dst = if start < end {
+            start <= utc_secs && utc_secs < end
+        } else {
+            !(end <= utc_secs && utc_secs < start)
+        };
+        if in_dst {
+            dst.offset
+        } else {
+            self.std_offset
+        }
+    }
+
+    /// The local wall clock time at `utc_secs`, as seconds since the local epoch.
+    pub fn to_local(&self, utc_secs: i64) -> i64 {
+        utc_secs + self.offset_at(utc_secs)
+    }
+
+    /// The Unix time of a local wall clock time, the later one when it is ambiguous.
+    pub fn to_utc(&self, local_secs: i64) -> i64 {
+        let guess = local_secs - self.offset_at(local_secs - self.std_offset);
+        local_secs - self.offset_at(guess)
+    }
+}
+
+impl Transition {
+    fn local_secs(&self, year: i64) -> i64 {
+        let leap = is_leap_year(year);
+        let day = match self.date {
+            RuleDate::Julian(n) => days_from_civil(year, 1, 1) + n - 1 + i64::from(leap && n >= 60),
+            RuleDate::Ordinal(n) => days_from_civil(year, 1, 1) + n,
+            RuleDate::MonthWeekDay {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = days_from_civil(year, month, 1);
+                let mut day = first + (weekday - weekday_of(first)).rem_euclid(7) + (week - 1) * 7;
+                while day >= first + days_in_month(year, month) {
+                    day -= 7;
+                }
+                day
+            }
+        };
+        day * DAY_SECS + self.time
+    }
+}
+
+struct Parser<'a>(&'a [u8]);
+
+impl Parser<'_> {
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        let (first, rest) = self.0.split_first()?;
+        if *first != byte {
+            return None;
+        }
+        self.0 = rest;
+        Some(())
+    }
+
+    fn name(&mut self) -> Option<()> {
+        let len = if self.0.first() == Some(&b'<') {
+            self.0.iter().position(|it| *it == b'>')? + 1
+        } else {
+            self.0
+                .iter()
+                .take_while(|it| it.is_ascii_alphabetic())
+                .count()
+        };
+        if len < 3 {
+            return None;
+        }
+        self.0 = &self.0[len..];
+        Some(())
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let len = self.0.iter().take_while(|it| it.is_ascii_digit()).count();
+        let number = std::str::from_utf8(&self.0[..len]).ok()?.parse().ok()?;
+        self.0 = &self.0[len..];
+        Some(number)
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds.
+    fn time(&mut self) -> Option<i64> {
+        let sign = match self.0.first() {
+            Some(b'-') => -1,
+            Some(b'+') => 1,
+            _ => 0,
+        };
+        if sign != 0 {
+            self.0 = &self.0[1..];
+        }
+        let mut secs = self.number()? * 60 * 60;
+        for unit in [60, 1] {
+            if self.expect(b':').is_none() {
+                break;
+            }
+            secs += self.number()? * unit;
+        }
+        Some(if sign == -1 { -secs } else { secs })
+    }
+
+    fn transition(&mut self) -> Option<Transition> {
+        let date = match self.0.first()? {
+            b'J' => {
+                self.0 = &self.0[1..];
+                RuleDate::Julian(self.number().filter(|it| (1..=365).contains(it))?)
+            }
+            b'M' => {
+                self.0 = &self.0[1..];
+                let month = self.number().filter(|it| (1..=12).contains(it))?;
+                self.expect(b'.')?;
+                let week = self.number().filter(|it| (1..=5).contains(it))?;
+                self.expect(b'.')?;
+                let weekday = self.number().filter(|it| (0..=6).contains(it))?;
+                RuleDate::MonthWeekDay {
+                    month,
+                    week,
+                    weekday,
+                }
+            }
+            _ => RuleDate::Ordinal(self.number().filter(|it| (0..=365).contains(it))?),
+        };
+        let time = if self.expect(b'/').is_some() {
+            self.time()?
+        } else {
+            2 * 60 * 60
+        };
+        Some(Transition { date, time })
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// 0 is Sunday.
+fn weekday_of(days: i64) -> i64 {
+    (days + 4).rem_euclid(7)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The day of the month of days since 1970-01-01.
+pub fn day_of_month(days: i64) -> u32 {
+    civil_from_days(days).2 as u32
+}
+
+/// The proleptic Gregorian date of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+    const STOCKHOLM: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+    const SYDNEY: &str = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+
+    fn local(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> i64 {
+        days_from_civil(year, month, day) * DAY_SECS + hour * HOUR + minute * 60
+    }
+
+    #[test]
+    fn northern_dst_starts_in_march_and_ends_in_october() {
+        let zone = TimeZone::parse(STOCKHOLM).unwrap();
+        // 2024-03-31 and 2024-10-27, both at 01:00 UTC.
+        let (start, end) = (1_711_846_800, 1_729_990_800);
+        assert_eq!(zone.offset_at(start - 1), HOUR);
+        assert_eq!(zone.offset_at(start), 2 * HOUR);
+        assert_eq!(zone.offset_at(end - 1), 2 * HOUR);
+        assert_eq!(zone.offset_at(end), HOUR);
+    }
+
+    #[test]
+    fn southern_dst_spans_the_new_year() {
+        let zone = TimeZone::parse(SYDNEY).unwrap();
+        // Ends 2024-04-06 and starts 2024-10-05, both at 16:00 UTC.
+        let (end, start) = (1_712_419_200, 1_728_144_000);
+        assert_eq!(zone.offset_at(end - 1), 11 * HOUR);
+        assert_eq!(zone.offset_at(end), 10 * HOUR);
+        assert_eq!(zone.offset_at(start - 1), 10 * HOUR);
+        assert_eq!(zone.offset_at(start), 11 * HOUR);
+        assert_eq!(
+            zone.to_local(local(2024, 1, 1, 0, 0)),
+            local(2024, 1, 1, 11, 0)
+        );
+    }
+
+    #[test]
+    fn to_utc_skips_the_gap_and_takes_the_later_overlap() {
+        let zone = TimeZone::parse(STOCKHOLM).unwrap();
+        // 02:30 doesn't exist on 2024-03-31, so it becomes 03:30 CEST.
+        assert_eq!(zone.to_utc(local(2024, 3, 31, 2, 30)), 1_711_848_600);
+        // 02:30 happens twice on 2024-10-27, the second time in CET.
+        assert_eq!(zone.to_utc(local(2024, 10, 27, 2, 30)), 1_729_992_600);
+        assert_eq!(
+            zone.to_utc(local(2024, 7, 1, 12, 0)),
+            local(2024, 7, 1, 10, 0)
+        );
+    }
+
+    #[test]
+    fn footer_rules() {
+        let india = TimeZone::parse("IST-5:30").unwrap();
+        assert_eq!((india.std_offset, india.dst), (5 * HOUR + 30 * 60, None));
+        assert_eq!(TimeZone::parse("<-03>3").unwrap().std_offset, -3 * HOUR);
+
+        let new_york = TimeZone::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        // Second Sunday of March at 02:00 EST, 2024-03-10 07:00 UTC.
+        assert_eq!(new_york.offset_at(1_710_054_000 - 1), -5 * HOUR);
+        assert_eq!(new_york.offset_at(1_710_054_000), -4 * HOUR);
+
+        let lord_howe = TimeZone::parse("<+1030>-10:30<+11>-11,M10.1.0,M4.1.0").unwrap();
+        assert_eq!(lord_howe.dst.unwrap().offset, 11 * HOUR);
+
+        for invalid in [
+            "",
+            "CET",
+            "CET-1CEST",
+            "CET-1CEST,M13.5.0,M10.5.0",
+            "CET-1CEST,M3.6.0,M10.5.0",
+            "CET-1CEST,M3.5.0,M10.5.0/3x",
+        ] {
+            assert_eq!(TimeZone::parse(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn julian_days_skip_february_29_and_ordinal_days_count_it() {
+        let julian = Transition {
+            date: RuleDate::Julian(60),
+            time: 0,
+        };
+        assert_eq!(julian.local_secs(2024), local(2024, 3, 1, 0, 0));
+        assert_eq!(julian.local_secs(2023), local(2023, 3, 1, 0, 0));
+        let ordinal = Transition {
+            date: RuleDate::Ordinal(59),
+            time: 2 * HOUR,
+        };
+        assert_eq!(ordinal.local_secs(2024), local(2024, 2, 29, 2, 0));
+        assert_eq!(ordinal.local_secs(2023), local(2023, 3, 1, 2, 0));
+    }
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        for days in [-719_468, -1, 0, 11_016, 19_813, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(weekday_of(days_from_civil(2024, 3, 31)), 0);
+    }
+
+    #[test]
+    fn load_reads_the_footer_of_tzif_files() {
+        let dir = env::temp_dir().join(format!("lara-zoneinfo-{}", std::process::id()));
+        fs::create_dir_all(dir.join("Europe")).unwrap();
+        let file = [
+            b"TZif2".as_slice(),
+            &[0; 39],
+            b"\n",
+            STOCKHOLM.as_bytes(),
+            b"\n",
+        ]
+        .concat();
+        fs::write(dir.join("Europe/Stockholm"), &file).unwrap();
+        fs::write(dir.join("Old"), [b"TZif\0".as_slice(), &file[5..]].concat()).unwrap();
+        let dir_str = dir.to_str().unwrap();
+
+        assert_eq!(
+            TimeZone::load_from(dir_str, "Europe/Stockholm"),
+            TimeZone::parse(STOCKHOLM)
+        );
+        assert_eq!(TimeZone::load_from(dir_str, "Old"), None);
+        assert_eq!(TimeZone::load_from(dir_str, "../Europe/Stockholm"), None);
+        assert_eq!(TimeZone::load_from(dir_str, "Europe/Oslo"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
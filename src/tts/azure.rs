@@ -0,0 +1,186 @@
+use super::{
+    cache::{self, AudioStore},
+    SpeechSynthesizer,
+};
+use crate::{
+    error::{Error, Result},
+    store::StateStore,
+    util::new_reqwest_client,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const DEFAULT_REGION: &str = "northeurope";
+const OUTPUT_FORMAT: &str = "ogg-16khz-16bit-mono-opus";
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_string()
+}
+
+/// The `tts` or `stt` host of the Speech service in `region`, or
+/// `AZURE_SPEECH_ENDPOINT` for both, such as a proxy or a fake server in tests.
+fn speech_host(region: &str, service: &str) -> String {
+    std::env::var("AZURE_SPEECH_ENDPOINT")
+        .map(|it| it.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| format!("https://{region}.{service}.speech.microsoft.com"))
+}
+
+/// The short audio recognition endpoint, shared by transcription and
+/// pronunciation assessment.
+pub fn stt_url(region: &str) -> String {
+    format!(
+        "{}/speech/recognition/conversation/cognitiveservices/v1",
+        speech_host(region, "stt")
+    )
+}
+
+fn tts_url(region: &str) -> String {
+    format!("{}/cognitiveservices/v1", speech_host(region, "tts"))
+}
+
+fn voice_list_url(region: &str) -> String {
+    format!(
+        "{}/cognitiveservices/voices/list",
+        speech_host(region, "tts")
+    )
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub enum Gender {
+    Male,
+    Female,
+    #[serde(other)]
+    Other,
+}
+
+impl fmt::Display for Gender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Gender::Male => write!(f, "Male"),
+            Gender::Female => write!(f, "Female"),
+            // SSML knows no other genders, and the voice name decides anyway.
+            Gender::Other => write!(f, "Neutral"),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Voice {
+    name: String,
+    short_name: String,
+    pub gender: Gender,
+    pub locale: String,
+    #[serde(default)]
+    style_list: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AzureTTS {
+    pub(crate) subscription_key: String,
+    #[serde(default = "default_region")]
+    pub(crate) region: String,
+    pub voices: Vec<Voice>,
+    #[serde(skip)]
+    cache: Option<Arc<dyn AudioStore>>,
+}
+
+impl AzureTTS {
+    pub async fn new(
+        subscription_key: impl ToString,
+        region: impl ToString,
+        cache: Option<Arc<dyn AudioStore>>,
+    ) -> Result<Self> {
+        let region = region.to_string();
+        let response = new_reqwest_client()
+            .get(voice_list_url(&region))
+            .header("Ocp-Apim-Subscription-Key", subscription_key.to_string())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::AzureTTS(format!(
+                "listing voices failed with {}",
+                response.status()
+            )));
+        }
+        let voices = response.json().await?;
+        Ok(Self {
+            subscription_key: subscription_key.to_string(),
+            region,
+            voices,
+            cache,
+        })
+    }
+
+    /// Reads `AZURE_TTS_SUBSCRIPTION_KEY` and `AZURE_TTS_REGION` (default
+    /// `northeurope`), caching the audio as configured in [`cache::store_from_env`].
+    pub async fn from_env(store: Arc<dyn StateStore>) -> Result<Self> {
+        Self::new(
+            std::env::var("AZURE_TTS_SUBSCRIPTION_KEY").unwrap(),
+            std::env::var("AZURE_TTS_REGION").unwrap_or_else(|_| default_region()),
+            cache::store_from_env(store),
+        )
+        .await
+    }
+
+    pub fn voice_for(&self, language: &str) -> Option<&Voice> {
+        self.voices.iter().find(|it| it.locale.contains(language))
+    }
+
+    pub async fn tts_simple(&self, text: &str, voice: &Voice) -> Option<Bytes> {
+        self.tts(&[(text, voice)]).await
+    }
+
+    pub async fn tts(&self, content: &[(&str, &Voice)]) -> Option<Bytes> {
+        let locale = content[0].1.locale.clone();
+        let mut tts_ssml = format!("<speak version='1.0' xml:lang='{locale}'>");
+        for (
+            text,
+            Voice {
+                short_name, gender, ..
+            },
+        ) in content
+        {
+            tts_ssml += &format!(
+                "<voice xml:lang='{locale}' xml:gender='{gender}' name='{short_name}'>{text}</voice>"
+            )
+        }
+        tts_ssml += "</speak>";
+        let cache_key = cache::cache_key(&tts_ssml, OUTPUT_FORMAT);
+        if let Some(cache) = &self.cache {
+            if let Some(audio) = cache.get(&cache_key).await {
+                return Some(audio);
+            }
+        }
+        let response = new_reqwest_client()
+            .post(tts_url(&self.region))
+            .header("Ocp-Apim-Subscription-Key", &self.subscription_key)
+            .header("Content-Type", "application/ssml+xml")
+            .header("X-Microsoft-OutputFormat", OUTPUT_FORMAT)
+            .body(tts_ssml)
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_success() {
+            println!("{:?}", &response);
+            return None;
+        }
+        let audio = response.bytes().await.ok()?;
+        if let Some(cache) = &self.cache {
+            cache.put(&cache_key, &audio).await;
+        }
+        Some(audio)
+    }
+}
+
+#[async_trait]
+impl SpeechSynthesizer for AzureTTS {
+    async fn synthesize(&self, text: &str, language: &str) -> Option<Bytes> {
+        let voice = self.voice_for(language)?;
+        self.tts_simple(text, voice).await
+    }
+}
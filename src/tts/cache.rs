@@ -0,0 +1,416 @@
+use std::{
+    env,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    error::Result,
+    review::now_ms,
+    store::{schema, StateStore},
+};
+
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+/// How often the file store is scanned for old entries when not full.
+const FILE_EVICT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Content-addressed storage for synthesized audio.
+///
+/// Entries not used for `max_age_ms` are evicted, then the least recently
+/// used ones until the store holds at most `max_bytes`.
+#[async_trait]
+pub trait AudioStore: Send + Sync + Debug {
+    async fn get(&self, key: &str) -> Option<Bytes>;
+    async fn put(&self, key: &str, audio: &Bytes);
+}
+
+/// The cache key of everything that determines the audio.
+pub fn cache_key(ssml: &str, output_format: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(output_format.as_bytes());
+    hasher.update([0]);
+    hasher.update(ssml.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Identifies audio by its content, e.g. to remember its Telegram `file_id`.
+pub fn audio_digest(audio: &[u8]) -> String {
+    hex::encode(Sha1::digest(audio))
+}
+
+/// Picks the store from `TTS_CACHE`: `state` (in `store`, formerly `redis`), `fs` (in
+/// `TTS_CACHE_DIR`, default `./tts-cache`) or `off`. By default the audio
+/// goes where the state does, into files next to a file state store.
+/// `TTS_CACHE_MAX_BYTES` and `TTS_CACHE_MAX_AGE_DAYS` bound its size.
+pub fn store_from_env(store: Arc<dyn StateStore>) -> Option<Arc<dyn AudioStore>> {
+    let max_bytes = env::var("TTS_CACHE_MAX_BYTES")
+        .ok()
+        .and_then(|it| it.parse().ok())
+        .unwrap_or(DEFAULT_MAX_BYTES);
+    let max_age_ms = env::var("TTS_CACHE_MAX_AGE_DAYS")
+        .ok()
+        .and_then(|it| it.parse::<u64>().ok())
+        .map_or(schema::AUDIO_TTL_SECS as u64 * 1000, |it| it * DAY_MS);
+    let file_state = env::var("STATE_STORE").as_deref() == Ok("fs");
+    match env::var("TTS_CACHE").as_deref() {
+        Ok("off") => {
+            println!("Synthesized audio isn't cached, TTS_CACHE is off");
+            None
+        }
+        Ok("fs") => Some(Arc::new(AudioFileStore::from_env(max_bytes, max_age_ms))),
+        Ok("state" | "redis") => Some(Arc::new(StateAudioStore {
+            store,
+            max_bytes,
+            max_age_ms,
+        })),
+        Ok(other) => {
+            println!("Synthesized audio isn't cached, unknown TTS_CACHE {other}");
+            None
+        }
+        Err(_) if file_state => Some(Arc::new(AudioFileStore::from_env(max_bytes, max_age_ms))),
+        Err(_) => Some(Arc::new(StateAudioStore {
+            store,
+            max_bytes,
+            max_age_ms,
+        })),
+    }
+}
+
+/// Keeps the audio in the state store under [`schema::audio_key`], with its
+/// last use in [`schema::AUDIO_INDEX_KEY`], its size in
+/// [`schema::AUDIO_SIZES_KEY`] and their sum in [`schema::AUDIO_BYTES_KEY`].
+pub struct StateAudioStore {
+    store: Arc<dyn StateStore>,
+    max_bytes: u64,
+    max_age_ms: u64,
+}
+
+impl Debug for StateAudioStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateAudioStore")
+            .field("max_bytes", &self.max_bytes)
+            .field("max_age_ms", &self.max_age_ms)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StateAudioStore {
+    fn ttl_secs(&self) -> usize {
+        (self.max_age_ms / 1000) as usize
+    }
+
+    /// Evicts entries past their age, then the oldest while over `total` bytes.
+    async fn evict(&self, mut total: i64) -> Result<()> {
+        let oldest_allowed = now_ms().saturating_sub(self.max_age_ms);
+        let expired = self
+            .store
+            .sorted_set_up_to(schema::AUDIO_INDEX_KEY, oldest_allowed as f64)
+            .await?;
+        for key in expired {
+            total -= self.remove(&key).await?;
+        }
+        while total > self.max_bytes as i64 {
+            let oldest = self
+                .store
+                .sorted_set_bottom(schema::AUDIO_INDEX_KEY, 1)
+                .await?;
+            let Some(key) = oldest.into_iter().next() else {
+                break;
+            };
+            total -= self.remove(&key).await?;
+        }
+        Ok(())
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<i64>> {
+        let size = self.store.hash_get(schema::AUDIO_SIZES_KEY, key).await?;
+        Ok(size.and_then(|it| it.parse().ok()))
+    }
+
+    /// Removes an entry, returning the bytes it freed, none when another
+    /// eviction removed it first.
+    async fn remove(&self, key: &str) -> Result<i64> {
+        let size = self.size(key).await?;
+        self.store.delete(&[schema::audio_key(key)]).await?;
+        self.store
+            .sorted_set_remove(schema::AUDIO_INDEX_KEY, key)
+            .await?;
+        if !self.store.hash_delete(schema::AUDIO_SIZES_KEY, key).await? {
+            return Ok(0);
+        }
+        let freed = size.unwrap_or_default();
+        self.store
+            .increment(schema::AUDIO_BYTES_KEY, -freed)
+            .await?;
+        Ok(freed)
+    }
+
+    /// Adds `added` bytes to the running total and returns it.
+    async fn count(&self, added: i64) -> Result<i64> {
+        if self.store.get(schema::AUDIO_BYTES_KEY).await?.is_some() {
+            return self.store.increment(schema::AUDIO_BYTES_KEY, added).await;
+        }
+        // Caches from before the running total get it summed up once.
+        let sizes = self.store.hash_get_all(schema::AUDIO_SIZES_KEY).await?;
+        let total: i64 = sizes.values().filter_map(|it| it.parse::<i64>().ok()).sum();
+        self.store
+            .set_string(schema::AUDIO_BYTES_KEY, &total.to_string(), None)
+            .await?;
+        Ok(total)
+    }
+}
+
+#[async_trait]
+impl AudioStore for StateAudioStore {
+    async fn get(&self, key: &str) -> Option<Bytes> {
+        let audio_key = schema::audio_key(key);
+        let audio = self.store.get(&audio_key).await.ok()??;
+        let _ = self
+            .store
+            .sorted_set_add(schema::AUDIO_INDEX_KEY, key, now_ms() as f64)
+            .await;
+        let _ = self.store.expire(&audio_key, self.ttl_secs()).await;
+        Some(Bytes::from(audio))
+    }
+
+    async fn put(&self, key: &str, audio: &Bytes) {
+        let result: Result<()> = async {
+            let previous = self.size(key).await?;
+            self.store
+                .set(&schema::audio_key(key), audio, Some(self.ttl_secs()))
+                .await?;
+            let size = [(key.to_string(), audio.len().to_string())];
+            self.store.hash_set(schema::AUDIO_SIZES_KEY, &size).await?;
+            self.store
+                .sorted_set_add(schema::AUDIO_INDEX_KEY, key, now_ms() as f64)
+                .await?;
+            let added = audio.len() as i64 - previous.unwrap_or_default();
+            let total = self.count(added).await?;
+            self.evict(total).await
+        }
+        .await;
+        if let Err(error) = result {
+            println!("Failed to cache audio: {error}");
+        }
+    }
+}
+
+/// Keeps the audio as `{dir}/{key}.ogg`, using the modification time as last use.
+///
+/// The directory is only scanned when the running total of its size goes over
+/// `max_bytes`, or [`FILE_EVICT_INTERVAL`] after the last scan.
+#[derive(Debug)]
+pub struct AudioFileStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age_ms: u64,
+    usage: Mutex<Usage>,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    /// The size found by the last scan plus what was written since, `None`
+    /// before the first scan.
+    total_bytes: Option<u64>,
+    last_scan: Option<Instant>,
+    scanning: bool,
+}
+
+impl AudioFileStore {
+    fn from_env(max_bytes: u64, max_age_ms: u64) -> Self {
+        Self {
+            dir: env::var("TTS_CACHE_DIR")
+                .unwrap_or_else(|_| "./tts-cache".to_string())
+                .into(),
+            max_bytes,
+            max_age_ms,
+            usage: Mutex::default(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.ogg"))
+    }
+
+    /// Counts `added` bytes, and whether this put should scan the directory.
+    fn claim_scan(&self, added: u64) -> bool {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(total) = &mut usage.total_bytes {
+            *total += added;
+        }
+        let due = usage.total_bytes.is_none_or(|it| it > self.max_bytes)
+            || usage
+                .last_scan
+                .is_none_or(|it| it.elapsed() >= FILE_EVICT_INTERVAL);
+        if !due || usage.scanning {
+            return false;
+        }
+        usage.scanning = true;
+        true
+    }
+
+    async fn evict(&self) {
+        let (dir, max_bytes, max_age_ms) = (self.dir.clone(), self.max_bytes, self.max_age_ms);
+        let result = tokio::task::spawn_blocking(move || evict_dir(&dir, max_bytes, max_age_ms))
+            .await
+            .unwrap_or_else(|error| Err(std::io::Error::other(error)));
+        let mut usage = self.usage.lock().unwrap();
+        usage.scanning = false;
+        usage.last_scan = Some(Instant::now());
+        match result {
+            Ok(total) => usage.total_bytes = Some(total),
+            Err(error) => println!("Failed to evict audio in {}: {error}", self.dir.display()),
+        }
+    }
+}
+
+/// Removes the files in `dir` older than `max_age_ms`, then the least recently
+/// used until at most `max_bytes` remain, returning the bytes left.
+fn evict_dir(dir: &Path, max_bytes: u64, max_age_ms: u64) -> std::io::Result<u64> {
+    let now = SystemTime::now();
+    let max_age = Duration::from_millis(max_age_ms);
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let modified = metadata.modified()?;
+        if now.duration_since(modified).unwrap_or_default() > max_age {
+            fs::remove_file(entry.path())?;
+        } else {
+            entries.push((modified, metadata.len(), entry.path()));
+        }
+    }
+    entries.sort();
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        fs::remove_file(path)?;
+        total -= size;
+    }
+    Ok(total)
+}
+
+#[async_trait]
+impl AudioStore for AudioFileStore {
+    async fn get(&self, key: &str) -> Option<Bytes> {
+        let path = self.path(key);
+        let audio = tokio::fs::read(&path).await.ok()?;
+        let _ = tokio::task::spawn_blocking(move || {
+            fs::File::options()
+                .append(true)
+                .open(path)?
+                .set_modified(SystemTime::now())
+        })
+        .await;
+        Some(Bytes::from(audio))
+    }
+
+    async fn put(&self, key: &str, audio: &Bytes) {
+        let result = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(self.path(key), audio).await
+        }
+        .await;
+        if let Err(error) = result {
+            println!("Failed to cache audio in {}: {error}", self.dir.display());
+        } else if self.claim_scan(audio.len() as u64) {
+            self.evict().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        store::{FileStore, RedisStore},
+        tests::fake_redis::FakeRedis,
+    };
+
+    /// Puts `size` bytes under `key`, a little after the previous put.
+    async fn put(store: &dyn AudioStore, key: &str, size: usize) {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        store.put(key, &Bytes::from(vec![0xff; size])).await;
+    }
+
+    /// Evicts from the running total, whichever state store holds the audio.
+    async fn exercise(store: Arc<dyn StateStore>) {
+        let audio = StateAudioStore {
+            store: store.clone(),
+            max_bytes: 10,
+            max_age_ms: DAY_MS,
+        };
+        put(&audio, "a", 4).await;
+        put(&audio, "b", 4).await;
+        // Putting again replaces the size and counts as a use.
+        put(&audio, "a", 4).await;
+        let total = || store.get_string(schema::AUDIO_BYTES_KEY);
+        assert_eq!(total().await.unwrap().as_deref(), Some("8"));
+        put(&audio, "c", 4).await;
+        assert_eq!(total().await.unwrap().as_deref(), Some("8"));
+        let mut sizes = store
+            .hash_get_all(schema::AUDIO_SIZES_KEY)
+            .await
+            .unwrap()
+            .into_keys()
+            .collect::<Vec<_>>();
+        sizes.sort();
+        assert_eq!(sizes, ["a", "c"]);
+        assert!(audio.get("b").await.is_none());
+        assert_eq!(audio.get("a").await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn redis_audio_evicts_by_its_running_total() {
+        let redis = FakeRedis::start().await;
+        exercise(Arc::new(RedisStore::open(&redis.url).await.unwrap())).await;
+        assert!(redis.get(&schema::audio_key("c")).is_some());
+    }
+
+    #[tokio::test]
+    async fn file_state_audio_evicts_by_its_running_total() {
+        let path = env::temp_dir().join(format!("lara-tts-state-{}.json", std::process::id()));
+        exercise(Arc::new(FileStore::open(&path).unwrap())).await;
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(path.with_extension("blobs")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_store_scans_when_full() {
+        let dir = env::temp_dir().join(format!("lara-tts-cache-{}", std::process::id()));
+        let store = AudioFileStore {
+            dir: dir.clone(),
+            max_bytes: 10,
+            max_age_ms: DAY_MS,
+            usage: Mutex::default(),
+        };
+        // Left from a previous run, and too old to keep.
+        fs::create_dir_all(&dir).unwrap();
+        let stale = fs::File::create(dir.join("stale.ogg")).unwrap();
+        stale
+            .set_modified(SystemTime::now() - Duration::from_millis(2 * DAY_MS))
+            .unwrap();
+        put(&store, "a", 4).await;
+        assert!(!dir.join("stale.ogg").exists());
+        put(&store, "b", 4).await;
+        let usage = store.usage.lock().unwrap().total_bytes;
+        assert_eq!(usage, Some(8));
+        put(&store, "c", 4).await;
+        assert!(store.get("a").await.is_none());
+        assert!(store.get("b").await.is_some());
+        assert!(store.get("c").await.is_some());
+        let usage = store.usage.lock().unwrap().total_bytes;
+        assert_eq!(usage, Some(8));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
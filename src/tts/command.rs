@@ -0,0 +1,61 @@
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use super::SpeechSynthesizer;
+
+/// Runs a local engine such as espeak-ng or Piper through `sh -c`.
+///
+/// The text is written to the command's stdin and `{language}` in the command
+/// line is replaced by the ISO 639-1 code. The command must print OGG/Opus
+/// audio to stdout, e.g.
+/// `espeak-ng -v {language} --stdin --stdout | ffmpeg -i - -c:a libopus -f ogg -`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CommandTTS {
+    command: String,
+}
+
+impl CommandTTS {
+    pub fn new(command: impl ToString) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+
+    /// Reads the command line from `TTS_COMMAND`, `None` if it is not set.
+    pub fn from_env() -> Option<Self> {
+        std::env::var("TTS_COMMAND").ok().map(Self::new)
+    }
+}
+
+#[async_trait]
+impl SpeechSynthesizer for CommandTTS {
+    async fn synthesize(&self, text: &str, language: &str) -> Option<Bytes> {
+        if !language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return None;
+        }
+        let command_line = self.command.replace("{language}", language);
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&command_line)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut stdin = child.stdin.take()?;
+        stdin.write_all(text.as_bytes()).await.ok()?;
+        drop(stdin);
+        let output = child.wait_with_output().await.ok()?;
+        if !output.status.success() || output.stdout.is_empty() {
+            println!("`{command_line}` failed with {}", output.status);
+            return None;
+        }
+        Some(Bytes::from(output.stdout))
+    }
+}
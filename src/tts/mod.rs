@@ -0,0 +1,83 @@
+mod azure;
+mod cache;
+mod command;
+
+use std::{
+    env,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use crate::store::StateStore;
+
+pub use azure::{stt_url, AzureTTS};
+pub use cache::audio_digest;
+pub use command::CommandTTS;
+
+#[async_trait]
+pub trait SpeechSynthesizer: Send + Sync {
+    /// Speaks `text` in `language` (ISO 639-1 code) as OGG/Opus audio,
+    /// or returns `None` when this engine can't do it right now.
+    async fn synthesize(&self, text: &str, language: &str) -> Option<Bytes>;
+}
+
+/// Asks each engine in turn until one of them produces audio.
+pub struct Fallback<'a>(pub Vec<&'a dyn SpeechSynthesizer>);
+
+#[async_trait]
+impl SpeechSynthesizer for Fallback<'_> {
+    async fn synthesize(&self, text: &str, language: &str) -> Option<Bytes> {
+        for engine in &self.0 {
+            if let Some(voice) = engine.synthesize(text, language).await {
+                return Some(voice);
+            }
+        }
+        None
+    }
+}
+
+/// Whether Azure should be tried at all, `TTS_ENGINE=command` skips it.
+pub fn azure_enabled() -> bool {
+    std::env::var("TTS_ENGINE").as_deref() != Ok("command")
+}
+
+const AZURE_RETRY_SECS: u64 = 60;
+
+static AZURE: OnceLock<Arc<AzureTTS>> = OnceLock::new();
+
+/// The Azure engine shared by every user, once [`load_azure`] listed its
+/// voices.
+pub fn azure() -> Option<Arc<AzureTTS>> {
+    AZURE.get().cloned()
+}
+
+/// Lists the Azure voices for [`azure`] when speech or recognition uses Azure
+/// and `AZURE_TTS_SUBSCRIPTION_KEY` is set. Returns whether nothing is left to
+/// load, failing while Azure can't be reached. Its audio is cached in `store`
+/// unless configured otherwise.
+pub async fn load_azure(store: &Arc<dyn StateStore>) -> bool {
+    let wanted = azure_enabled() || crate::stt::azure_enabled();
+    if AZURE.get().is_some() || !wanted || env::var("AZURE_TTS_SUBSCRIPTION_KEY").is_err() {
+        return true;
+    }
+    match AzureTTS::from_env(store.clone()).await {
+        Ok(azure) => {
+            let _ = AZURE.set(Arc::new(azure));
+            true
+        }
+        Err(error) => {
+            println!("Azure is unavailable, using the other speech engines: {error}");
+            false
+        }
+    }
+}
+
+/// Retries [`load_azure`] every [`AZURE_RETRY_SECS`] alongside a server.
+pub async fn load_azure_forever(store: Arc<dyn StateStore>) {
+    while !load_azure(&store).await {
+        tokio::time::sleep(Duration::from_secs(AZURE_RETRY_SECS)).await;
+    }
+}
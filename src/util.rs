@@ -13,6 +13,13 @@ pub fn new_reqwest_client() -> reqwest::Client {
     builder.build().unwrap()
 }
 
+/// The English name of an ISO 639-1 code, or the code itself if unknown.
+pub fn language_name(language: &str) -> String {
+    isolang::Language::from_639_1(language)
+        .map(|it| it.to_name().to_string())
+        .unwrap_or_else(|| language.to_string())
+}
+
 pub fn decrypt(data: &[u8], secret: &[u8]) -> String {
     let key = &secret[0..32];
     let iv = &secret[32..(32 + 16)];
@@ -0,0 +1,80 @@
+use bytes::Bytes;
+
+use crate::{
+    bing_dictionary::Word,
+    error::Result,
+    store::{schema, StateStore},
+};
+
+/// A looked-up word together with the audio of its spelling and example sentence.
+pub struct CachedWord {
+    pub word: Word,
+    pub spell_voice: Option<Bytes>,
+    pub sentence_voice: Option<Bytes>,
+}
+
+/// Where a word is cached, the same word looked up for a different course or
+/// interface language is a different entry.
+pub struct WordKey(String);
+
+impl WordKey {
+    pub fn new(language: &str, ui_language: &str, spell: &str) -> Self {
+        Self(schema::word_key(language, ui_language, spell))
+    }
+
+    fn voice_keys(&self) -> [String; 2] {
+        schema::word_voice_keys(&self.0)
+    }
+}
+
+pub async fn load_word(store: &dyn StateStore, key: &WordKey) -> Result<Option<CachedWord>> {
+    let word = store.get_string(&key.0).await?;
+    let Some(word) = word.and_then(|it| serde_json::from_str(&it).ok()) else {
+        return Ok(None);
+    };
+    let voices = store.get_many(&key.voice_keys()).await?;
+    let mut voices = voices.into_iter().map(|it| it.map(Bytes::from));
+    Ok(Some(CachedWord {
+        word,
+        spell_voice: voices.next().flatten(),
+        sentence_voice: voices.next().flatten(),
+    }))
+}
+
+/// The audio of a cached word's spelling alone.
+pub async fn load_spell_voice(store: &dyn StateStore, key: &WordKey) -> Result<Option<Bytes>> {
+    let [spell_voice, _] = key.voice_keys();
+    Ok(store.get(&spell_voice).await?.map(Bytes::from))
+}
+
+/// The cached words for `keys`, without their audio.
+pub async fn load_words(store: &dyn StateStore, keys: &[WordKey]) -> Result<Vec<Option<Word>>> {
+    let keys = keys.iter().map(|it| it.0.clone()).collect::<Vec<_>>();
+    let words = store.get_many(&keys).await?;
+    Ok(words
+        .into_iter()
+        .map(|it| it.and_then(|it| serde_json::from_slice(&it).ok()))
+        .collect())
+}
+
+pub async fn save_word(store: &dyn StateStore, key: &WordKey, cached: &CachedWord) -> Result<()> {
+    let ttl_secs = Some(schema::WORD_TTL_SECS);
+    store
+        .set_string(&key.0, &serde_json::to_string(&cached.word)?, ttl_secs)
+        .await?;
+    let voices = [&cached.spell_voice, &cached.sentence_voice];
+    for (voice_key, voice) in key.voice_keys().into_iter().zip(voices) {
+        match voice {
+            Some(voice) => store.set(&voice_key, voice, ttl_secs).await?,
+            None => store.delete(&[voice_key]).await?,
+        }
+    }
+    Ok(())
+}
+
+pub async fn forget_word(store: &dyn StateStore, key: &WordKey) -> Result<()> {
+    let [spell_voice, sentence_voice] = key.voice_keys();
+    store
+        .delete(&[key.0.clone(), spell_voice, sentence_voice])
+        .await
+}